@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate ruxpin_app;
+
+use alloc::vec::Vec;
+
+use ruxpin_api::{println, clock_gettime, clock_settime, exit};
+use ruxpin_types::{ClockId, Timespec};
+
+use ruxpin_app::env;
+
+
+#[no_mangle]
+pub fn main() {
+    let args: Vec<&str> = env::args().skip(1).collect();
+
+    if args.len() == 2 && args[0] == "-s" {
+        let seconds = match args[1].parse::<u64>() {
+            Ok(seconds) => seconds,
+            Err(_) => {
+                println!("Error: invalid time {}", args[1]);
+                exit(-1);
+            },
+        };
+
+        if let Err(err) = clock_settime(ClockId::Realtime, &Timespec::new(seconds, 0)) {
+            println!("Error: {:?}", err);
+            exit(-1);
+        }
+    } else if args.len() != 0 {
+        println!("Usage: date [-s <seconds since the epoch>]");
+        exit(0);
+    }
+
+    let mut now = Timespec::default();
+    if let Err(err) = clock_gettime(ClockId::Realtime, &mut now) {
+        println!("Error: {:?}", err);
+        exit(-1);
+    }
+
+    let (year, month, day) = civil_from_days(now.seconds / 86400);
+    let seconds_of_day = now.seconds % 86400;
+    println!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, seconds_of_day / 3600, seconds_of_day / 60 % 60, seconds_of_day % 60);
+}
+
+// Convert a number of days since 1970-01-01 into a year, month, and day in the Gregorian calendar
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Count from 0000-03-01 so that the leap day is at the end of each year
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
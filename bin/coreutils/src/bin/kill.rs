@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+extern crate ruxpin_app;
+
+use ruxpin_api::{println, kill, exit};
+use ruxpin_types::{Pid, Signal};
+
+use ruxpin_app::env;
+
+
+#[no_mangle]
+pub fn main() {
+    let mut signal = Signal::Terminate;
+    let mut pid = None;
+    for arg in env::args().skip(1) {
+        if arg.starts_with('-') {
+            match arg[1..].parse::<u8>() {
+                Ok(num) => signal = Signal(num),
+                Err(_) => {
+                    println!("kill: invalid signal {}", arg);
+                    exit(1);
+                },
+            }
+        } else {
+            pid = arg.parse::<Pid>().ok();
+        }
+    }
+
+    let pid = match pid {
+        Some(pid) => pid,
+        None => {
+            println!("Usage: kill [-<signal>] <pid>");
+            exit(1);
+        },
+    };
+
+    if let Err(err) = kill(pid, signal) {
+        println!("Error: {:?}", err);
+    }
+}
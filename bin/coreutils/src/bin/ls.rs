@@ -1,24 +1,38 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
 extern crate ruxpin_app;
 
-use ruxpin_api::{println, open, close, readdir};
-use ruxpin_types::{OpenFlags, FileAccess, DirEntry};
+use alloc::string::String;
+
+use ruxpin_api::{println, open, close, readdir, stat};
+use ruxpin_types::{OpenFlags, FileAccess, DirEntry, Stat};
 
 use ruxpin_app::env;
 
 
 #[no_mangle]
 pub fn main() {
-    let mut args = env::args();
-    let dirname = args.nth(1).unwrap_or(".");
+    let mut long_format = false;
+    let mut dirname = ".";
+    for arg in env::args().skip(1) {
+        if arg == "-l" {
+            long_format = true;
+        } else {
+            dirname = arg;
+        }
+    }
 
     let file = open(dirname, OpenFlags::ReadOnly, FileAccess::DefaultDir).unwrap();
     loop {
         let mut dirent = DirEntry::new_empty();
         if readdir(file.clone(), &mut dirent).unwrap() {
-            println!("{}", dirent.as_str());
+            if long_format {
+                print_long(dirname, dirent.as_str());
+            } else {
+                println!("{}", dirent.as_str());
+            }
         } else {
             break;
         }
@@ -26,3 +40,39 @@ pub fn main() {
     close(file).unwrap();
 }
 
+fn print_long(dirname: &str, filename: &str) {
+    let mut path = String::from(dirname);
+    path.push('/');
+    path.push_str(filename);
+
+    let mut info = Stat::new_empty();
+    match stat(path.as_str(), &mut info) {
+        Ok(()) => {
+            let mut mode = [b'-'; 10];
+            format_mode(&mut mode, info.access);
+            println!("{} {:>3} {:>5} {:>5} {:>10} {}", core::str::from_utf8(&mode).unwrap(), info.nlinks, info.uid, info.gid, info.size, filename);
+        },
+        Err(err) => {
+            println!("?????????? {} ({:?})", filename, err);
+        },
+    }
+}
+
+fn format_mode(mode: &mut [u8; 10], access: FileAccess) {
+    mode[0] = match access.file_type() {
+        FileAccess::Directory => b'd',
+        FileAccess::CharDevice => b'c',
+        FileAccess::BlockDevice => b'b',
+        FileAccess::SymbolicLink => b'l',
+        FileAccess::Fifo => b'p',
+        FileAccess::Socket => b's',
+        _ => b'-',
+    };
+
+    let chars = b"rwxrwxrwx";
+    for i in 0..9 {
+        if access.0 & (0o400 >> i) != 0 {
+            mode[i + 1] = chars[i];
+        }
+    }
+}
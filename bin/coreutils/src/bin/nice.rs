@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate ruxpin_app;
+
+use alloc::vec::Vec;
+
+use ruxpin_api::{println, nice, exec, exit};
+
+use ruxpin_app::env;
+
+
+const DEFAULT_INCREMENT: isize = 10;
+
+#[no_mangle]
+pub fn main() {
+    let mut args: Vec<&str> = env::args().skip(1).collect();
+
+    let mut increment = DEFAULT_INCREMENT;
+    if args.len() >= 2 && args[0] == "-n" {
+        increment = match args[1].parse::<isize>() {
+            Ok(increment) => increment,
+            Err(_) => {
+                println!("Error: invalid increment {}", args[1]);
+                exit(-1);
+            },
+        };
+        args.drain(..2);
+    }
+
+    if args.len() == 0 {
+        println!("Usage: nice [-n increment] <command> [args...]");
+        exit(0);
+    }
+
+    // The nice value is kept across exec, so the command runs with the new priority
+    if let Err(err) = nice(increment) {
+        println!("Error: {:?}", err);
+        exit(-1);
+    }
+
+    exec(args[0], &args[..], &[]);
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate ruxpin_app;
+
+use alloc::vec::Vec;
+
+use ruxpin_api::{println, personality, exec, exit};
+use ruxpin_types::Personality;
+
+use ruxpin_app::env;
+
+
+#[no_mangle]
+pub fn main() {
+    let args: Vec<&str> = env::args().skip(1).collect();
+    if args.len() == 0 {
+        println!("Usage: norandom <command> [args...]");
+        exit(0);
+    }
+
+    // The flag is kept across exec, so the command will be loaded without its layout randomized
+    if let Err(err) = personality(Personality::NoRandomize) {
+        println!("Error: {:?}", err);
+        exit(-1);
+    }
+
+    exec(args[0], &args[..], &[]);
+}
@@ -0,0 +1,22 @@
+#![no_std]
+#![no_main]
+
+extern crate ruxpin_app;
+
+use core::str;
+
+use ruxpin_api::{println, getcwd};
+
+
+#[no_mangle]
+pub fn main() {
+    let mut path = [0; 256];
+    match getcwd(&mut path) {
+        Ok(len) => {
+            println!("{}", str::from_utf8(&path[..len]).unwrap());
+        },
+        Err(err) => {
+            println!("Error: {:?}", err);
+        },
+    }
+}
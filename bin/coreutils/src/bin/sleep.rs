@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate ruxpin_app;
+
+use alloc::vec::Vec;
+
+use ruxpin_api::{println, nanosleep, exit};
+use ruxpin_types::{Timespec, NANOSECONDS_PER_SECOND};
+
+use ruxpin_app::env;
+
+
+#[no_mangle]
+pub fn main() {
+    let args: Vec<&str> = env::args().skip(1).collect();
+
+    if args.len() != 1 {
+        println!("Usage: sleep <seconds>");
+        exit(0);
+    }
+
+    let duration = match parse_seconds(args[0]) {
+        Some(duration) => duration,
+        None => {
+            println!("Error: invalid time {}", args[0]);
+            exit(-1);
+        },
+    };
+
+    if let Err(err) = nanosleep(&duration) {
+        println!("Error: {:?}", err);
+        exit(-1);
+    }
+}
+
+// Parse a number of seconds, which can have a fractional part
+fn parse_seconds(arg: &str) -> Option<Timespec> {
+    let (whole, fraction) = arg.split_once('.').unwrap_or((arg, ""));
+    let seconds = if whole.len() > 0 { whole.parse::<u64>().ok()? } else { 0 };
+
+    let mut nanoseconds = 0;
+    let mut scale = NANOSECONDS_PER_SECOND;
+    for digit in fraction.chars() {
+        scale /= 10;
+        nanoseconds += digit.to_digit(10)? as u64 * scale;
+    }
+
+    Some(Timespec::new(seconds, nanoseconds))
+}
@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+extern crate ruxpin_app;
+
+use ruxpin_app::env;
+use ruxpin_api::{println, swapoff, exit};
+
+
+#[no_mangle]
+pub fn main() {
+    let mut args = env::args();
+    let path = match args.nth(1) {
+        Some(path) => path,
+        None => {
+            println!("Usage: swapoff <device or file>");
+            exit(0);
+        },
+    };
+
+    if let Err(err) = swapoff(path) {
+        println!("Error: {:?}", err);
+    }
+}
+
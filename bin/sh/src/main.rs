@@ -9,8 +9,8 @@ use core::str;
 use alloc::vec;
 use alloc::vec::Vec;
 
-use ruxpin_api::{STDIN_FILENO, STDOUT_FILENO, print, println, open, dup2, fork, exec, read, waitpid};
-use ruxpin_types::{OpenFlags, FileAccess};
+use ruxpin_api::{STDIN_FILENO, STDOUT_FILENO, print, println, open, close, dup2, pipe, fork, exec, read, waitpid, chdir};
+use ruxpin_types::{OpenFlags, FileAccess, FileDesc, WaitOptions, WaitStatus};
 
 
 struct Command<'a> {
@@ -39,7 +39,9 @@ fn parse_words<'a>(input: &'a [u8]) -> Vec<&'a str> {
         }
 
         let (word, remain) = get_next_word(next);
-        words.push(str::from_utf8(word).unwrap());
+        if word != b"" {
+            words.push(str::from_utf8(word).unwrap());
+        }
 
         next = remain;
     }
@@ -48,40 +50,42 @@ fn parse_words<'a>(input: &'a [u8]) -> Vec<&'a str> {
 fn parse_command<'a>(input: &'a [u8]) -> Vec<Command> {
     let mut commands = vec![];
 
+    for segment in input.split(|ch| *ch == b'|') {
+        commands.push(parse_redirections(segment));
+    }
+
+    commands
+}
+
+fn parse_redirections<'a>(input: &'a [u8]) -> Command {
     for (i, ch) in str::from_utf8(input).unwrap().chars().enumerate() {
         match ch {
             '<' => {
-                commands.push(Command {
+                return Command {
                     words: parse_words(&input[..i]),
-                    input: Some(str::from_utf8(&input[i..]).unwrap().trim()),
+                    input: Some(str::from_utf8(&input[i + 1..]).unwrap().trim()),
                     output: None,
                     append: false,
-                });
-
-                return commands;
+                };
             },
             '>' => {
-                commands.push(Command {
+                return Command {
                     words: parse_words(&input[..i]),
                     input: None,
                     output: Some(str::from_utf8(&input[i + 1..]).unwrap().trim()),
                     append: false,
-                });
-
-                return commands;
+                };
             },
             _ => { },
         }
     }
 
-    commands.push(Command {
+    Command {
         words: parse_words(input),
         input: None,
         output: None,
         append: false,
-    });
-
-    commands
+    }
 }
 
 fn substitute_path<'a>(fullpath: &'a mut [u8], path: &str, command: &str) -> &'a str {
@@ -112,13 +116,48 @@ pub fn main() {
             break;
         }
 
-        for mut command in commands {
+        if commands[0].words[0] == "cd" {
+            let dirname = commands[0].words.get(1).map(|s| *s).unwrap_or("/");
+            if let Err(err) = chdir(dirname) {
+                println!("cd: {:?}", err);
+            }
+            continue;
+        }
+
+        let mut pids = vec![];
+        let mut pipe_input: Option<FileDesc> = None;
+        let count = commands.len();
+        for (i, mut command) in commands.into_iter().enumerate() {
+            if command.words.len() == 0 {
+                println!("Error: missing command in pipeline");
+                break;
+            }
+
             let mut fullpath = [0; 256];
             command.words[0] = substitute_path(&mut fullpath, "/bin/", command.words[0]);
 
+            // Every command except the last sends its output to the next command's input
+            let pipe_output = if i + 1 < count {
+                let mut fds = [FileDesc(0); 2];
+                pipe(&mut fds).unwrap();
+                Some(fds)
+            } else {
+                None
+            };
+
             let pid = fork().unwrap();
             if pid == 0 {
-                // TODO open the in/out files and use dup2() syscall
+                if let Some(fd) = pipe_input {
+                    dup2(fd, STDIN_FILENO).unwrap();
+                    close(fd).unwrap();
+                }
+
+                if let Some(fds) = pipe_output {
+                    dup2(fds[1], STDOUT_FILENO).unwrap();
+                    close(fds[0]).unwrap();
+                    close(fds[1]).unwrap();
+                }
+
                 if let Some(name) = command.input {
                     let fd = open(name, OpenFlags::ReadOnly, FileAccess::DefaultFile).unwrap();
                     dup2(fd, STDIN_FILENO).unwrap();
@@ -137,12 +176,35 @@ pub fn main() {
 
                 exec(command.words[0], &command.words[..], &[]);
             } else {
-                let mut status = 0;
-                let result = waitpid(pid, &mut status, 0);
-                match result {
-                    Ok(pid) => { println!("pid {} exited with {}", pid, status); },
-                    Err(err) => { println!("Error while waiting for process: {:?}", err); },
+                if let Some(fd) = pipe_input.take() {
+                    close(fd).unwrap();
+                }
+
+                if let Some(fds) = pipe_output {
+                    close(fds[1]).unwrap();
+                    pipe_input = Some(fds[0]);
                 }
+
+                pids.push(pid);
+            }
+        }
+
+        if let Some(fd) = pipe_input {
+            close(fd).unwrap();
+        }
+
+        for pid in pids {
+            let mut status = WaitStatus(0);
+            let result = waitpid(pid, &mut status, WaitOptions::Empty);
+            match result {
+                Ok(pid) => {
+                    if status.signaled() {
+                        println!("pid {} terminated by signal {}", pid, status.term_signal().0);
+                    } else {
+                        println!("pid {} exited with {}", pid, status.exit_code());
+                    }
+                },
+                Err(err) => { println!("Error while waiting for process: {:?}", err); },
             }
         }
     }
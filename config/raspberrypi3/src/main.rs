@@ -11,17 +11,21 @@ use ruxpin_kernel::printk::printk_dump_slice;
 use ruxpin_kernel::arch::PhysicalAddress;
 
 use ruxpin_kernel::irqs;
+use ruxpin_kernel::smp;
 use ruxpin_kernel::fs;
 use ruxpin_kernel::tasklets;
 use ruxpin_kernel::mm::kmalloc;
 use ruxpin_kernel::mm::vmalloc;
 use ruxpin_kernel::api::binaries;
 use ruxpin_kernel::proc::scheduler;
+use ruxpin_kernel::proc::policy::FairPolicy;
 
 use ruxpin_types::{OpenFlags, FileAccess, Seek, DeviceID};
 
-use ruxpin_drivers_arm::SystemTimer;
+use ruxpin_drivers_arm::GenericTimer;
 use ruxpin_drivers_arm::GenericInterruptController;
+use ruxpin_drivers_arm::LocalInterruptController;
+use ruxpin_drivers_arm::local::PHYSICAL_TIMER_IRQ;
 use ruxpin_drivers_raspberrypi::console;
 use ruxpin_drivers_raspberrypi::emmc::EmmcDevice;
 
@@ -37,13 +41,17 @@ pub fn register_devices() -> Result<(), KernelError> {
 
     notice!("starting kernel...");
 
-    kmalloc::initialize(PhysicalAddress::from(0x20_0000), PhysicalAddress::from(0x100_0000));
-    vmalloc::initialize(PhysicalAddress::from(0x100_0000), PhysicalAddress::from(0x1000_0000))?;
-    irqs::register_interrupt_controller(Box::new(GenericInterruptController::new()));
+    // The initial heap is only used until the page pool is ready, after which the heap grows using pages
+    kmalloc::initialize(PhysicalAddress::from(0x20_0000), PhysicalAddress::from(0x40_0000));
+    vmalloc::initialize(PhysicalAddress::from(0x40_0000), PhysicalAddress::from(0x1000_0000))?;
+    irqs::register_interrupt_controller(Box::new(LocalInterruptController::new(GenericInterruptController::new())));
+    smp::initialize()?;
 
     tasklets::initialize()?;
     fs::initialize()?;
-    scheduler::initialize()?;
+    // Tasks share the cpu according to their nice values, and are switched after each time slice
+    scheduler::initialize(|| Box::new(FairPolicy::new()))?;
+    scheduler::set_time_slice(scheduler::DEFAULT_TIME_SLICE);
 
     // Register File Systems
     fs::register_filesystem(DevFilesystem::new())?;
@@ -66,6 +74,7 @@ pub fn register_devices() -> Result<(), KernelError> {
     fs::mount(None, "/dev", "devfs", None, 0).unwrap();
     fs::mount(None, "/proc", "procfs", None, 0).unwrap();
     fs::mount(None, "/tmp", "tmpfs", None, 0).unwrap();
+    fs::writeback::start()?;
 
     startup_tests().unwrap();
 
@@ -73,7 +82,10 @@ pub fn register_devices() -> Result<(), KernelError> {
     notice!("loading the first processs (/bin/sh) from elf binary file");
     binaries::load_process("/bin/sh").unwrap();
 
-    SystemTimer::init(1);
+    GenericTimer::init(PHYSICAL_TIMER_IRQ);
+
+    // The secondary cores start running tasks as soon as they're released, so everything must be set up first
+    smp::start_secondary_cores();
 
     notice!("kernel initialization complete");
 
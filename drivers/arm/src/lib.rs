@@ -1,8 +1,10 @@
 #![no_std]
 
 pub mod gic;
+pub mod local;
 pub mod timer;
 
-pub use timer::SystemTimer;
+pub use timer::{SystemTimer, GenericTimer};
 pub use gic::GenericInterruptController;
+pub use local::LocalInterruptController;
 
@@ -0,0 +1,173 @@
+
+use ruxpin_kernel::notice;
+use ruxpin_kernel::smp::{self, MAX_CPUS};
+use ruxpin_kernel::arch::KernelVirtualAddress;
+use ruxpin_kernel::misc::deviceio::DeviceRegisters;
+use ruxpin_kernel::irqs::{InterruptController, Ipi, LOCAL_IRQ_BASE, IPI_IRQ_BASE};
+
+use crate::gic::{GenericInterruptController, PendingInterruptIterator};
+
+
+mod registers {
+    pub const CORE_TIMER_IRQCNTL: usize = 0x40;
+    pub const MAILBOX_IRQCNTL: usize = 0x50;
+    pub const CORE_IRQ_SOURCE: usize = 0x60;
+    pub const MAILBOX0_SET: usize = 0x80;
+    pub const MAILBOX0_RDCLR: usize = 0xC0;
+
+    pub const fn per_core(reg: usize, cpu: usize) -> usize {
+        reg + cpu * 4
+    }
+
+    pub const fn per_core_mailbox(reg: usize, cpu: usize) -> usize {
+        reg + cpu * 16
+    }
+}
+
+// The bits of the irq source register for interrupts that aren't numbered as local interrupts
+const SOURCE_MAILBOX_BITS: u32 = 0xF0;
+const SOURCE_GPU_BIT: u32 = 1 << 8;
+
+/// The irq number of the non-secure physical timer of each core
+pub const PHYSICAL_TIMER_IRQ: usize = LOCAL_IRQ_BASE + 1;
+
+
+/// The interrupt controller for the interrupts that belong to each core, such as the core timers and mailboxes
+///
+/// The shared interrupts are passed on to the generic interrupt controller, and are only delivered to the first core.
+/// Enabling or disabling a local interrupt only affects the core that does it.  The inter-processor interrupts are
+/// sent using the bits of the first mailbox of each core.
+pub struct LocalInterruptController {
+    registers: DeviceRegisters<u32>,
+    gic: GenericInterruptController,
+    enabled_ipis: [u32; MAX_CPUS],
+    iter: Option<LocalPendingIterator>,
+}
+
+impl LocalInterruptController {
+    pub fn new(gic: GenericInterruptController) -> Self {
+        notice!("interrupts: initializing local arm interrupt controller");
+
+        let controller = Self {
+            registers: DeviceRegisters::new(KernelVirtualAddress::new(0x4000_0000)),
+            gic,
+            enabled_ipis: [0; MAX_CPUS],
+            iter: None,
+        };
+
+        unsafe {
+            for cpu in 0..MAX_CPUS {
+                controller.registers.set(registers::per_core(registers::CORE_TIMER_IRQCNTL, cpu), 0);
+                controller.registers.set(registers::per_core(registers::MAILBOX_IRQCNTL, cpu), 0);
+                controller.registers.set(registers::per_core_mailbox(registers::MAILBOX0_RDCLR, cpu), !0);
+            }
+        }
+
+        controller
+    }
+
+    pub fn iter(&mut self) -> LocalPendingIterator {
+        let cpu = smp::cpu_id();
+        let source = unsafe { self.registers.get(registers::per_core(registers::CORE_IRQ_SOURCE, cpu)) };
+
+        let mailbox = unsafe {
+            let reg = registers::per_core_mailbox(registers::MAILBOX0_RDCLR, cpu);
+            let pending = self.registers.get(reg) & self.enabled_ipis[cpu];
+            self.registers.set(reg, pending);
+            pending
+        };
+
+        LocalPendingIterator {
+            gpu: if source & SOURCE_GPU_BIT != 0 { Some(self.gic.iter()) } else { None },
+            local: source & !(SOURCE_MAILBOX_BITS | SOURCE_GPU_BIT),
+            mailbox,
+        }
+    }
+
+    fn set_timer_irq(&mut self, bit: usize, enable: bool) {
+        let reg = registers::per_core(registers::CORE_TIMER_IRQCNTL, smp::cpu_id());
+        unsafe {
+            let value = self.registers.get(reg);
+            self.registers.set(reg, if enable { value | (1 << bit) } else { value & !(1 << bit) });
+        }
+    }
+
+    fn set_ipi_irq(&mut self, bit: usize, enable: bool) {
+        let cpu = smp::cpu_id();
+        if enable {
+            self.enabled_ipis[cpu] |= 1 << bit;
+        } else {
+            self.enabled_ipis[cpu] &= !(1 << bit);
+        }
+
+        // All of the inter-processor interrupts use the first mailbox, so its irq is enabled while any of them are
+        unsafe {
+            self.registers.set(registers::per_core(registers::MAILBOX_IRQCNTL, cpu), if self.enabled_ipis[cpu] != 0 { 1 } else { 0 });
+        }
+    }
+}
+
+impl InterruptController for LocalInterruptController {
+    fn enable_irq(&mut self, irq: usize) {
+        if irq < LOCAL_IRQ_BASE {
+            self.gic.enable_irq(irq);
+        } else if irq < IPI_IRQ_BASE {
+            self.set_timer_irq(irq - LOCAL_IRQ_BASE, true);
+        } else {
+            self.set_ipi_irq(irq - IPI_IRQ_BASE, true);
+        }
+    }
+
+    fn disable_irq(&mut self, irq: usize) {
+        if irq < LOCAL_IRQ_BASE {
+            self.gic.disable_irq(irq);
+        } else if irq < IPI_IRQ_BASE {
+            self.set_timer_irq(irq - LOCAL_IRQ_BASE, false);
+        } else {
+            self.set_ipi_irq(irq - IPI_IRQ_BASE, false);
+        }
+    }
+
+    fn pending_irqs(&mut self) -> &mut dyn Iterator<Item=usize> {
+        self.iter = Some(self.iter());
+        self.iter.as_mut().unwrap()
+    }
+
+    fn send_ipi(&mut self, cpu: usize, ipi: Ipi) {
+        unsafe {
+            self.registers.set(registers::per_core_mailbox(registers::MAILBOX0_SET, cpu), 1 << (ipi as usize));
+        }
+    }
+}
+
+
+pub struct LocalPendingIterator {
+    gpu: Option<PendingInterruptIterator>,
+    local: u32,
+    mailbox: u32,
+}
+
+impl Iterator for LocalPendingIterator {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(irq) = self.gpu.as_mut().and_then(|gpu| gpu.next()) {
+            return Some(irq);
+        }
+        self.gpu = None;
+
+        if self.local != 0 {
+            let bit = self.local.trailing_zeros() as usize;
+            self.local &= !(1 << bit);
+            return Some(LOCAL_IRQ_BASE + bit);
+        }
+
+        if self.mailbox != 0 {
+            let bit = self.mailbox.trailing_zeros() as usize;
+            self.mailbox &= !(1 << bit);
+            return Some(IPI_IRQ_BASE + bit);
+        }
+
+        None
+    }
+}
@@ -1,8 +1,13 @@
 
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use ruxpin_kernel::irqs;
+use ruxpin_kernel::smp;
+use ruxpin_kernel::time;
 use ruxpin_kernel::notice;
 use ruxpin_kernel::proc::scheduler;
-use ruxpin_kernel::arch::KernelVirtualAddress;
+use ruxpin_kernel::arch::{self, KernelVirtualAddress};
 use ruxpin_kernel::misc::deviceio::DeviceRegisters;
 
 mod registers {
@@ -23,7 +28,7 @@ impl SystemTimer {
         irqs::enable_irq(irq);
 
         unsafe {
-            let value = SYS_TIMER.get(registers::COUNT_LOW) + 20000;
+            let value = SYS_TIMER.get(registers::COUNT_LOW).wrapping_add(scheduler::get_time_slice());
             SYS_TIMER.set(registers::COMPARE_1, value);
         }
     }
@@ -31,14 +36,59 @@ impl SystemTimer {
     pub fn reset() {
         unsafe {
             SYS_TIMER.set(registers::CONTROL, 1 << 1);
-            let value = SYS_TIMER.get(registers::COUNT_LOW) + 20000;
+            // The counter runs at 1MHz, so the time slice is the number of counts until the next interrupt
+            let value = SYS_TIMER.get(registers::COUNT_LOW).wrapping_add(scheduler::get_time_slice());
             SYS_TIMER.set(registers::COMPARE_1, value);
         }
     }
 
     fn handle_irq() {
         SystemTimer::reset();
+        time::tick();
         scheduler::schedule();
     }
 }
 
+
+
+// The irq of the timer is the same on each core, but each core enables it for itself
+static GENERIC_TIMER_IRQ: AtomicUsize = AtomicUsize::new(0);
+
+/// The timer built into each core, which interrupts each core separately to switch between the tasks running on it
+pub struct GenericTimer;
+
+impl GenericTimer {
+    pub fn init(irq: usize) {
+        notice!("timer: initializing per-core arm timer to trigger context switch");
+
+        GENERIC_TIMER_IRQ.store(irq, Ordering::Relaxed);
+        irqs::register_irq(irq, GenericTimer::handle_irq).unwrap();
+
+        // The secondary cores start their own timers when they're started
+        smp::register_core_init(GenericTimer::init_core);
+        GenericTimer::init_core();
+    }
+
+    fn init_core() {
+        irqs::enable_irq(GENERIC_TIMER_IRQ.load(Ordering::Relaxed));
+        GenericTimer::reset();
+    }
+
+    pub fn reset() {
+        unsafe {
+            let ticks = arch::read_counter_frequency() * scheduler::get_time_slice() as u64 / 1_000_000;
+            asm!(
+                "msr    CNTP_TVAL_EL0, {ticks}",
+                "msr    CNTP_CTL_EL0, {enable}",
+                ticks = in(reg) ticks,
+                enable = in(reg) 1u64,
+            );
+        }
+    }
+
+    fn handle_irq() {
+        GenericTimer::reset();
+        time::tick();
+        scheduler::schedule();
+    }
+}
@@ -82,6 +82,10 @@ impl BlockOperations for EmmcDevice {
         }
         EmmcDevice::write_data(buffer, self.base + offset)
     }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
 }
 
 const MMC_RESP_COMMAND_COMPLETE: u32     = 1 << 31;
@@ -7,6 +7,7 @@ use alloc::sync::Arc;
 use ruxpin_types::{OpenFlags, FileAccess, Seek, DeviceID};
 
 use ruxpin_kernel::tty;
+use ruxpin_kernel::block;
 use ruxpin_kernel::sync::Spinlock;
 use ruxpin_kernel::errors::KernelError;
 use ruxpin_kernel::fs::{new_vnode, Filesystem, Mount, MountOperations, Vnode, WeakVnode, VnodeOperations, FileAttributes, FilePointer};
@@ -31,6 +32,11 @@ pub struct DevCharDeviceVnode {
     device_id: DeviceID,
 }
 
+pub struct DevBlockDeviceVnode {
+    attrs: FileAttributes,
+    device_id: DeviceID,
+}
+
 
 impl Filesystem for DevFilesystem {
     fn fstype(&self) -> &'static str {
@@ -87,13 +93,29 @@ impl DevRootDirectoryVnode {
 
 impl DevCharDeviceVnode {
     pub fn new(device_id: DeviceID) -> Self {
+        let mut attrs = FileAttributes::new(FileAccess::CharDevice.plus(FileAccess::DefaultFile), 0, 0);
+        attrs.rdev = Some(device_id);
+
         Self {
-            attrs: Default::default(),
+            attrs,
             device_id,
         }
     }
 }
 
+impl DevBlockDeviceVnode {
+    pub fn new(device_id: DeviceID) -> Result<Self, KernelError> {
+        let mut attrs = FileAttributes::new(FileAccess::BlockDevice.plus(FileAccess::DefaultFile), 0, 0);
+        attrs.rdev = Some(device_id);
+        attrs.size = block::get_size(device_id)? as usize;
+
+        Ok(Self {
+            attrs,
+            device_id,
+        })
+    }
+}
+
 impl VnodeOperations for DevRootDirectoryVnode {
     fn set_self(&mut self, vnode: WeakVnode) {
         self.self_vnode = Some(vnode);
@@ -112,9 +134,12 @@ impl VnodeOperations for DevRootDirectoryVnode {
             return vnode.upgrade().ok_or(KernelError::FileNotFound);
         }
 
-        // TODO will need to support block devices as well
-        let device_id = tty::lookup_device(filename)?;
-        Ok(new_vnode(DevCharDeviceVnode::new(device_id)))
+        if let Ok(device_id) = tty::lookup_device(filename) {
+            return Ok(new_vnode(DevCharDeviceVnode::new(device_id)));
+        }
+
+        let device_id = block::lookup_device(filename)?;
+        Ok(new_vnode(DevBlockDeviceVnode::new(device_id)?))
     }
 
     fn attributes<'a>(&'a mut self) -> Result<&'a FileAttributes, KernelError> {
@@ -159,13 +184,18 @@ impl VnodeOperations for DevCharDeviceVnode {
         Ok(nbytes)
     }
 
-    fn seek(&mut self, file: &mut FilePointer, offset: usize, whence: Seek) -> Result<usize, KernelError> {
+    fn seek(&mut self, file: &mut FilePointer, offset: isize, whence: Seek) -> Result<usize, KernelError> {
         let position = match whence {
             Seek::FromStart => offset,
-            Seek::FromCurrent => file.position + offset,
-            Seek::FromEnd => self.attrs.size + offset,
+            Seek::FromCurrent => file.position as isize + offset,
+            Seek::FromEnd => self.attrs.size as isize + offset,
         };
 
+        if position < 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        let position = position as usize;
         if position >= self.attrs.size {
             file.position = self.attrs.size;
         } else {
@@ -175,4 +205,44 @@ impl VnodeOperations for DevCharDeviceVnode {
     }
 }
 
+impl VnodeOperations for DevBlockDeviceVnode {
+    fn attributes<'a>(&'a mut self) -> Result<&'a FileAttributes, KernelError> {
+        Ok(&mut self.attrs)
+    }
+
+    // The device is opened by the filesystem or subsystem that uses it, so opening the vnode doesn't open the device
+    fn open(&mut self, _file: &mut FilePointer, _flags: OpenFlags) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    fn close(&mut self, _file: &mut FilePointer) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    fn read(&mut self, file: &mut FilePointer, buffer: &mut [u8]) -> Result<usize, KernelError> {
+        let nbytes = block::raw_read(self.device_id, buffer, file.position as u64)?;
+        file.position += nbytes;
+        Ok(nbytes)
+    }
+
+    fn write(&mut self, file: &mut FilePointer, buffer: &[u8]) -> Result<usize, KernelError> {
+        let nbytes = block::raw_write(self.device_id, buffer, file.position as u64)?;
+        file.position += nbytes;
+        Ok(nbytes)
+    }
+
+    fn seek(&mut self, file: &mut FilePointer, offset: isize, whence: Seek) -> Result<usize, KernelError> {
+        let position = match whence {
+            Seek::FromStart => offset,
+            Seek::FromCurrent => file.position as isize + offset,
+            Seek::FromEnd => self.attrs.size as isize + offset,
+        };
 
+        if position < 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        file.position = position as usize;
+        Ok(file.position)
+    }
+}
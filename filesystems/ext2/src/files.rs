@@ -1,6 +1,9 @@
 
-use ruxpin_kernel::block;
+use ruxpin_types::DeviceID;
+
+use ruxpin_kernel::block::{self, BlockNum, PageNum};
 use ruxpin_kernel::debug;
+use ruxpin_kernel::arch::mmu;
 use ruxpin_kernel::errors::KernelError;
 
 use super::inodes::Ext2Vnode;
@@ -62,5 +65,29 @@ impl Ext2Vnode {
         self.dirty = true;
         Ok(offset)
     }
-}
 
+    pub(super) fn get_device_page_of_vnode(&mut self, offset: usize) -> Result<Option<(DeviceID, PageNum)>, KernelError> {
+        let page_size = mmu::page_size();
+        let block_size = self.get_block_size();
+
+        // The page can only be used directly if it's entirely inside the file, and its blocks fill a page of the device
+        if offset % page_size != 0 || page_size % block_size != 0 || offset + page_size > self.attrs.size {
+            return Ok(None);
+        }
+
+        let blocks_per_page = page_size / block_size;
+        let znum = offset / block_size;
+        let first_block = match self.get_file_block_num(znum, GetFileBlockOp::Lookup)? {
+            Some(num) if num as usize % blocks_per_page == 0 => num,
+            _ => { return Ok(None); },
+        };
+
+        for i in 1..blocks_per_page {
+            if self.get_file_block_num(znum + i, GetFileBlockOp::Lookup)? != Some(first_block + i as BlockNum) {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some((self.get_device_id(), first_block as usize / blocks_per_page)))
+    }
+}
@@ -7,7 +7,7 @@ use alloc::sync::Arc;
 use ruxpin_types::{OpenFlags, FileAccess, Seek, DeviceID, UserID, GroupID, DirEntry};
 
 use ruxpin_kernel::trace;
-use ruxpin_kernel::block;
+use ruxpin_kernel::block::{self, PageNum};
 use ruxpin_kernel::sync::Spinlock;
 use ruxpin_kernel::errors::KernelError;
 
@@ -77,6 +77,7 @@ impl VnodeOperations for Ext2Vnode {
 
         let (inode_num, vnode) = self.get_mount().alloc_inode(self.attrs.inode, access, uid, gid)?;
         self.add_directory_to_vnode(filename, inode_num, access)?;
+        self.attrs.update_modified();
 
         Ok(vnode)
     }
@@ -114,7 +115,9 @@ impl VnodeOperations for Ext2Vnode {
         self.add_directory_to_vnode(filename, target.lock().attributes()?.inode, self.attrs.access)?;
         target.lock().attributes_mut(&mut |attrs| {
             attrs.nlinks += 1;
+            attrs.update_changed();
         })?;
+        self.attrs.update_modified();
         Ok(())
     }
 
@@ -125,6 +128,8 @@ impl VnodeOperations for Ext2Vnode {
 
         let inode = self.remove_directory_entry(filename)?;
         self.dirty = true;
+        self.attrs.update_modified();
+        target.try_lock().unwrap().attributes_mut(&mut |attrs| attrs.update_changed())?;
         self.attrs.nlinks -= 1;
         if self.attrs.nlinks == 0 {
             target.try_lock().unwrap().truncate()?;
@@ -143,12 +148,14 @@ impl VnodeOperations for Ext2Vnode {
 
         self.remove_directory_entry(old_name)?;
         self.attrs.nlinks -= 1;
+        self.attrs.update_modified();
         self.dirty = true;
         Ok(())
     }
 
     fn truncate(&mut self) -> Result<(), KernelError> {
         self.free_all_blocks()?;
+        self.attrs.update_modified();
         Ok(())
     }
 
@@ -198,18 +205,25 @@ impl VnodeOperations for Ext2Vnode {
         if file.position > self.attrs.size {
             self.attrs.size = file.position;
         }
+        self.attrs.update_modified();
+        self.dirty = true;
         self.writeback()?;
 
         Ok(offset)
     }
 
-    fn seek(&mut self, file: &mut FilePointer, offset: usize, whence: Seek) -> Result<usize, KernelError> {
+    fn seek(&mut self, file: &mut FilePointer, offset: isize, whence: Seek) -> Result<usize, KernelError> {
         let position = match whence {
             Seek::FromStart => offset,
-            Seek::FromCurrent => file.position + offset,
-            Seek::FromEnd => self.attrs.size + offset,
+            Seek::FromCurrent => file.position as isize + offset,
+            Seek::FromEnd => self.attrs.size as isize + offset,
         };
 
+        if position < 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        let position = position as usize;
         if position >= self.attrs.size {
             file.position = self.attrs.size;
         } else {
@@ -236,5 +250,9 @@ impl VnodeOperations for Ext2Vnode {
             }
         }
     }
+
+    fn get_device_page(&mut self, offset: usize) -> Result<Option<(DeviceID, PageNum)>, KernelError> {
+        self.get_device_page_of_vnode(offset)
+    }
 }
 
@@ -10,8 +10,10 @@ use alloc::sync::Arc;
 
 use ruxpin_types::{OpenFlags, FileAccess, DeviceID, Pid, DirEntry};
 
+use ruxpin_kernel::time;
 use ruxpin_kernel::write_bytes;
 use ruxpin_kernel::sync::Spinlock;
+use ruxpin_kernel::mm::kmalloc;
 use ruxpin_kernel::errors::KernelError;
 use ruxpin_kernel::misc::writer::SliceWriter;
 use ruxpin_kernel::proc::scheduler;
@@ -167,6 +169,8 @@ impl VnodeOperations for ProcFsRootVnode {
 
 const ROOT_ENTRIES: &'static [(&'static str, GenericStaticFileData<()>)] = &[
     ("mounts", file_data_mount),
+    ("kmalloc", file_data_kmalloc),
+    ("uptime", file_data_uptime),
 ];
 
 fn file_data_mount(_nothing: &()) -> Result<Vec<u8>, KernelError> {
@@ -184,6 +188,36 @@ fn file_data_mount(_nothing: &()) -> Result<Vec<u8>, KernelError> {
     Ok(data)
 }
 
+fn file_data_kmalloc(_nothing: &()) -> Result<Vec<u8>, KernelError> {
+    let stats = kmalloc::get_stats();
+
+    let mut data = vec![0; 1024];
+    let mut writer = SliceWriter::new(data.as_mut_slice());
+
+    write!(writer, "size total used\n").map_err(|_| KernelError::IOError)?;
+    for slab in stats.slabs.iter() {
+        write!(writer, "{} {} {}\n", slab.object_size, slab.total_objects, slab.used_objects).map_err(|_| KernelError::IOError)?;
+    }
+    write!(writer, "large {} {}\n", stats.large_allocations, stats.large_bytes).map_err(|_| KernelError::IOError)?;
+    write!(writer, "heap {} {}\n", stats.heap_size, stats.heap_size - stats.heap_free).map_err(|_| KernelError::IOError)?;
+
+    let len = writer.len();
+    unsafe { data.set_len(len); }
+    Ok(data)
+}
+
+fn file_data_uptime(_nothing: &()) -> Result<Vec<u8>, KernelError> {
+    let uptime = time::get_uptime();
+
+    let mut data = vec![0; 64];
+    let mut writer = SliceWriter::new(data.as_mut_slice());
+    write!(writer, "{}.{:02}\n", uptime.seconds, uptime.nanoseconds / 10_000_000).map_err(|_| KernelError::IOError)?;
+
+    let len = writer.len();
+    unsafe { data.set_len(len); }
+    Ok(data)
+}
+
 
 const PROCESS_ENTRIES: &'static [(&'static str, GenericStaticFileData<Pid>)] = &[
     ("stat", file_data_stat),
@@ -197,13 +231,14 @@ fn file_data_stat(process_id: &Pid) -> Result<Vec<u8>, KernelError> {
     let mut data = vec![0; 128];
     let mut writer = SliceWriter::new(data.as_mut_slice());
     write!(writer,
-        "{} {} {} {} {} {}",
+        "{} {} {} {} {} {} {}",
         locked_proc.process_id,
         locked_proc.cmd,
         proc_state(locked_proc.state),
         locked_proc.parent_id,
         locked_proc.process_group_id,
         locked_proc.session_id,
+        locked_proc.nice,
     ).map_err(|_| KernelError::FileNotFound)?;
     let len = writer.len();
     unsafe { data.set_len(len); }
@@ -145,3 +145,40 @@ pub const PF_R: Elf64Word               = 1 << 2;       // Segment is readable
 pub const PF_MASKOS: Elf64Word          = 0x0ff00000;   // OS-specific
 pub const PF_MASKPROC: Elf64Word        = 0xf0000000;   // Processor-specific
 
+
+
+// Dynamic Section Entry
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Elf64Dynamic {
+    pub d_tag:      Elf64Sxword,        // Dynamic entry type
+    pub d_val:      Elf64Xword,         // Integer or address value
+}
+
+// Possible Dynamic Entry Types
+
+pub const DT_NULL: Elf64Sxword          = 0;            // Marks end of dynamic section
+pub const DT_RELA: Elf64Sxword          = 7;            // Address of Rela relocs
+pub const DT_RELASZ: Elf64Sxword        = 8;            // Total size of Rela relocs
+pub const DT_RELAENT: Elf64Sxword       = 9;            // Size of one Rela reloc
+
+
+// Relocation Entry With Addend
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Elf64Rela {
+    pub r_offset:   Elf64Addr,          // Address
+    pub r_info:     Elf64Xword,         // Relocation type and symbol index
+    pub r_addend:   Elf64Sxword,        // Addend
+}
+
+pub const fn elf64_r_type(info: Elf64Xword) -> Elf64Word {
+    (info & 0xffffffff) as Elf64Word
+}
+
+// AArch64 Relocation Types
+
+pub const R_AARCH64_NONE: Elf64Word     = 0;            // No relocation
+pub const R_AARCH64_RELATIVE: Elf64Word = 1027;         // Adjust by program base
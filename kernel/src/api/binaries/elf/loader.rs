@@ -3,24 +3,37 @@ use core::mem;
 use core::slice;
 use alloc::string::ToString;
 
-use ruxpin_types::{OpenFlags, FileAccess};
+use ruxpin_types::{OpenFlags, FileAccess, Personality};
 
 use crate::fs;
 use crate::debug;
+use crate::random;
 use crate::arch::mmu;
 use crate::misc::memory;
-use crate::misc::align_down;
+use crate::misc::{align_up, align_down};
 use crate::errors::KernelError;
 use crate::arch::VirtualAddress;
 use crate::misc::strarray::StandardArrayOfStrings;
 use crate::proc::scheduler::Task;
 use crate::proc::tasks::TaskRecord;
 use crate::mm::{MemoryPermissions, SegmentType};
-use crate::mm::pagecache;
+use crate::mm::{pagecache, vmalloc};
 
 use super::defs::*;
 
 
+// The highest address of the stack, before randomization
+const STACK_TOP: u64 = 0x1_0000_0000;
+// The address that position independent executables are loaded at, before randomization
+const DYN_BASE: u64 = 0x1000_0000;
+
+// The largest random offset that's applied to each part of the process's memory
+const STACK_RANDOM_RANGE: u64 = 0x1000_0000;
+const DYN_RANDOM_RANGE: u64 = 0x4000_0000;
+const HEAP_RANDOM_RANGE: u64 = 0x200_0000;
+const MMAP_RANDOM_RANGE: u64 = 0x100_0000_0000;
+
+
 pub fn load_binary(proc: Task, path: &str, argv: &StandardArrayOfStrings, envp: &StandardArrayOfStrings) -> Result<(), KernelError> {
     let mut locked_proc = proc.try_lock()?;
     locked_proc.cmd = path.to_string();
@@ -30,8 +43,9 @@ pub fn load_binary(proc: Task, path: &str, argv: &StandardArrayOfStrings, envp:
     let file = fs::open(None, path, OpenFlags::ReadOnly, FileAccess::DefaultFile, locked_proc.current_uid)?;
     let cache = pagecache::get_page_entry(file.clone())?;
 
-    let header_page = cache.lookup_page_slice(0)?;
-    let header: &Elf64Header = unsafe { memory::cast_to_ref(header_page) };
+    // The pages are held until the headers have been read, so that they can't be reclaimed while in use
+    let header_page = cache.lookup(0)?;
+    let header: &Elf64Header = unsafe { memory::cast_to_ref(mmu::get_page_slice(header_page.addr())) };
 
     // Look for the ELF signature, 64-bit Little Endian ELF Version 1
     if &header.e_ident[0..7] != b"\x7F\x45\x4C\x46\x02\x01\x01" {
@@ -39,7 +53,7 @@ pub fn load_binary(proc: Task, path: &str, argv: &StandardArrayOfStrings, envp:
     }
 
     // Make sure it's an executable for the Aarch64
-    if header.e_type != ET_EXEC || header.e_machine != EM_AARCH64 || header.e_phentsize as usize != mem::size_of::<Elf64ProgramSegment>() {
+    if (header.e_type != ET_EXEC && header.e_type != ET_DYN) || header.e_machine != EM_AARCH64 || header.e_phentsize as usize != mem::size_of::<Elf64ProgramSegment>() {
         return Err(KernelError::NotExecutable);
     }
 
@@ -49,34 +63,108 @@ pub fn load_binary(proc: Task, path: &str, argv: &StandardArrayOfStrings, envp:
     }
 
     // Load the program headers from the ELF file
-    let ps_page = cache.lookup_page_slice(header.e_phoff as usize)?;
+    let ps_page = cache.lookup(header.e_phoff as usize)?;
     let program_segments: &[Elf64ProgramSegment] = unsafe {
-        slice::from_raw_parts(ps_page.as_ptr().add(header.e_phoff as usize) as *const Elf64ProgramSegment, header.e_phnum as usize)
+        slice::from_raw_parts(mmu::get_page_slice(ps_page.addr()).as_ptr().add(header.e_phoff as usize) as *const Elf64ProgramSegment, header.e_phnum as usize)
     };
 
+    // Position independent executables can be loaded anywhere, so they're moved by a random amount
+    let randomize = !locked_proc.personality.is_set(Personality::NoRandomize);
+    let load_bias = if header.e_type == ET_DYN { DYN_BASE + random_offset(randomize, DYN_RANDOM_RANGE) } else { 0 };
+
     let mut end_of_data = 0;
+    let mut dynamic = None;
     for (i, segment) in program_segments.iter().enumerate() {
         debug!("program segment {}: {:x} {:x} offset: {:x} v:{:x} p:{:x} size: {:x}", i, segment.p_type, segment.p_flags, segment.p_offset, segment.p_vaddr, segment.p_paddr, segment.p_filesz);
 
         if segment.p_type == PT_LOAD {
-            let vaddr = VirtualAddress::from(segment.p_vaddr).align_down(4096);
-            let offset = VirtualAddress::from(segment.p_vaddr).offset_from_align(4096);
+            let vaddr = VirtualAddress::from(load_bias + segment.p_vaddr).align_down(4096);
+            let offset = VirtualAddress::from(load_bias + segment.p_vaddr).offset_from_align(4096);
 
             let permissions = flags_to_permissions(segment.p_flags)?;
             let stype = if permissions == MemoryPermissions::ReadWrite { SegmentType::Data } else { SegmentType::Text };
-            locked_proc.space.try_lock()?.add_file_backed_segment(stype, permissions, cache.clone(), segment.p_offset as usize, segment.p_filesz as usize, vaddr, offset, segment.p_memsz as usize)?;
-        } else if segment.p_type == PT_GNU_RELRO {
-            //char **data = proc->map.segments[M_TEXT].base + prog_headers[i].p_vaddr;
-            //for (int entries = prog_headers[i].p_memsz >> 2; entries; entries--, data++)
-            //    *data = (char *) proc->map.segments[M_TEXT].base + (size_t) *data;
+            locked_proc.space.try_lock()?.add_file_backed_segment(stype, permissions, cache.clone(), segment.p_offset as usize, segment.p_filesz as usize, vaddr, offset, segment.p_memsz as usize, false)?;
+        } else if segment.p_type == PT_DYNAMIC {
+            dynamic = Some((load_bias + segment.p_vaddr, segment.p_memsz as usize));
         }
 
-        if segment.p_vaddr + segment.p_memsz > end_of_data {
-            end_of_data = segment.p_vaddr + segment.p_memsz;
+        if load_bias + segment.p_vaddr + segment.p_memsz > end_of_data {
+            end_of_data = load_bias + segment.p_vaddr + segment.p_memsz;
         }
     }
-    set_up_stack(&mut *locked_proc, end_of_data, VirtualAddress::from(header.e_entry), argv, envp)?;
 
+    if let Some((vaddr, size)) = dynamic {
+        apply_relocations(&mut *locked_proc, load_bias, VirtualAddress::from(vaddr), size)?;
+    }
+
+    // The heap starts empty, a random distance after the program, and is grown by sbrk
+    let heap_start = align_up(end_of_data as usize, mmu::page_size()) as u64 + random_offset(randomize, HEAP_RANDOM_RANGE);
+    locked_proc.space.try_lock()?.add_memory_segment(SegmentType::Data, MemoryPermissions::ReadWrite, VirtualAddress::from(heap_start), 0)?;
+
+    if randomize {
+        locked_proc.space.try_lock()?.set_mmap_base(VirtualAddress::from(vmalloc::MMAP_BASE + random_offset(randomize, MMAP_RANDOM_RANGE)))?;
+    }
+
+    let stack_start = STACK_TOP - random_offset(randomize, STACK_RANDOM_RANGE);
+    set_up_stack(&mut *locked_proc, stack_start, heap_start, VirtualAddress::from(load_bias + header.e_entry), argv, envp)?;
+
+    Ok(())
+}
+
+/// Returns a random page-aligned offset less than the given range, or 0 if the process shouldn't be randomized
+fn random_offset(randomize: bool, range: u64) -> u64 {
+    if !randomize {
+        return 0;
+    }
+    let page_size = mmu::page_size() as u64;
+    random::next_below(range / page_size) * page_size
+}
+
+/// Adjust the addresses stored in the program's data by the address it was loaded at
+///
+/// Only the relative relocations generated for static position independent executables are supported
+fn apply_relocations(locked_proc: &mut TaskRecord, load_bias: u64, dynamic: VirtualAddress, size: usize) -> Result<(), KernelError> {
+    let mut space = locked_proc.space.try_lock()?;
+
+    let mut rela = None;
+    let mut rela_size = 0;
+    let mut rela_entry_size = mem::size_of::<Elf64Rela>();
+    for i in 0..size / mem::size_of::<Elf64Dynamic>() {
+        let mut data = [0; mem::size_of::<Elf64Dynamic>()];
+        space.read_user_data(dynamic.add(i * mem::size_of::<Elf64Dynamic>()), &mut data)?;
+        let entry: Elf64Dynamic = unsafe { memory::read_struct(&data) };
+
+        match entry.d_tag {
+            DT_NULL => break,
+            DT_RELA => rela = Some(load_bias + entry.d_val),
+            DT_RELASZ => rela_size = entry.d_val as usize,
+            DT_RELAENT => rela_entry_size = entry.d_val as usize,
+            _ => { },
+        }
+    }
+
+    let rela = match rela {
+        Some(rela) => VirtualAddress::from(rela),
+        None => return Ok(()),
+    };
+    if rela_entry_size != mem::size_of::<Elf64Rela>() {
+        return Err(KernelError::NotExecutable);
+    }
+
+    for i in 0..rela_size / rela_entry_size {
+        let mut data = [0; mem::size_of::<Elf64Rela>()];
+        space.read_user_data(rela.add(i * rela_entry_size), &mut data)?;
+        let entry: Elf64Rela = unsafe { memory::read_struct(&data) };
+
+        match elf64_r_type(entry.r_info) {
+            R_AARCH64_NONE => { },
+            R_AARCH64_RELATIVE => {
+                let value = load_bias.wrapping_add(entry.r_addend as u64);
+                space.write_user_data(VirtualAddress::from(load_bias + entry.r_offset), &value.to_le_bytes())?;
+            },
+            _ => return Err(KernelError::NotExecutable),
+        }
+    }
     Ok(())
 }
 
@@ -93,13 +181,11 @@ fn flags_to_permissions(flags: Elf64Word) -> Result<MemoryPermissions, KernelErr
     }
 }
 
-fn set_up_stack(locked_proc: &mut TaskRecord, end_of_data: u64, entrypoint: VirtualAddress, argv: &StandardArrayOfStrings, envp: &StandardArrayOfStrings) -> Result<(), KernelError> {
+fn set_up_stack(locked_proc: &mut TaskRecord, stack_start: u64, end_of_data: u64, entrypoint: VirtualAddress, argv: &StandardArrayOfStrings, envp: &StandardArrayOfStrings) -> Result<(), KernelError> {
     let page_size = mmu::page_size();
 
-    // TODO the size here is wrong, it needs to use the brk as the stack size, it needs to start higher (0x0001_0000_0000_0000 or 0x0000_8000_0000_0000)
-    let stack_start = 0x1_0000_0000 as u64;
-    //let stack_size = page_size * page_size;
-    let stack_size = align_down((stack_start - end_of_data) as usize, page_size);
+    // The stack takes up all the space above the heap, and is shrunk as the heap grows
+    let stack_size = align_down((stack_start - end_of_data) as usize, page_size) - vmalloc::STACK_GUARD_SIZE;
 
     locked_proc.space.try_lock()?.add_memory_segment(SegmentType::Stack, MemoryPermissions::ReadWrite, VirtualAddress::from(stack_start - stack_size as u64), stack_size)?;
 
@@ -11,7 +11,7 @@ use crate::misc::strarray::StandardArrayOfStrings;
 use self::elf::loader;
 
 pub fn load_process(cmd: &str) -> Result<(), KernelError> {
-    let proc = create_task(None);
+    let proc = create_task(None)?;
     let parsed_argv = StandardArrayOfStrings::new();
     let parsed_envp = StandardArrayOfStrings::new();
     loader::load_binary(proc.clone(), cmd, &parsed_argv, &parsed_envp)?;
@@ -1,12 +1,17 @@
 
-use ruxpin_types::{FileDesc, OpenFlags, FileAccess, DirEntry, UserID};
+use ruxpin_types::{FileDesc, OpenFlags, FileAccess, Seek, DirEntry, Stat, UserID};
 use ruxpin_syscall_proc::syscall_handler;
 
 use crate::proc::scheduler;
 use crate::fs::{self, Vnode};
+use crate::mm::pagecache;
+use crate::sync::Mutex;
 use crate::errors::KernelError;
 
 
+// Writing back every cached page can take a long time, so a task that syncs while another is already syncing sleeps until it's done
+static SYNC_LOCK: Mutex<()> = Mutex::new(());
+
 #[syscall_handler]
 pub fn syscall_open(path: &str, flags: OpenFlags, access: FileAccess) -> Result<FileDesc, KernelError> {
     let proc = scheduler::get_current();
@@ -44,6 +49,12 @@ pub fn syscall_write(file: FileDesc, buffer: &[u8]) -> Result<usize, KernelError
     fs::write(file, buffer)
 }
 
+#[syscall_handler]
+pub fn syscall_seek(file: FileDesc, offset: isize, whence: Seek) -> Result<usize, KernelError> {
+    let file = scheduler::get_current().try_lock()?.files.try_lock()?.get_file(file)?;
+    fs::seek(file, offset, whence)
+}
+
 #[syscall_handler]
 pub fn syscall_readdir(file: FileDesc, dirent: &mut DirEntry) -> Result<bool, KernelError> {
     let file = scheduler::get_current().try_lock()?.files.try_lock()?.get_file(file)?;
@@ -56,6 +67,26 @@ pub fn syscall_readdir(file: FileDesc, dirent: &mut DirEntry) -> Result<bool, Ke
     }
 }
 
+#[syscall_handler]
+pub fn syscall_stat(path: &str, stat: &mut Stat) -> Result<(), KernelError> {
+    let (cwd, current_uid) = get_current_cwd_and_uid()?;
+    *stat = fs::stat(cwd, path, current_uid)?;
+    Ok(())
+}
+
+#[syscall_handler]
+pub fn syscall_fstat(file: FileDesc, stat: &mut Stat) -> Result<(), KernelError> {
+    let file = scheduler::get_current().try_lock()?.files.try_lock()?.get_file(file)?;
+    *stat = fs::fstat(file)?;
+    Ok(())
+}
+
+/// There are no symlinks yet, so lstat is the same as stat until there are
+#[syscall_handler]
+pub fn syscall_lstat(path: &str, stat: &mut Stat) -> Result<(), KernelError> {
+    syscall_stat(path, stat)
+}
+
 #[syscall_handler]
 pub fn syscall_dup2(old_fd: FileDesc, new_fd: FileDesc) -> Result<(), KernelError> {
     if old_fd == new_fd {
@@ -69,6 +100,28 @@ pub fn syscall_dup2(old_fd: FileDesc, new_fd: FileDesc) -> Result<(), KernelErro
     Ok(())
 }
 
+#[syscall_handler]
+pub fn syscall_pipe(fds: &mut [FileDesc; 2]) -> Result<(), KernelError> {
+    let (read_file, write_file) = fs::pipe()?;
+
+    let files = scheduler::get_current().try_lock()?.files.clone();
+    let mut locked_files = files.try_lock()?;
+    let read_fd = locked_files.find_free_slot()?;
+    locked_files.set_slot(read_fd, read_file)?;
+    let write_fd = match locked_files.find_free_slot() {
+        Ok(write_fd) => write_fd,
+        Err(err) => {
+            locked_files.clear_slot(read_fd)?;
+            return Err(err);
+        },
+    };
+    locked_files.set_slot(write_fd, write_file)?;
+
+    fds[0] = read_fd;
+    fds[1] = write_fd;
+    Ok(())
+}
+
 #[syscall_handler]
 pub fn syscall_unlink(path: &str) -> Result<(), KernelError> {
     let (cwd, current_uid) = get_current_cwd_and_uid()?;
@@ -91,9 +144,24 @@ pub fn syscall_mkdir(path: &str, access: FileAccess) -> Result<(), KernelError>
 }
 
 #[syscall_handler]
-pub fn syscall_getcwd(path: &mut [u8]) -> Result<(), KernelError> {
+pub fn syscall_getcwd(path: &mut [u8]) -> Result<usize, KernelError> {
+    let (cwd, current_uid) = get_current_cwd_and_uid()?;
+    let cwd_path = fs::get_path(cwd, current_uid)?;
+
+    let len = cwd_path.as_bytes().len();
+    if len > path.len() {
+        return Err(KernelError::InvalidArgument);
+    }
+    path[..len].copy_from_slice(cwd_path.as_bytes());
+    Ok(len)
+}
 
-    Err(KernelError::OperationNotPermitted)
+#[syscall_handler]
+pub fn syscall_chdir(path: &str) -> Result<(), KernelError> {
+    let (cwd, current_uid) = get_current_cwd_and_uid()?;
+    let vnode = fs::change_directory(cwd, path, current_uid)?;
+    scheduler::get_current().try_lock()?.files.try_lock()?.set_cwd(vnode);
+    Ok(())
 }
 
 fn get_current_cwd_and_uid() -> Result<(Option<Vnode>, UserID), KernelError> {
@@ -107,6 +175,9 @@ fn get_current_cwd_and_uid() -> Result<(Option<Vnode>, UserID), KernelError> {
 
 #[syscall_handler]
 pub fn syscall_sync() -> Result<(), KernelError> {
+    // The lock is taken before anything is written, so the syscall can be restarted from the top when the task is woken
+    let _guard = SYNC_LOCK.lock()?;
+    pagecache::sync_all()?;
     fs::sync_all()
 }
 
@@ -0,0 +1,133 @@
+
+use ruxpin_types::{FileDesc, OpenFlags, FileAccess, MemoryProtection, MapFlags};
+use ruxpin_syscall_proc::syscall_handler;
+
+use crate::fs;
+use crate::arch::mmu;
+use crate::arch::VirtualAddress;
+use crate::proc::scheduler;
+use crate::mm::MemoryPermissions;
+use crate::mm::{pagecache, swap};
+use crate::misc::align_up;
+use crate::errors::KernelError;
+
+
+#[syscall_handler]
+pub fn syscall_mmap(addr: usize, len: usize, prot: MemoryProtection, flags: MapFlags, file: FileDesc, offset: usize) -> Result<*const u8, KernelError> {
+    let permissions = protection_to_permissions(prot)?;
+    let hint = VirtualAddress::from(addr as u64);
+    let len = align_up(len, mmu::page_size());
+    let fixed = flags.is_set(MapFlags::Fixed);
+
+    if len == 0 || flags.is_set(MapFlags::Shared) == flags.is_set(MapFlags::Private) || !is_page_aligned(addr) {
+        return Err(KernelError::InvalidArgument);
+    }
+    if fixed && addr == 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    let proc = scheduler::get_current();
+    let vaddr = if flags.is_set(MapFlags::Anonymous) {
+        // Anonymous memory is copied on write by fork, so there's no way yet to share it with a child process
+        if flags.is_set(MapFlags::Shared) {
+            return Err(KernelError::InvalidArgument);
+        }
+        proc.try_lock()?.space.try_lock()?.map_anonymous(hint, len, permissions, fixed)?
+    } else {
+        if !is_page_aligned(offset) {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        let file = proc.try_lock()?.files.try_lock()?.get_file(file)?;
+        if fs::fstat(file.clone())?.access.file_type() != FileAccess::Regular {
+            return Err(KernelError::NoSuchDevice);
+        }
+
+        // The page cache reads through its own file pointer, so that it doesn't move the position of the open file
+        let cache = pagecache::get_page_entry(fs::reopen(file)?)?;
+        proc.try_lock()?.space.try_lock()?.map_file(hint, len, permissions, fixed, cache, offset, flags.is_set(MapFlags::Shared))?
+    };
+
+    Ok(usize::from(vaddr) as *const u8)
+}
+
+#[syscall_handler]
+pub fn syscall_munmap(addr: usize, len: usize) -> Result<(), KernelError> {
+    if !is_page_aligned(addr) || len == 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    let len = align_up(len, mmu::page_size());
+    let proc = scheduler::get_current();
+    let result = proc.try_lock()?.space.try_lock()?.unmap_range(VirtualAddress::from(addr as u64), len);
+    result
+}
+
+#[syscall_handler]
+pub fn syscall_mprotect(addr: usize, len: usize, prot: MemoryProtection) -> Result<(), KernelError> {
+    let permissions = protection_to_permissions(prot)?;
+    if !is_page_aligned(addr) || len == 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    let len = align_up(len, mmu::page_size());
+    let proc = scheduler::get_current();
+    let result = proc.try_lock()?.space.try_lock()?.protect_range(VirtualAddress::from(addr as u64), len, permissions);
+    result
+}
+
+#[syscall_handler]
+pub fn syscall_msync(addr: usize, len: usize) -> Result<(), KernelError> {
+    if !is_page_aligned(addr) {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    let len = align_up(len, mmu::page_size());
+    let proc = scheduler::get_current();
+    let result = proc.try_lock()?.space.try_lock()?.sync_range(VirtualAddress::from(addr as u64), len);
+    result
+}
+
+#[syscall_handler]
+pub fn syscall_swapon(path: &str) -> Result<(), KernelError> {
+    let file = open_swap_file(path)?;
+    swap::swapon(file)
+}
+
+#[syscall_handler]
+pub fn syscall_swapoff(path: &str) -> Result<(), KernelError> {
+    let file = open_swap_file(path)?;
+    swap::swapoff(file)
+}
+
+fn open_swap_file(path: &str) -> Result<fs::File, KernelError> {
+    let (cwd, current_uid) = {
+        let proc = scheduler::get_current();
+        let locked_proc = proc.try_lock()?;
+        if locked_proc.current_uid != 0 {
+            return Err(KernelError::OperationNotPermitted);
+        }
+        let cwd = locked_proc.files.try_lock()?.get_cwd();
+        (cwd, locked_proc.current_uid)
+    };
+
+    fs::open(cwd, path, OpenFlags::ReadWrite, FileAccess::Regular, current_uid)
+}
+
+fn protection_to_permissions(prot: MemoryProtection) -> Result<MemoryPermissions, KernelError> {
+    // Pages can't be mapped without read access, so write and execute imply read
+    let write = prot.is_set(MemoryProtection::Write);
+    let exec = prot.is_set(MemoryProtection::Exec);
+    match (write, exec) {
+        (false, false) if prot.is_set(MemoryProtection::Read) => Ok(MemoryPermissions::ReadOnly),
+        (false, true) => Ok(MemoryPermissions::ReadExecute),
+        (true, false) => Ok(MemoryPermissions::ReadWrite),
+        (true, true) => Ok(MemoryPermissions::ReadWriteExecute),
+        _ => Err(KernelError::InvalidArgument),
+    }
+}
+
+fn is_page_aligned(addr: usize) -> bool {
+    addr & (mmu::page_size() - 1) == 0
+}
+
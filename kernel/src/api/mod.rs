@@ -8,6 +8,10 @@ use crate::arch::Context;
 
 mod file;
 mod proc;
+mod memory;
+mod signal;
+mod time;
+pub mod user;
 pub mod binaries;
 
 
@@ -32,6 +36,16 @@ pub fn process_syscall(syscall: &mut SyscallRequest) {
         return;
     }
 
+    if syscall.function == SyscallFunction::SigReturn {
+        self::signal::handle_syscall_sigreturn(syscall);
+        if syscall.error {
+            Context::write_syscall_result_to_current_context(syscall);
+        }
+        // Return without setting the return value, which would overwrite the
+        // registers restored from the signal frame
+        return;
+    }
+
     match syscall.function {
         SyscallFunction::Exit => {
             self::proc::handle_syscall_exit(syscall);
@@ -41,6 +55,50 @@ pub fn process_syscall(syscall: &mut SyscallRequest) {
             self::proc::handle_syscall_fork(syscall);
         },
 
+        SyscallFunction::Clone => {
+            self::proc::handle_syscall_clone(syscall);
+        },
+        SyscallFunction::GetTid => {
+            self::proc::handle_syscall_gettid(syscall);
+        },
+        SyscallFunction::ThreadExit => {
+            self::proc::handle_syscall_thread_exit(syscall);
+        },
+        SyscallFunction::Futex => {
+            self::proc::handle_syscall_futex(syscall);
+        },
+        SyscallFunction::Personality => {
+            self::proc::handle_syscall_personality(syscall);
+        },
+        SyscallFunction::Nice => {
+            self::proc::handle_syscall_nice(syscall);
+        },
+        SyscallFunction::SetPriority => {
+            self::proc::handle_syscall_setpriority(syscall);
+        },
+        SyscallFunction::GetPriority => {
+            self::proc::handle_syscall_getpriority(syscall);
+        },
+        SyscallFunction::Sleep => {
+            self::proc::handle_syscall_sleep(syscall);
+        },
+        SyscallFunction::NanoSleep => {
+            self::proc::handle_syscall_nanosleep(syscall);
+        },
+
+        SyscallFunction::ClockGetTime => {
+            self::time::handle_syscall_clock_gettime(syscall);
+        },
+        SyscallFunction::ClockSetTime => {
+            self::time::handle_syscall_clock_settime(syscall);
+        },
+        SyscallFunction::GetTimeOfDay => {
+            self::time::handle_syscall_gettimeofday(syscall);
+        },
+        SyscallFunction::SetTimeOfDay => {
+            self::time::handle_syscall_settimeofday(syscall);
+        },
+
         //SyscallFunction::Exec => {
         //    self::proc::handle_syscall_exec(syscall);
         //},
@@ -52,6 +110,34 @@ pub fn process_syscall(syscall: &mut SyscallRequest) {
         SyscallFunction::Sbrk => {
             self::proc::handle_syscall_sbrk(syscall);
         },
+        SyscallFunction::Mmap => {
+            self::memory::handle_syscall_mmap(syscall);
+        },
+        SyscallFunction::Munmap => {
+            self::memory::handle_syscall_munmap(syscall);
+        },
+        SyscallFunction::Mprotect => {
+            self::memory::handle_syscall_mprotect(syscall);
+        },
+        SyscallFunction::Msync => {
+            self::memory::handle_syscall_msync(syscall);
+        },
+        SyscallFunction::Swapon => {
+            self::memory::handle_syscall_swapon(syscall);
+        },
+        SyscallFunction::Swapoff => {
+            self::memory::handle_syscall_swapoff(syscall);
+        },
+
+        SyscallFunction::Kill => {
+            self::signal::handle_syscall_kill(syscall);
+        },
+        SyscallFunction::SigAction => {
+            self::signal::handle_syscall_sigaction(syscall);
+        },
+        SyscallFunction::SigProcMask => {
+            self::signal::handle_syscall_sigprocmask(syscall);
+        },
 
         SyscallFunction::Open => {
             self::file::handle_syscall_open(syscall);
@@ -65,12 +151,27 @@ pub fn process_syscall(syscall: &mut SyscallRequest) {
         SyscallFunction::Write => {
             self::file::handle_syscall_write(syscall);
         },
+        SyscallFunction::Seek => {
+            self::file::handle_syscall_seek(syscall);
+        },
         SyscallFunction::ReadDir => {
             self::file::handle_syscall_readdir(syscall);
         },
+        SyscallFunction::Stat => {
+            self::file::handle_syscall_stat(syscall);
+        },
+        SyscallFunction::FStat => {
+            self::file::handle_syscall_fstat(syscall);
+        },
+        SyscallFunction::LStat => {
+            self::file::handle_syscall_lstat(syscall);
+        },
         SyscallFunction::Dup2 => {
             self::file::handle_syscall_dup2(syscall);
         },
+        SyscallFunction::Pipe => {
+            self::file::handle_syscall_pipe(syscall);
+        },
         SyscallFunction::Unlink => {
             self::file::handle_syscall_unlink(syscall);
         },
@@ -83,6 +184,9 @@ pub fn process_syscall(syscall: &mut SyscallRequest) {
         SyscallFunction::GetCwd => {
             self::file::handle_syscall_getcwd(syscall);
         },
+        SyscallFunction::ChDir => {
+            self::file::handle_syscall_chdir(syscall);
+        },
         SyscallFunction::Sync => {
             self::file::handle_syscall_sync(syscall);
         },
@@ -1,19 +1,24 @@
 
-use ruxpin_types::Pid;
+use core::sync::atomic::{AtomicU32, Ordering};
+use alloc::vec;
+
+use ruxpin_types::{Pid, Tid, WaitOptions, WaitStatus, CloneArgs, FutexOp, Personality, Timespec, NANOSECONDS_PER_SECOND};
 use ruxpin_syscall_proc::syscall_handler;
 
-use crate::proc::scheduler;
+use crate::time;
+use crate::proc::{scheduler, futex};
 use crate::errors::KernelError;
 use crate::proc::scheduler::Task;
 use crate::proc::tasks::TaskCloneArgs;
 use crate::misc::strarray::{StrArray, StandardArrayOfStrings};
 
+use super::user;
 use super::binaries::elf::loader;
 
 
 #[syscall_handler]
 pub fn syscall_exit(status: isize) -> Result<(), KernelError> {
-    scheduler::exit_current(status);
+    scheduler::exit_current(WaitStatus::new_exited(status));
     Ok(())
 }
 
@@ -25,23 +30,147 @@ pub fn syscall_fork() -> Result<Pid, KernelError> {
     Ok(child_pid)
 }
 
+#[syscall_handler]
+pub fn syscall_clone(args: &CloneArgs) -> Result<Tid, KernelError> {
+    let args = TaskCloneArgs::from(args);
+    let new_task = scheduler::clone_current(args)?;
+    let task_id = new_task.try_lock()?.task_id;
+    Ok(task_id)
+}
+
+#[syscall_handler]
+pub fn syscall_gettid() -> Result<Tid, KernelError> {
+    let task_id = scheduler::get_current().try_lock()?.task_id;
+    Ok(task_id)
+}
+
+#[syscall_handler]
+pub fn syscall_thread_exit(status: isize) -> Result<(), KernelError> {
+    let current = scheduler::get_current();
+
+    let clear_tid = current.try_lock()?.clear_tid;
+    if let Some(address) = clear_tid {
+        // Let any joining threads know this thread has exited
+        let tid_word = unsafe { &*(u64::from(address) as *const AtomicU32) };
+        if current.try_lock()?.space.try_lock()?.check_user_range(address, 4, true).is_ok() {
+            tid_word.store(0, Ordering::SeqCst);
+            futex::wake(tid_word, usize::MAX)?;
+        }
+    }
+
+    scheduler::exit_thread(current, WaitStatus::new_exited(status));
+    Ok(())
+}
+
+#[syscall_handler]
+pub fn syscall_futex(futex_word: &AtomicU32, op: FutexOp, value: usize) -> Result<usize, KernelError> {
+    match op {
+        FutexOp::Wait => {
+            futex::wait(futex_word, value as u32)?;
+            Ok(0)
+        },
+        FutexOp::Wake => {
+            futex::wake(futex_word, value)
+        },
+    }
+}
+
+#[syscall_handler]
+pub fn syscall_personality(persona: Personality) -> Result<Personality, KernelError> {
+    let current = scheduler::get_current();
+    let mut locked_current = current.try_lock()?;
+    let previous = locked_current.personality;
+    locked_current.personality = persona;
+    Ok(previous)
+}
+
+#[syscall_handler]
+pub fn syscall_nice(increment: isize) -> Result<i32, KernelError> {
+    let process_id = scheduler::get_current().try_lock()?.process_id;
+    let nice = scheduler::get_nice(process_id)?;
+    scheduler::set_nice(process_id, nice as isize + increment)
+}
+
+#[syscall_handler]
+pub fn syscall_setpriority(pid: Pid, nice: isize) -> Result<(), KernelError> {
+    scheduler::set_nice(process_or_current(pid)?, nice)?;
+    Ok(())
+}
+
+#[syscall_handler]
+pub fn syscall_getpriority(pid: Pid) -> Result<i32, KernelError> {
+    scheduler::get_nice(process_or_current(pid)?)
+}
+
+#[syscall_handler]
+pub fn syscall_sleep(seconds: usize) -> Result<(), KernelError> {
+    let duration = (seconds as u64).checked_mul(NANOSECONDS_PER_SECOND).ok_or(KernelError::InvalidArgument)?;
+    sleep_current(duration)
+}
+
+#[syscall_handler]
+pub fn syscall_nanosleep(duration: &Timespec) -> Result<(), KernelError> {
+    let duration = duration.as_nanoseconds().ok_or(KernelError::InvalidArgument)?;
+    sleep_current(duration)
+}
+
+fn sleep_current(duration: u64) -> Result<(), KernelError> {
+    let current = scheduler::get_current();
+    let now = time::get_uptime_ns();
+
+    // The syscall is restarted when the task is woken, so the deadline and timer are only set the first time through
+    {
+        let mut locked_current = current.try_lock()?;
+        let deadline = *locked_current.sleep_deadline.get_or_insert(now.saturating_add(duration));
+        if now >= deadline {
+            locked_current.cancel_sleep();
+            return Ok(());
+        }
+
+        if locked_current.sleep_timer.is_none() {
+            let task = current.clone();
+            locked_current.sleep_timer = Some(time::add_timer(deadline, move || {
+                scheduler::wake_tasks(vec![task]);
+            }));
+        }
+    }
+
+    scheduler::suspend(current);
+    Ok(())
+}
+
+fn process_or_current(pid: Pid) -> Result<Pid, KernelError> {
+    match pid {
+        0 => Ok(scheduler::get_current().try_lock()?.process_id),
+        pid if pid > 0 => Ok(pid),
+        _ => Err(KernelError::InvalidArgument),
+    }
+}
+
 #[syscall_handler]
 pub fn syscall_exec(path: &str, argv: &[&str], envp: &[&str]) -> Result<(), KernelError> {
-    // This function must not return an error without exiting the process
+    // This function must not return an error without exiting the process, once the process has been changed
     let proc = scheduler::get_current();
 
+    // The strings in the arrays are only checked here, since the syscall handler only checks the arrays themselves.
+    // The process hasn't been changed yet, so it can still return from exec with an error
+    for arg in argv.iter().chain(envp.iter()) {
+        user::check_user_access(arg.as_ptr() as usize, arg.len(), false)?;
+    }
     let parsed_argv = StandardArrayOfStrings::new_parsed(argv);
     let parsed_envp = StandardArrayOfStrings::new_parsed(envp);
 
-    // Need to copy the path out of user memory before we free it all, but this should eventually use a copy_from_user() function
+    // Copy the path out of user memory before it's all freed
     let mut saved_path: StrArray<100> = StrArray::new();
-    saved_path.copy_into(path);
+    let length = path.len().min(saved_path.as_mut().len());
+    user::copy_from_user(&mut saved_path.as_mut()[..length], path.as_ptr() as usize)?;
+    unsafe { saved_path.set_len(length); }
 
     let result = setup_process(proc, saved_path.as_str(), &parsed_argv, &parsed_envp);
     match result {
         Ok(_) => Ok(()),
         Err(err) => {
-            scheduler::exit_current(-1);
+            scheduler::exit_current(WaitStatus::new_exited(-1));
             Err(err)
         },
     }
@@ -50,7 +179,10 @@ pub fn syscall_exec(path: &str, argv: &[&str], envp: &[&str]) -> Result<(), Kern
 fn setup_process(proc: Task, path: &str, argv: &StandardArrayOfStrings, envp: &StandardArrayOfStrings) -> Result<(), KernelError> {
     // This function can return an error safely
 
+    // The other threads would keep running the old image, so they exit, and this thread takes over the process
+    scheduler::exit_other_threads(proc.clone(), WaitStatus::new_exited(0));
     proc.try_lock()?.free_memory()?;
+    proc.try_lock()?.signals.reset_for_exec();
 
     loader::load_binary(proc.clone(), path, argv, envp)?;
 
@@ -58,23 +190,41 @@ fn setup_process(proc: Task, path: &str, argv: &StandardArrayOfStrings, envp: &S
 }
 
 #[syscall_handler]
-pub fn syscall_waitpid(pid: Pid, status: &mut isize, _options: usize) -> Result<Pid, KernelError> {
-    let parent_id = scheduler::get_current().lock().process_id;
-
-    let search_pid = if pid > 0 { Some(pid) } else { None };
-    let search_parent = if pid == 0 { Some(parent_id) } else { None };
-    let proc = scheduler::find_exited(search_pid, search_parent, None);
-
-    if let Some(proc) = proc {
-        let pid = proc.try_lock()?.process_id;
-        if status as *mut isize as usize != 0 {
-            *status = proc.try_lock()?.exit_status.unwrap();
-        }
-        scheduler::clean_up(pid)?;
-        Ok(pid)
-    } else {
-        scheduler::suspend(scheduler::get_current());
-        Ok(0)
+pub fn syscall_waitpid(pid: Pid, status: Option<&mut WaitStatus>, options: WaitOptions) -> Result<Pid, KernelError> {
+    let (parent_id, group_id) = {
+        let current = scheduler::get_current();
+        let locked_task = current.try_lock()?;
+        (locked_task.process_id, locked_task.process_group_id)
+    };
+
+    // pid > 0 is a specific child, pid == -1 is any child, pid == 0 is any child in the same process group, and pid < -1 is any child in the group -pid
+    let (search_pid, search_group) = match pid {
+        pid if pid > 0 => (Some(pid), None),
+        -1 => (None, None),
+        0 => (None, Some(group_id)),
+        pid => (None, Some(-pid)),
+    };
+
+    match scheduler::find_exited(parent_id, search_pid, search_group)? {
+        Some(proc) => {
+            let (pid, exit_status) = {
+                let locked_proc = proc.try_lock()?;
+                (locked_proc.process_id, locked_proc.exit_status.unwrap())
+            };
+            scheduler::clean_up(pid)?;
+            if let Some(status) = status {
+                *status = exit_status;
+            }
+            Ok(pid)
+        },
+        None if options.is_set(WaitOptions::NoHang) => {
+            Ok(0)
+        },
+        None => {
+            let exit_queue = scheduler::get_current().try_lock()?.child_exit_queue.clone();
+            exit_queue.wait();
+            Ok(0)
+        },
     }
 }
 
@@ -0,0 +1,55 @@
+
+use ruxpin_types::{Pid, Signal, SignalSet, SignalAction, RawSignalAction, SignalMaskHow};
+use ruxpin_syscall_proc::syscall_handler;
+
+use crate::proc::{scheduler, signals};
+use crate::errors::KernelError;
+
+
+#[syscall_handler]
+pub fn syscall_kill(pid: Pid, signal: Signal) -> Result<(), KernelError> {
+    if signal.0 != 0 && !signal.is_valid() {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    scheduler::send_signal(pid, signal)
+}
+
+#[syscall_handler]
+pub fn syscall_sigaction(signal: Signal, action: Option<&RawSignalAction>, old_action: Option<&mut RawSignalAction>) -> Result<(), KernelError> {
+    if !signal.is_valid() {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    // Copy the new action out of user memory before locking the task, in case it causes a page fault
+    let new_action = match action {
+        Some(action) => Some(SignalAction::try_from(*action).map_err(|_| KernelError::InvalidArgument)?),
+        None => None,
+    };
+
+    let previous = {
+        let current = scheduler::get_current();
+        let mut locked_task = current.try_lock()?;
+        match new_action {
+            Some(new_action) => locked_task.signals.set_action(signal, new_action)?,
+            None => locked_task.signals.get_action(signal),
+        }
+    };
+
+    if let Some(old_action) = old_action {
+        *old_action = previous.into();
+    }
+    Ok(())
+}
+
+#[syscall_handler]
+pub fn syscall_sigprocmask(how: SignalMaskHow, mask: SignalSet) -> Result<SignalSet, KernelError> {
+    let current = scheduler::get_current();
+    let previous = current.try_lock()?.signals.set_blocked(how, mask);
+    Ok(previous)
+}
+
+#[syscall_handler]
+pub fn syscall_sigreturn() -> Result<(), KernelError> {
+    signals::return_from_signal(scheduler::get_current())
+}
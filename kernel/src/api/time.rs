@@ -0,0 +1,47 @@
+
+use ruxpin_types::{ClockId, Timespec, Timeval};
+use ruxpin_syscall_proc::syscall_handler;
+
+use crate::time;
+use crate::proc::scheduler;
+use crate::errors::KernelError;
+
+
+#[syscall_handler]
+pub fn syscall_clock_gettime(clock: ClockId, time: &mut Timespec) -> Result<(), KernelError> {
+    *time = match clock {
+        ClockId::Realtime => time::get_realtime(),
+        ClockId::Monotonic => time::get_uptime(),
+    };
+    Ok(())
+}
+
+#[syscall_handler]
+pub fn syscall_clock_settime(clock: ClockId, time: &Timespec) -> Result<(), KernelError> {
+    match clock {
+        ClockId::Realtime => set_realtime(*time),
+        ClockId::Monotonic => Err(KernelError::InvalidArgument),
+    }
+}
+
+#[syscall_handler]
+pub fn syscall_gettimeofday(time: &mut Timeval) -> Result<(), KernelError> {
+    *time = Timeval::from(time::get_realtime());
+    Ok(())
+}
+
+#[syscall_handler]
+pub fn syscall_settimeofday(time: &Timeval) -> Result<(), KernelError> {
+    if time.microseconds >= 1_000_000 {
+        return Err(KernelError::InvalidArgument);
+    }
+    set_realtime(Timespec::from(*time))
+}
+
+fn set_realtime(time: Timespec) -> Result<(), KernelError> {
+    // Only root can set the clock
+    if scheduler::get_current().try_lock()?.current_uid != 0 {
+        return Err(KernelError::OperationNotPermitted);
+    }
+    time::set_realtime(time)
+}
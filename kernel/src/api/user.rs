@@ -0,0 +1,40 @@
+
+use core::slice;
+
+use crate::proc::scheduler;
+use crate::arch::VirtualAddress;
+use crate::errors::KernelError;
+
+
+/// Check that the current process can access the given range of its memory, and load any pages that aren't in memory
+///
+/// The syscall handlers call this for every pointer argument before it's used
+pub fn check_user_access(addr: usize, len: usize, write: bool) -> Result<(), KernelError> {
+    if len == 0 {
+        return Ok(());
+    }
+
+    // Null is never mapped, and arguments that can be null are decoded as an Option instead of being checked
+    if addr == 0 {
+        return Err(KernelError::AddressUnmapped);
+    }
+
+    let space = scheduler::get_current().try_lock()?.space.clone();
+    let result = space.try_lock()?.fault_in_user_range(VirtualAddress::from(addr as u64), len, write);
+    result
+}
+
+pub fn copy_from_user(dest: &mut [u8], src: usize) -> Result<(), KernelError> {
+    check_user_access(src, dest.len(), false)?;
+    let source = unsafe { slice::from_raw_parts(src as *const u8, dest.len()) };
+    dest.copy_from_slice(source);
+    Ok(())
+}
+
+pub fn copy_to_user(dest: usize, src: &[u8]) -> Result<(), KernelError> {
+    check_user_access(dest, src.len(), true)?;
+    let destination = unsafe { slice::from_raw_parts_mut(dest as *mut u8, src.len()) };
+    destination.copy_from_slice(src);
+    Ok(())
+}
+
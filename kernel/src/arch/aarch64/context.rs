@@ -6,6 +6,7 @@ use core::arch::asm;
 use ruxpin_syscall::{SyscallRequest, SyscallFunction};
 
 use crate::notice;
+use super::smp::MAX_CPUS;
 use super::types::VirtualAddress;
 use super::mmu::TranslationTable;
 
@@ -15,8 +16,14 @@ extern "C" {
     pub fn _start_multitasking() -> !;
 }
 
+const SPSR_CONDITION_FLAGS: u64 = 0xF000_0000;
+
+// Kernel threads run in EL1 using SP_EL0 as their stack, with IRQs unmasked so they can be preempted
+const SPSR_KERNEL_THREAD: u64 = 0x344;
+
+// The context of the task running on each core, which is saved to and restored from by the exception handlers
 #[no_mangle]
-pub static mut CURRENT_CONTEXT: *mut Context = ptr::null_mut();
+pub static mut CURRENT_CONTEXT: [*mut Context; MAX_CPUS] = [ptr::null_mut(); MAX_CPUS];
 
 #[repr(C)]
 #[derive(Clone)]
@@ -73,6 +80,20 @@ impl Context {
         self.spsr = 0x0;
     }
 
+    /// Set up the context of a kernel thread, which runs the entry function on its own stack with the argument in x0
+    pub fn init_kernel_thread_context(&mut self, entry: extern "C" fn(usize) -> !, arg: usize, sp: VirtualAddress, ttbr: u64) {
+        self.set_ttbr(ttbr);
+        unsafe {
+            _create_context(self, u64::from(sp), entry as u64);
+        }
+        self.x_registers[0] = arg as u64;
+        self.spsr = SPSR_KERNEL_THREAD;
+    }
+
+    pub(super) fn kernel_thread_request(&self) -> usize {
+        self.x_registers[0] as usize
+    }
+
     pub fn set_ttbr(&mut self, ttbr: u64) {
         self.ttbr = ttbr;
     }
@@ -108,12 +129,34 @@ impl Context {
         self.x_registers[1] = argv.into();
         self.x_registers[2] = envp.into();
     }
+
+    pub fn setup_thread_start(&mut self, entry: VirtualAddress, sp: VirtualAddress, arg: usize) {
+        self.x_registers[0] = arg as u64;
+        self.x_registers[30] = 0;
+        self.x_registers[31] = sp.into();
+        self.elr = entry.into();
+    }
+
+    pub fn setup_signal_handler(&mut self, handler: VirtualAddress, sp: VirtualAddress, return_addr: VirtualAddress, signal: usize) {
+        self.x_registers[0] = signal as u64;
+        self.x_registers[30] = return_addr.into();
+        self.x_registers[31] = sp.into();
+        self.elr = handler.into();
+    }
+
+    pub fn restore_signal_context(&mut self, saved: &Context) {
+        self.x_registers = saved.x_registers;
+        self.v_registers = saved.v_registers;
+        self.elr = saved.elr;
+        // Only the condition flags can be restored from user memory, so that the task can't raise its exception level
+        self.spsr = saved.spsr & SPSR_CONDITION_FLAGS;
+    }
 }
 
 impl Context {
     pub fn dump_current() {
         unsafe {
-            notice!("{}", &*CURRENT_CONTEXT);
+            notice!("{}", &*CURRENT_CONTEXT[cpu_id()]);
         }
     }
 
@@ -125,19 +168,19 @@ impl Context {
                 ttbr = in(reg) new_context.ttbr,
             );
 
-            CURRENT_CONTEXT = new_context as *mut Context;
+            CURRENT_CONTEXT[cpu_id()] = new_context as *mut Context;
         }
     }
 
     pub fn syscall_from_current_context() -> SyscallRequest {
         unsafe {
-            (&*CURRENT_CONTEXT).into()
+            (&*CURRENT_CONTEXT[cpu_id()]).into()
         }
     }
 
     pub fn write_syscall_result_to_current_context(syscall: &SyscallRequest) {
         unsafe {
-            (&mut *CURRENT_CONTEXT).write_syscall_result(syscall);
+            (&mut *CURRENT_CONTEXT[cpu_id()]).write_syscall_result(syscall);
         }
     }
 }
@@ -176,6 +219,40 @@ pub fn loop_forever() -> ! {
     }
 }
 
+/// Read the system counter, which counts up at a fixed frequency from when the system was started
+pub fn read_counter() -> u64 {
+    unsafe {
+        let mut count;
+        asm!(
+            "mrs	{count}, CNTPCT_EL0",
+            count = out(reg) count,
+        );
+        count
+    }
+}
+
+/// Read the number of times per second that the system counter counts up
+pub fn read_counter_frequency() -> u64 {
+    unsafe {
+        let mut frequency;
+        asm!(
+            "mrs	{frequency}, CNTFRQ_EL0",
+            frequency = out(reg) frequency,
+        );
+        frequency
+    }
+}
+
+/// Trap to the exception handler from a kernel thread, so that its context is saved before the request is handled
+pub fn kernel_thread_request(request: usize) {
+    unsafe {
+        asm!(
+            "svc    #0",
+            inout("x0") request => _,
+        );
+    }
+}
+
 pub fn cpu_id() -> usize {
     unsafe {
         let mut id;
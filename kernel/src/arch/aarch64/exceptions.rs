@@ -1,12 +1,16 @@
 
 use core::arch::asm;
 
+use ruxpin_types::Signal;
+
 use crate::api;
 use crate::irqs;
 use crate::tasklets;
 use crate::{error, debug, trace};
 use crate::printk::printk_dump;
-use crate::proc::scheduler;
+use crate::mm::{kstack, swap};
+use crate::proc::{scheduler, signals, kthread};
+use crate::errors::KernelError;
 
 use super::types::VirtualAddress;
 use super::context::{self, Context};
@@ -15,6 +19,7 @@ use super::context::{self, Context};
 const DFSC_TRANSLATION_FAULT: u64       = 0b000100;
 const DFSC_ACCESS_FAULT: u64            = 0b001000;
 const DFSC_PERMISSIONS_FAULT: u64       = 0b001100;
+const DFSC_ALIGNMENT_FAULT: u64         = 0b100001;
 
 
 pub type IrqFlags = u64;
@@ -79,7 +84,27 @@ pub extern "C" fn fatal_user_error(context: &Context, elr: u64, esr: u64, far: u
     }
 
     //context::loop_forever();
-    scheduler::abort(scheduler::get_current());
+    scheduler::force_signal(scheduler::get_current(), signal_for_exception(esr));
+}
+
+fn signal_for_exception(esr: u64) -> Signal {
+    match esr >> 26 {
+        // Instruction or Data Abort from lower EL
+        0b100000 | 0b100100 => {
+            if esr & 0b111111 == DFSC_ALIGNMENT_FAULT {
+                Signal::BusError
+            } else {
+                Signal::SegmentationFault
+            }
+        },
+        // PC or SP Alignment Fault
+        0b100010 | 0b100110 => Signal::BusError,
+        // Trapped Floating Point Exception
+        0b101100 => Signal::FloatingPointError,
+        // BRK Instruction
+        0b111100 => Signal::Trap,
+        _ => Signal::IllegalInstruction,
+    }
 }
 
 #[no_mangle]
@@ -103,11 +128,25 @@ extern "C" fn handle_user_exception(context: &Context, elr: u64, esr: u64, far:
             match esr & 0b111100 {
                 DFSC_ACCESS_FAULT | DFSC_TRANSLATION_FAULT => {
                     trace!("Instruction or Data Abort {:x} caused by Access Flag at address {:x} (allocating new page)", esr, far);
-                    page_fault_handler(far);
+                    // Make room for the new page before locking the address space, since swapping out will lock each
+                    // space and wait for the other cpus.  Faults from the kernel can't swap, because they can happen
+                    // while a syscall holds locks
+                    swap::balance();
+                    match page_fault_handler(far) {
+                        Ok(()) => { },
+                        Err(KernelError::StackOverflow) => {
+                            scheduler::force_signal(scheduler::get_current(), Signal::SegmentationFault);
+                        },
+                        Err(_) => {
+                            fatal_user_error(context, elr, esr, far);
+                        },
+                    }
                 },
                 DFSC_PERMISSIONS_FAULT => {
                     trace!("Instruction or Data Abort {:x} caused by Permissions Flag at address {:x} (either copy-on-write or fault)", esr, far);
-                    page_access_handler(far);
+                    if page_access_handler(far).is_err() {
+                        fatal_user_error(context, elr, esr, far);
+                    }
                 },
                 _ => {
                     fatal_user_error(context, elr, esr, far);
@@ -122,6 +161,7 @@ extern "C" fn handle_user_exception(context: &Context, elr: u64, esr: u64, far:
 
     run_tasklets_with_interrupts();
     scheduler::check_restart_syscall();
+    signals::check_pending_signals();
 }
 
 #[no_mangle]
@@ -130,6 +170,37 @@ extern "C" fn handle_user_irq(_context: &Context, _elr: u64, _esr: u64, _far: u6
 
     irqs::handle_irqs();
 
+    run_tasklets_with_interrupts();
+    scheduler::check_restart_syscall();
+    signals::check_pending_signals();
+}
+
+#[no_mangle]
+extern "C" fn handle_kernel_thread_exception(context: &Context, elr: u64, esr: u64, far: u64, sp: u64) {
+    match esr >> 26 {
+        // SVC from Aarch64
+        0b010101 => {
+            kthread::handle_request(context.kernel_thread_request());
+        },
+
+        _ => {
+            if kstack::is_kernel_stack_guard(VirtualAddress::from(far)) {
+                error!("\nStack overflow in kernel thread {:?} at address {:#x}", scheduler::get_current().lock().task_id, far);
+            }
+            fatal_kernel_error(sp, elr, esr, far);
+        }
+    }
+
+    run_tasklets_with_interrupts();
+    scheduler::check_restart_syscall();
+    signals::check_pending_signals();
+}
+
+#[no_mangle]
+extern "C" fn handle_kernel_thread_irq(_context: &Context, _elr: u64, _esr: u64, _far: u64, _sp: u64) {
+    // The timer interrupt can switch to another task, in which case the kernel thread continues where it was interrupted
+    irqs::handle_irqs();
+
     run_tasklets_with_interrupts();
     scheduler::check_restart_syscall();
 }
@@ -144,11 +215,15 @@ extern "C" fn handle_kernel_exception(sp: u64, elr: u64, esr: u64, far: u64) {
             match esr & 0b111100 {
                 DFSC_ACCESS_FAULT | DFSC_TRANSLATION_FAULT => {
                     trace!("Instruction or Data Abort {:x} caused by Access Flag at address {:x} (allocating new page)", esr, far);
-                    page_fault_handler(far);
+                    if page_fault_handler(far).is_err() {
+                        scheduler::abort(scheduler::get_current());
+                    }
                 },
                 DFSC_PERMISSIONS_FAULT => {
                     trace!("Instruction or Data Abort {:x} caused by Permissions Flag at address {:x} (either copy-on-write or fault)", esr, far);
-                    page_access_handler(far);
+                    if page_access_handler(far).is_err() {
+                        scheduler::abort(scheduler::get_current());
+                    }
                 },
                 _ => {
                     fatal_kernel_error(sp, elr, esr, far);
@@ -177,21 +252,21 @@ fn run_tasklets_with_interrupts() {
     disable_all_irq();
 }
 
-fn page_fault_handler(far: u64) {
+fn page_fault_handler(far: u64) -> Result<(), KernelError> {
     let current = scheduler::get_current();
-    let result = current.try_lock().unwrap().space.try_lock().unwrap().alloc_page_at(VirtualAddress::from(far));
-    match result {
-        Ok(()) => { },
-        Err(_) => scheduler::abort(scheduler::get_current()),
+    // Another thread of the process can be holding the address space on another cpu, which is a fault rather than a panic
+    let space = current.try_lock()?.space.clone();
+    let result = space.try_lock()?.alloc_page_at(VirtualAddress::from(far));
+    if let Err(KernelError::StackOverflow) = result {
+        error!("\nStack overflow in PID {} at address {:#x}", current.lock().process_id, far);
     }
+    result
 }
 
-fn page_access_handler(far: u64) {
+fn page_access_handler(far: u64) -> Result<(), KernelError> {
     let current = scheduler::get_current();
-    let result = current.try_lock().unwrap().space.try_lock().unwrap().copy_on_write_at(VirtualAddress::from(far));
-    match result {
-        Ok(()) => { },
-        Err(_) => scheduler::abort(scheduler::get_current()),
-    }
+    let space = current.try_lock()?.space.clone();
+    let result = space.try_lock()?.copy_on_write_at(VirtualAddress::from(far));
+    result
 }
 
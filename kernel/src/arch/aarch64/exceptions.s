@@ -9,6 +9,15 @@
 
 .section .text
 
+// Load the pointer to the context of the task running on this core
+.macro LOAD_CURRENT_CONTEXT reg, tmp
+	mrs	\tmp, MPIDR_EL1
+	and	\tmp, \tmp, #0x03
+	adrp	\reg, CURRENT_CONTEXT
+	add	\reg, \reg, :lo12:CURRENT_CONTEXT
+	ldr	\reg, [\reg, \tmp, lsl #3]
+.endm
+
 .global _create_context
 _create_context:
 	// Integer Registers
@@ -62,7 +71,7 @@ _create_context:
 
 .global _start_multitasking
 _start_multitasking:
-	ldr	x0, CURRENT_CONTEXT
+	LOAD_CURRENT_CONTEXT x0, x1
 	b	_restore_context
 
 
@@ -220,7 +229,7 @@ _loop:
 	b	_loop
 
 
-// Handle an exception from EL0 to EL1 (save the user process context)
+// Handle an exception from EL0 or a kernel thread to EL1 (save the task's context)
 .macro HANDLE_CONTEXT_SWITCH handler
 	// Save two register values before using the registers for temporary values
 	sub	sp, sp, #16
@@ -240,7 +249,7 @@ _loop:
 	b.ne	_kernel_exception_fatal
 
 	// Save the user process's context (and subtract the values stored at the start from the stack)
-	ldr	x0, CURRENT_CONTEXT
+	LOAD_CURRENT_CONTEXT x0, x30
 	bl	_save_context
 	add	sp, sp, #16
 
@@ -252,7 +261,7 @@ _loop:
 	bl	\handler
 
 	// Restore the context and return the user process
-	ldr	x0, CURRENT_CONTEXT
+	LOAD_CURRENT_CONTEXT x0, x1
 	b	_restore_context
 .endm
 
@@ -380,12 +389,12 @@ _debug_print_number:
 .global _default_exceptions_table
 _default_exceptions_table:
 
-// Exceptions where SP_EL0 is the stack
+// Exceptions where SP_EL0 is the stack, which is only used by kernel threads
 .balign 0x80	// Synchronous
-	b	_kernel_exception_fatal
+	HANDLE_CONTEXT_SWITCH handle_kernel_thread_exception
 
 .balign 0x80	// IRQ
-	b	_kernel_exception_fatal
+	HANDLE_CONTEXT_SWITCH handle_kernel_thread_irq
 
 .balign 0x80	// Fast IRQ
 	b	_kernel_exception_fatal
@@ -1,9 +1,11 @@
 
 use core::mem;
 use core::slice;
+use alloc::vec::Vec;
 
 use crate::errors::KernelError;
-use crate::mm::pages::PagePool;
+use crate::mm::swap::{self, SwapSlot};
+use crate::mm::pages::{PagePool, OwnedPage};
 use crate::mm::{MemoryType, MemoryPermissions};
 
 use super::types::{PhysicalAddress, VirtualAddress};
@@ -16,6 +18,8 @@ const TT2_DESCRIPTOR_TABLE: u64 = 0b11;
 #[allow(dead_code)]
 const TT2_DESCRIPTOR_BLOCK: u64 = 0b01;
 const TT3_DESCRIPTOR_BLOCK: u64 = 0b11;
+// An invalid descriptor to the hardware, which stores the swap slot in place of the page address
+const TT3_DESCRIPTOR_SWAPPED: u64 = 0b10;
 
 const TT_ACCESS_FLAG: u64 = 1 << 10;
 const TT_READ_ONLY_FLAG: u64 = 0b11 << 6;
@@ -29,6 +33,7 @@ const TT_TYPE_MASK: u64 = 0b11;
 const TT_TABLE_MASK: u64 = 0x0000_ffff_ffff_f000;
 const TT_BLOCK_MASK: u64 = 0x0000_ffff_ffff_f000;
 const TT_PERMISSIONS_MASK: u64 = 0b11 << 6;
+const TT_ACCESS_CONTROL_MASK: u64 = TT_PERMISSIONS_MASK | TT_NO_EXECUTE_FLAG | TT_COPY_ON_WRITE_FLAG;
 
 const TL0_ADDR_BITS: usize = 9 + 9 + 9 + 12;
 
@@ -65,6 +70,32 @@ pub fn get_page_slice(page: PhysicalAddress) -> &'static mut [u8] {
     }
 }
 
+/// Flush all cached translations, after mappings have been removed from a table other than the current one
+pub fn invalidate_tlb() {
+    use core::arch::asm;
+
+    unsafe {
+        asm!(
+            "tlbi   VMALLE1IS",
+            "dsb    ISH",
+            "isb",
+        );
+    }
+}
+
+/// Flush the cached translations of only this core, after another core has changed the mappings
+pub fn invalidate_local_tlb() {
+    use core::arch::asm;
+
+    unsafe {
+        asm!(
+            "tlbi   VMALLE1",
+            "dsb    NSH",
+            "isb",
+        );
+    }
+}
+
 impl TranslationTable {
     pub fn initial_kernel_table() -> Self {
         use core::arch::asm;
@@ -79,13 +110,13 @@ impl TranslationTable {
         Self(ttbr)
     }
 
-    pub fn new_table(pages: &mut PagePool) -> Self {
-        let tl0 = allocate_table(pages);
-        Self(u64::from(tl0))
+    pub fn new_table(pages: &PagePool) -> Result<Self, KernelError> {
+        let tl0 = allocate_table(pages)?;
+        Ok(Self(u64::from(tl0)))
     }
 
 
-    pub fn map_existing_range(&mut self, access: MemoryPermissions, start: VirtualAddress, paddr: PhysicalAddress, len: usize, pages: &mut PagePool) -> Result<(), KernelError> {
+    pub fn map_existing_range(&mut self, access: MemoryPermissions, start: VirtualAddress, paddr: PhysicalAddress, len: usize, pages: &PagePool) -> Result<(), KernelError> {
         check_vaddr_and_usize(start, len)?;
 
         let end = start.add(len);
@@ -96,16 +127,16 @@ impl TranslationTable {
         mapper.visit_table(TL0_ADDR_BITS, self.as_slice_mut(), start, end)
     }
 
-    pub fn map_paged_range(&mut self, mtype: MemoryType, access: MemoryPermissions, start: VirtualAddress, len: usize, pages: &mut PagePool) -> Result<(), KernelError> {
+    pub fn map_paged_range(&mut self, mtype: MemoryType, access: MemoryPermissions, start: VirtualAddress, len: usize, pages: &PagePool) -> Result<(), KernelError> {
         check_vaddr_and_usize(start, len)?;
 
         let end = start.add(len);
 
-        let mut mapper = MapRange::new(pages, mtype, access, |pages, _, granuale_size| {
+        let mut mapper = MapRange::new(pages, mtype, access, |_, _, granuale_size| {
             if granuale_size != page_size() {
                 Ok(None) // Don't map granuales larger than a page
             } else if mtype == MemoryType::Allocated {
-                Ok(Some(pages.alloc_page_zeroed()))
+                Ok(Some(OwnedPage::alloc_zeroed()?.into_raw()))
             } else {
                 Ok(Some(PhysicalAddress::from(0)))
             }
@@ -113,7 +144,7 @@ impl TranslationTable {
         mapper.visit_table(TL0_ADDR_BITS, self.as_slice_mut(), start, end)
     }
 
-    pub fn duplicate_paged_range(&mut self, parent_table: &mut Self, access: MemoryPermissions, start: VirtualAddress, len: usize, pages: &mut PagePool) -> Result<(), KernelError> {
+    pub fn duplicate_paged_range(&mut self, parent_table: &mut Self, access: MemoryPermissions, start: VirtualAddress, len: usize, pages: &PagePool) -> Result<(), KernelError> {
         check_vaddr_and_usize(start, len)?;
 
         let end = start.add(len);
@@ -131,7 +162,7 @@ impl TranslationTable {
         mapper.visit_table(TL0_ADDR_BITS, parent_table.as_slice_mut(), self.as_slice_mut(), start, end)
     }
 
-    pub fn remap_range_copy_on_write(&mut self, parent_table: &mut Self, start: VirtualAddress, len: usize, pages: &mut PagePool) -> Result<(), KernelError> {
+    pub fn remap_range_copy_on_write(&mut self, parent_table: &mut Self, start: VirtualAddress, len: usize, pages: &PagePool) -> Result<(), KernelError> {
         check_vaddr_and_usize(start, len)?;
 
         let end = start.add(len);
@@ -152,14 +183,24 @@ impl TranslationTable {
         mapper.visit_table(TL0_ADDR_BITS, parent_table.as_slice_mut(), self.as_slice_mut(), start, end)
     }
 
-    pub fn unmap_range(&mut self, start: VirtualAddress, len: usize, pages: &mut PagePool) -> Result<(), KernelError> {
+    /// Unmap the range and return the table's references to the pages and tables that were removed
+    ///
+    /// Other cpus can still be using the old mappings, so the pages must be kept until the TLBs have been flushed
+    #[must_use = "the pages must be held until the TLBs have been flushed"]
+    pub fn unmap_range(&mut self, start: VirtualAddress, len: usize, pages: &PagePool) -> Result<Vec<OwnedPage>, KernelError> {
         check_vaddr_and_usize(start, len)?;
 
         let end = start.add(len);
-        let free_pages_fn = |pages: &mut PagePool, _, paddr| {
-            pages.free_page(paddr)
-        };
-        let mut visitor = UnmapRange::new(pages, free_pages_fn);
+        let mut visitor = UnmapRange::new(pages, start, end);
+        visitor.visit_table(TL0_ADDR_BITS, self.as_slice_mut(), start, end)?;
+        Ok(visitor.released)
+    }
+
+    pub fn change_permissions(&mut self, access: MemoryPermissions, start: VirtualAddress, len: usize, pages: &PagePool) -> Result<(), KernelError> {
+        check_vaddr_and_usize(start, len)?;
+
+        let end = start.add(len);
+        let mut visitor = ChangePermissions::new(pages, access, start, end);
         visitor.visit_table(TL0_ADDR_BITS, self.as_slice_mut(), start, end)
     }
 
@@ -188,19 +229,93 @@ impl TranslationTable {
         }
     }
 
-    pub fn update_page_addr(&mut self, vaddr: VirtualAddress, paddr: PhysicalAddress, pages: &mut PagePool) -> Result<(), KernelError> {
+    /// Map the given page at the address, and return the page that was previously mapped there, if any
+    pub fn update_page_addr(&mut self, vaddr: VirtualAddress, page: OwnedPage, pages: &PagePool) -> Result<Option<OwnedPage>, KernelError> {
         check_vaddr_and_usize(vaddr, page_size())?;
 
         let (descriptor, granuale_size) = lookup_level_mut(TL0_ADDR_BITS, self.as_slice_mut(), vaddr, Some(pages))?;
         if granuale_size == page_size() {
-            *descriptor &= !TT_BLOCK_MASK;
-            *descriptor |= (u64::from(paddr) & TT_BLOCK_MASK) | TT_ACCESS_FLAG;
-            Ok(())
+            let previous = if is_present_page(*descriptor) {
+                Some(unsafe { OwnedPage::from_raw(PhysicalAddress::from(*descriptor & TT_BLOCK_MASK)) })
+            } else {
+                None
+            };
+
+            // The descriptor type is also set, in case the page was swapped out
+            *descriptor &= !(TT_BLOCK_MASK | TT_TYPE_MASK);
+            *descriptor |= (u64::from(page.into_raw()) & TT_BLOCK_MASK) | TT_ACCESS_FLAG | TT3_DESCRIPTOR_BLOCK;
+            Ok(previous)
         } else {
             Err(KernelError::UnexpectedGranualeSize)
         }
     }
 
+    pub fn is_page_copy_on_write(&self, vaddr: VirtualAddress) -> bool {
+        match lookup_level(TL0_ADDR_BITS, self.as_slice(), vaddr.align_down(page_size())) {
+            Ok((descriptor, granuale_size)) => granuale_size == page_size() && is_present_page(*descriptor) && *descriptor & TT_COPY_ON_WRITE_FLAG != 0,
+            Err(_) => false,
+        }
+    }
+
+    /// Set the access flag of a mapped page, and return false if there's no page mapped at the address
+    pub fn set_page_accessed(&mut self, vaddr: VirtualAddress) -> Result<bool, KernelError> {
+        check_vaddr_and_usize(vaddr, page_size())?;
+
+        match lookup_level_mut(TL0_ADDR_BITS, self.as_slice_mut(), vaddr, None) {
+            Ok((descriptor, granuale_size)) if granuale_size == page_size() && is_present_page(*descriptor) => {
+                *descriptor |= TT_ACCESS_FLAG;
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    /// Clear the access flag of a mapped page, so the next access will fault, and return true if it was set
+    pub fn reset_page_accessed(&mut self, vaddr: VirtualAddress) -> Result<bool, KernelError> {
+        check_vaddr_and_usize(vaddr, page_size())?;
+
+        let (descriptor, granuale_size) = lookup_level_mut(TL0_ADDR_BITS, self.as_slice_mut(), vaddr, None)?;
+        if granuale_size != page_size() || !is_present_page(*descriptor) {
+            return Err(KernelError::AddressUnmapped);
+        }
+
+        let accessed = *descriptor & TT_ACCESS_FLAG != 0;
+        *descriptor &= !TT_ACCESS_FLAG;
+        Ok(accessed)
+    }
+
+    /// Replace a mapped page with the swap slot it was written to, and return the page that was mapped
+    pub fn set_page_swapped(&mut self, vaddr: VirtualAddress, slot: SwapSlot) -> Result<OwnedPage, KernelError> {
+        check_vaddr_and_usize(vaddr, page_size())?;
+
+        let (descriptor, granuale_size) = lookup_level_mut(TL0_ADDR_BITS, self.as_slice_mut(), vaddr, None)?;
+        if granuale_size != page_size() || !is_present_page(*descriptor) {
+            return Err(KernelError::AddressUnmapped);
+        }
+
+        let page = unsafe { OwnedPage::from_raw(PhysicalAddress::from(*descriptor & TT_BLOCK_MASK)) };
+        *descriptor = (*descriptor & !(TT_BLOCK_MASK | TT_TYPE_MASK | TT_ACCESS_FLAG)) | swap_slot_to_descriptor(slot) | TT3_DESCRIPTOR_SWAPPED;
+        Ok(page)
+    }
+
+    pub fn get_page_swapped(&mut self, vaddr: VirtualAddress) -> Option<SwapSlot> {
+        match lookup_level_mut(TL0_ADDR_BITS, self.as_slice_mut(), vaddr, None) {
+            Ok((descriptor, granuale_size)) if granuale_size == page_size() && *descriptor & TT_TYPE_MASK == TT3_DESCRIPTOR_SWAPPED => {
+                Some(descriptor_to_swap_slot(*descriptor))
+            },
+            _ => None,
+        }
+    }
+
+    pub fn find_swapped_pages(&mut self, start: VirtualAddress, len: usize) -> Result<Vec<(VirtualAddress, SwapSlot)>, KernelError> {
+        check_vaddr_and_usize(start, len)?;
+
+        let end = start.add(len);
+        let mut visitor = FindSwapped::new();
+        visitor.visit_table(TL0_ADDR_BITS, self.as_slice_mut(), start, end)?;
+        Ok(visitor.found)
+    }
+
     pub fn translate_addr(&self, vaddr: VirtualAddress) -> Result<PhysicalAddress, KernelError> {
         let (descriptor, granuale_size) = lookup_level(TL0_ADDR_BITS, self.as_slice(), vaddr.align_down(page_size()))?;
         Ok(PhysicalAddress::from(*descriptor & TT_BLOCK_MASK).add(vaddr.offset_from_align(granuale_size)))
@@ -249,6 +364,10 @@ trait TableVisitor {
     fn visit_empty(&mut self, _addr_bits: usize, _table: &mut [u64], _index: usize, _vaddr: VirtualAddress, _end: VirtualAddress) -> Result<(), KernelError> {
         Err(KernelError::AddressUnmapped)
     }
+
+    fn visit_swapped(&mut self, _addr_bits: usize, _table: &mut [u64], _index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
+        Ok(())
+    }
 }
 
 fn walk_table<V>(visitor: &mut V, addr_bits: usize, table: &mut [u64], mut vaddr: VirtualAddress, end: VirtualAddress) -> Result<(), KernelError>
@@ -268,13 +387,16 @@ where
             }
 
             visitor.visit_table_after(addr_bits, table, index, vaddr)?;
+        } else if is_swapped(addr_bits, table, index) {
+            visitor.visit_swapped(addr_bits, table, index, vaddr)?;
         } else if is_empty(table, index) {
             visitor.visit_empty(addr_bits, table, index, vaddr, end)?;
         } else {
             return Err(KernelError::CorruptTranslationTable);
         }
 
-        vaddr = vaddr.add(granuale_size);
+        // The start address might not be aligned to the granuale, but every following entry starts on a boundary
+        vaddr = vaddr.align_down(granuale_size).add(granuale_size);
         index += 1;
     }
     Ok(())
@@ -283,7 +405,7 @@ where
 
 /// Map 4K pages in a given range using a callback to allocate them
 struct MapRange<'a, F> {
-    pages: &'a mut PagePool,
+    pages: &'a PagePool,
     mtype: MemoryType,
     flags: u64,
     map_block: F,
@@ -291,9 +413,9 @@ struct MapRange<'a, F> {
 
 impl<'a, F> MapRange<'a, F>
 where
-    F: FnMut(&mut PagePool, VirtualAddress, usize) -> Result<Option<PhysicalAddress>, KernelError>,
+    F: FnMut(&PagePool, VirtualAddress, usize) -> Result<Option<PhysicalAddress>, KernelError>,
 {
-    fn new(pages: &'a mut PagePool, mtype: MemoryType, access: MemoryPermissions, map_block: F) -> Self {
+    fn new(pages: &'a PagePool, mtype: MemoryType, access: MemoryPermissions, map_block: F) -> Self {
         let flags = memory_type_flags(mtype) | memory_permissions_flags(access);
 
         Self {
@@ -307,12 +429,16 @@ where
 
 impl<'a, F> TableVisitor for MapRange<'a, F>
 where
-    F: FnMut(&mut PagePool, VirtualAddress, usize) -> Result<Option<PhysicalAddress>, KernelError>,
+    F: FnMut(&PagePool, VirtualAddress, usize) -> Result<Option<PhysicalAddress>, KernelError>,
 {
     fn visit_granuale(&mut self, _addr_bits: usize, _table: &mut [u64], _index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
         Err(KernelError::AddressAlreadyMapped)
     }
 
+    fn visit_swapped(&mut self, _addr_bits: usize, _table: &mut [u64], _index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
+        Err(KernelError::AddressAlreadyMapped)
+    }
+
     fn visit_empty(&mut self, addr_bits: usize, table: &mut [u64], index: usize, vaddr: VirtualAddress, end: VirtualAddress) -> Result<(), KernelError> {
         let granuale_size = 1 << addr_bits;
 
@@ -334,7 +460,7 @@ where
     }
 }
 
-fn ensure_table_entry(table: &mut [u64], index: usize, pages: &mut PagePool) -> Result<(), KernelError> {
+fn ensure_table_entry(table: &mut [u64], index: usize, pages: &PagePool) -> Result<(), KernelError> {
     let desc_type = descriptor_type(table, index);
 
     match desc_type {
@@ -344,7 +470,7 @@ fn ensure_table_entry(table: &mut [u64], index: usize, pages: &mut PagePool) ->
         },
 
         TT_DESCRIPTOR_EMPTY => {
-            let next_table = allocate_table(pages);
+            let next_table = allocate_table(pages)?;
             table[index] = (u64::from(next_table) & TT_TABLE_MASK) | TT2_DESCRIPTOR_TABLE;
             Ok(())
         },
@@ -359,40 +485,61 @@ fn ensure_table_entry(table: &mut [u64], index: usize, pages: &mut PagePool) ->
 
 
 /// Unmap all pages in the given address range
-struct UnmapRange<'a, F> {
-    pages: &'a mut PagePool,
-    unmap_block: F,
+struct UnmapRange<'a> {
+    pages: &'a PagePool,
+    start: VirtualAddress,
+    end: VirtualAddress,
+    released: Vec<OwnedPage>,
 }
 
-impl<'a, F> UnmapRange<'a, F>
-where
-    F: Fn(&mut PagePool, VirtualAddress, PhysicalAddress)
-{
-    fn new(pages: &'a mut PagePool, unmap_block: F) -> Self {
+impl<'a> UnmapRange<'a> {
+    fn new(pages: &'a PagePool, start: VirtualAddress, end: VirtualAddress) -> Self {
         Self {
             pages,
-            unmap_block,
+            start,
+            end,
+            released: Vec::new(),
         }
     }
 }
 
-impl<'a, F> TableVisitor for UnmapRange<'a, F>
-where
-    F: Fn(&mut PagePool, VirtualAddress, PhysicalAddress)
-{
-    fn visit_granuale(&mut self, _addr_bits: usize, table: &mut [u64], index: usize, vaddr: VirtualAddress) -> Result<(), KernelError> {
+impl<'a> TableVisitor for UnmapRange<'a> {
+    fn visit_granuale(&mut self, _addr_bits: usize, table: &mut [u64], index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
         let paddr = block_ptr(table, index);
         if usize::from(paddr) != 0 {
-            (self.unmap_block)(self.pages, vaddr, paddr);
+            // The table's reference to the page is taken over, and released once the caller has flushed the TLBs
+            self.released.push(unsafe { OwnedPage::from_raw(paddr) });
         }
         table[index] = 0;
         Ok(())
     }
 
+    fn visit_empty(&mut self, _addr_bits: usize, _table: &mut [u64], _index: usize, _vaddr: VirtualAddress, _end: VirtualAddress) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    fn visit_swapped(&mut self, _addr_bits: usize, table: &mut [u64], index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
+        swap::free_slot(descriptor_to_swap_slot(table[index]));
+        table[index] = 0;
+        Ok(())
+    }
+
+    fn visit_table_before(&mut self, addr_bits: usize, table: &mut [u64], index: usize, vaddr: VirtualAddress) -> Result<(), KernelError> {
+        if table_ref_mut(table, index).is_err() {
+            // A table that hasn't been allocated yet can be removed if it's entirely unmapped, but otherwise must be allocated to partially unmap it
+            if covers_granuale(addr_bits, vaddr, self.start, self.end) {
+                table[index] = 0;
+            } else {
+                expand_lazy_table(table, index, self.pages)?;
+            }
+        }
+        Ok(())
+    }
+
     fn visit_table_after(&mut self, _addr_bits: usize, table: &mut [u64], index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
         if let Ok(subtable) = table_ref_mut(table, index) {
             if table_is_empty(subtable) {
-                self.pages.free_page(table_ptr(table, index));
+                self.released.push(unsafe { OwnedPage::from_raw(table_ptr(table, index)) });
                 table[index] = 0;
             }
         }
@@ -401,6 +548,91 @@ where
 }
 
 
+/// Change the access permissions of all pages in the given address range
+struct ChangePermissions<'a> {
+    pages: &'a PagePool,
+    access: MemoryPermissions,
+    start: VirtualAddress,
+    end: VirtualAddress,
+}
+
+impl<'a> ChangePermissions<'a> {
+    fn new(pages: &'a PagePool, access: MemoryPermissions, start: VirtualAddress, end: VirtualAddress) -> Self {
+        Self {
+            pages,
+            access,
+            start,
+            end,
+        }
+    }
+}
+
+impl<'a> TableVisitor for ChangePermissions<'a> {
+    fn visit_granuale(&mut self, addr_bits: usize, table: &mut [u64], index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
+        if addr_bits != 12 {
+            return Err(KernelError::UnexpectedGranualeSize);
+        }
+
+        let writable = self.access == MemoryPermissions::ReadWrite || self.access == MemoryPermissions::ReadWriteExecute;
+        let flags = if writable && usize::from(block_ptr(table, index)) != 0 {
+            // A page that's already mapped could be shared with the page cache or another process, so it must be copied before it's written to
+            TT_COPY_ON_WRITE_FLAG | TT_READ_ONLY_FLAG | (memory_permissions_flags(self.access) & TT_NO_EXECUTE_FLAG)
+        } else {
+            memory_permissions_flags(self.access)
+        };
+
+        table[index] = (table[index] & !TT_ACCESS_CONTROL_MASK) | flags;
+        Ok(())
+    }
+
+    fn visit_swapped(&mut self, _addr_bits: usize, table: &mut [u64], index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
+        // A swapped page is always copied into a new page when it's swapped in, so it never needs to be copy-on-write
+        table[index] = (table[index] & !TT_ACCESS_CONTROL_MASK) | memory_permissions_flags(self.access);
+        Ok(())
+    }
+
+    fn visit_table_before(&mut self, addr_bits: usize, table: &mut [u64], index: usize, vaddr: VirtualAddress) -> Result<(), KernelError> {
+        if table_ref_mut(table, index).is_err() {
+            if covers_granuale(addr_bits, vaddr, self.start, self.end) {
+                table[index] = (table[index] & !TT_ACCESS_CONTROL_MASK) | memory_permissions_flags(self.access);
+            } else {
+                expand_lazy_table(table, index, self.pages)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Collect the addresses and swap slots of all swapped out pages in the given address range
+struct FindSwapped {
+    found: Vec<(VirtualAddress, SwapSlot)>,
+}
+
+impl FindSwapped {
+    fn new() -> Self {
+        Self {
+            found: Vec::new(),
+        }
+    }
+}
+
+impl TableVisitor for FindSwapped {
+    fn visit_granuale(&mut self, _addr_bits: usize, _table: &mut [u64], _index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    fn visit_empty(&mut self, _addr_bits: usize, _table: &mut [u64], _index: usize, _vaddr: VirtualAddress, _end: VirtualAddress) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    fn visit_swapped(&mut self, _addr_bits: usize, table: &mut [u64], index: usize, vaddr: VirtualAddress) -> Result<(), KernelError> {
+        self.found.push((vaddr, descriptor_to_swap_slot(table[index])));
+        Ok(())
+    }
+}
+
+
 /// Visitor to print the contents of the table for debugging and inspection
 struct PrintTable {}
 
@@ -452,6 +684,13 @@ trait TwoTableVisitor {
     fn visit_empty(&mut self, _addr_bits: usize, _parent_table: &mut [u64], _child_table: &mut [u64], _index: usize, _vaddr: VirtualAddress, _end: VirtualAddress) -> Result<(), KernelError> {
         Err(KernelError::AddressUnmapped)
     }
+
+    fn visit_swapped(&mut self, _addr_bits: usize, parent_table: &mut [u64], child_table: &mut [u64], index: usize, _vaddr: VirtualAddress) -> Result<(), KernelError> {
+        // Both tables refer to the same swap slot, and each will get its own copy of the page when it's swapped in
+        swap::ref_slot(descriptor_to_swap_slot(parent_table[index]));
+        child_table[index] = parent_table[index];
+        Ok(())
+    }
 }
 
 fn walk_two_tables<V>(visitor: &mut V, addr_bits: usize, parent_table: &mut [u64], child_table: &mut [u64], mut vaddr: VirtualAddress, end: VirtualAddress) -> Result<(), KernelError>
@@ -473,13 +712,16 @@ where
             }
 
             visitor.visit_table_after(addr_bits, parent_table, child_table, index, vaddr)?;
+        } else if is_swapped(addr_bits, parent_table, index) {
+            visitor.visit_swapped(addr_bits, parent_table, child_table, index, vaddr)?;
         } else if is_empty(parent_table, index) {
             visitor.visit_empty(addr_bits, parent_table, child_table, index, vaddr, end)?;
         } else {
             return Err(KernelError::CorruptTranslationTable);
         }
 
-        vaddr = vaddr.add(granuale_size);
+        // The start address might not be aligned to the granuale, but every following entry starts on a boundary
+        vaddr = vaddr.align_down(granuale_size).add(granuale_size);
         index += 1;
     }
     Ok(())
@@ -487,15 +729,15 @@ where
 
 /// Copy the mapping of 4K pages in a given range
 struct CopyRange<'a, F> {
-    pages: &'a mut PagePool,
+    pages: &'a PagePool,
     map_block: F,
 }
 
 impl<'a, F> CopyRange<'a, F>
 where
-    F: FnMut(&mut PagePool, VirtualAddress, &mut u64, usize) -> Result<Option<(PhysicalAddress, u64)>, KernelError>,
+    F: FnMut(&PagePool, VirtualAddress, &mut u64, usize) -> Result<Option<(PhysicalAddress, u64)>, KernelError>,
 {
-    fn new(pages: &'a mut PagePool, map_block: F) -> Self {
+    fn new(pages: &'a PagePool, map_block: F) -> Self {
         Self {
             pages,
             map_block,
@@ -505,7 +747,7 @@ where
 
 impl<'a, F> TwoTableVisitor for CopyRange<'a, F>
 where
-    F: FnMut(&mut PagePool, VirtualAddress, &mut u64, usize) -> Result<Option<(PhysicalAddress, u64)>, KernelError>,
+    F: FnMut(&PagePool, VirtualAddress, &mut u64, usize) -> Result<Option<(PhysicalAddress, u64)>, KernelError>,
 {
     fn visit_granuale(&mut self, addr_bits: usize, parent_table: &mut [u64], child_table: &mut [u64], index: usize, vaddr: VirtualAddress) -> Result<(), KernelError> {
         let granuale_size = 1 << addr_bits;
@@ -519,7 +761,7 @@ where
         if table_ref_mut(parent_table, index).is_ok() {
             ensure_table_entry(child_table, index, self.pages)?;
         } else {
-            child_table[index] = (parent_table[index] & (TT_PERMISSIONS_MASK | TT_COPY_ON_WRITE_FLAG)) | TT2_DESCRIPTOR_TABLE;
+            child_table[index] = (parent_table[index] & TT_ACCESS_CONTROL_MASK) | TT2_DESCRIPTOR_TABLE;
         }
         Ok(())
     }
@@ -539,23 +781,18 @@ fn lookup_level(addr_bits: usize, table: &[u64], vaddr: VirtualAddress) -> Resul
     }
 }
 
-fn lookup_level_mut<'a>(addr_bits: usize, table: &'a mut [u64], vaddr: VirtualAddress, mut pages: Option<&mut PagePool>) -> Result<(&'a mut u64, usize), KernelError> {
+fn lookup_level_mut<'a>(addr_bits: usize, table: &'a mut [u64], vaddr: VirtualAddress, mut pages: Option<&PagePool>) -> Result<(&'a mut u64, usize), KernelError> {
     let granuale_size = 1 << addr_bits;
 
     let index = table_index_from_vaddr(addr_bits, vaddr);
-    if is_block(addr_bits, table, index) {
+    if is_block(addr_bits, table, index) || is_swapped(addr_bits, table, index) {
         Ok((&mut table[index], granuale_size))
     } else if addr_bits == 12 {
         Err(KernelError::AddressUnmapped)
     } else {
         if table_ref_mut(table, index).is_err() {
             if let Some(pages) = &mut pages {
-                let next_table = allocate_table(pages);
-
-                table[index] |= u64::from(next_table) & TT_TABLE_MASK;
-
-                let parent_descriptor = table[index];
-                initialize_table(granuale_size, table_ref_mut(table, index)?, parent_descriptor)?;
+                expand_lazy_table(table, index, pages)?;
             } else {
                 return Err(KernelError::AddressUnmapped);
             }
@@ -564,14 +801,19 @@ fn lookup_level_mut<'a>(addr_bits: usize, table: &'a mut [u64], vaddr: VirtualAd
     }
 }
 
-fn initialize_table(granuale_size: usize, table: &mut [u64], parent_descriptor: u64) -> Result<(), KernelError> {
-    let dtype = if granuale_size == page_size() {
-        TT3_DESCRIPTOR_BLOCK
-    } else {
-        TT2_DESCRIPTOR_TABLE
-    };
+/// Allocate the table for an entry that was mapped without allocating its table, with the same permissions as the entry
+fn expand_lazy_table(table: &mut [u64], index: usize, pages: &PagePool) -> Result<(), KernelError> {
+    let next_table = allocate_table(pages)?;
 
-    let flags = (parent_descriptor & (TT_PERMISSIONS_MASK | TT_COPY_ON_WRITE_FLAG)) | dtype;
+    table[index] |= u64::from(next_table) & TT_TABLE_MASK;
+
+    let parent_descriptor = table[index];
+    initialize_table(table_ref_mut(table, index)?, parent_descriptor)
+}
+
+fn initialize_table(table: &mut [u64], parent_descriptor: u64) -> Result<(), KernelError> {
+    // NOTE TT3_DESCRIPTOR_BLOCK and TT2_DESCRIPTOR_TABLE have the same value, so either works for the entries of any level
+    let flags = (parent_descriptor & TT_ACCESS_CONTROL_MASK) | TT2_DESCRIPTOR_TABLE;
 
     for index in 0..table_entries() {
         table[index] = flags;
@@ -582,7 +824,7 @@ fn initialize_table(granuale_size: usize, table: &mut [u64], parent_descriptor:
 
 
 
-fn allocate_table(pages: &mut PagePool) -> PhysicalAddress {
+fn allocate_table(pages: &PagePool) -> Result<PhysicalAddress, KernelError> {
     pages.alloc_page_zeroed()
 }
 
@@ -643,10 +885,31 @@ fn is_table(addr_bits: usize, table: &[u64], index: usize) -> bool {
     addr_bits != 12 && descriptor_type(table, index) == TT2_DESCRIPTOR_TABLE
 }
 
+fn is_swapped(addr_bits: usize, table: &[u64], index: usize) -> bool {
+    addr_bits == 12 && descriptor_type(table, index) == TT3_DESCRIPTOR_SWAPPED
+}
+
+fn is_present_page(descriptor: u64) -> bool {
+    descriptor & TT_TYPE_MASK == TT3_DESCRIPTOR_BLOCK && descriptor & TT_BLOCK_MASK != 0
+}
+
+const fn swap_slot_to_descriptor(slot: SwapSlot) -> u64 {
+    ((slot as u64) << 12) & TT_BLOCK_MASK
+}
+
+const fn descriptor_to_swap_slot(descriptor: u64) -> SwapSlot {
+    ((descriptor & TT_BLOCK_MASK) >> 12) as SwapSlot
+}
+
 fn is_empty(table: &[u64], index: usize) -> bool {
     descriptor_type(table, index) == 0
 }
 
+fn covers_granuale(addr_bits: usize, vaddr: VirtualAddress, start: VirtualAddress, end: VirtualAddress) -> bool {
+    let granuale_start = vaddr.align_down(1 << addr_bits);
+    granuale_start >= start && granuale_start.add(1 << addr_bits) <= end
+}
+
 fn table_is_empty(table: &mut [u64]) -> bool {
     for index in 0..table_entries() {
         if descriptor_type(table, index) != TT_DESCRIPTOR_EMPTY {
@@ -527,6 +527,8 @@ _kernel_translation_table_l2:
 // Translation Table Level 1
 _kernel_translation_table_l1:
 .quad (_kernel_translation_table_l2 - 0xffff000000000000) + 3
+// Local Peripheral Address Space (the interrupt controller and mailboxes of each core)
+.quad 0x40000405
 //.quad 0x00000405
 //.quad 0x40000405
 //.quad 0x80000405
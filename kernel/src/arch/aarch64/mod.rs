@@ -1,6 +1,7 @@
 
 pub mod mmu;
 
+mod smp;
 mod types;
 mod context;
 mod exceptions;
@@ -10,6 +11,7 @@ core::arch::global_asm!(include_str!("mmu.s"));
 core::arch::global_asm!(include_str!("exceptions.s"));
 
 pub use self::types::{PhysicalAddress, VirtualAddress, KernelVirtualAddress};
-pub use self::context::{Context, cpu_id, start_multitasking, loop_forever};
+pub use self::context::{Context, cpu_id, read_counter, read_counter_frequency, start_multitasking, loop_forever, kernel_thread_request};
 pub use self::exceptions::{enable_irq, disable_irq, IrqFlags};
+pub use self::smp::{MAX_CPUS, start_secondary_core};
 
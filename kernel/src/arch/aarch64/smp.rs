@@ -0,0 +1,37 @@
+
+use core::ptr;
+use core::arch::asm;
+
+use super::types::{PhysicalAddress, VirtualAddress, KernelVirtualAddress};
+
+
+pub const MAX_CPUS: usize = 4;
+
+// The firmware holds the secondary cores in a loop until an address is written to their entry in this table
+const SPIN_TABLE_ADDR: u64 = 0xd8;
+
+extern "C" {
+    // These definitions are in aarch64/start.s
+    fn _non_boot_core();
+    static mut _secondary_stacks: [u64; MAX_CPUS];
+}
+
+
+/// Release a secondary core from the firmware, which will start running the kernel with the given stack
+pub fn start_secondary_core(cpu: usize, stack_top: VirtualAddress) {
+    unsafe {
+        _secondary_stacks[cpu] = u64::from(stack_top);
+
+        // The core starts with its MMU and caches disabled, so its entry address must be written out to memory
+        let entry = PhysicalAddress::from(KernelVirtualAddress::new(_non_boot_core as u64));
+        let spin_table: *mut u64 = PhysicalAddress::from(SPIN_TABLE_ADDR).to_kernel_addr().add(cpu * 8).as_mut();
+        ptr::write_volatile(spin_table, u64::from(entry));
+
+        asm!(
+            "dc     civac, {addr}",
+            "dsb    sy",
+            "sev",
+            addr = in(reg) spin_table,
+        );
+    }
+}
@@ -5,6 +5,7 @@
 .extern _kernel_translation_table_l0
 .extern __KERNEL_BSS_START
 .extern __KERNEL_BSS_END
+.extern __KERNEL_VIRTUAL_BASE_ADDR
 
 .section .text._start
 
@@ -34,15 +35,6 @@ _boot_core:
 
 	bl	_setup_common_system_registers
 
-	// Enable the 3 secondary (non-boot) cores
-	//adr	x15, _non_boot_core
-	//mov	x16, #0xe0
-	//str	x15, [x16]
-	//add	x16, x16, #8
-	//str	x15, [x16]
-	//add	x16, x16, #8
-	//str	x15, [x16]
-
 	// Patch the program counter to use the kernel address space
 	adr	x8, L_switch_to_kernel_vspace
 	ldr	x9, =__KERNEL_VIRTUAL_BASE_ADDR
@@ -72,19 +64,20 @@ _boot_core:
 	bl	boot_core_start
 
 
+/*
+ * Secondary Core Entry Point
+ *
+ * The secondary cores either start here along with the boot core, or are released
+ * by the boot core through the spin table once it has allocated their stacks
+ */
+.global _non_boot_core
 _non_boot_core:
 	// Print a '2' for debugging
-	ldr	x4, =0x3F201000
-	mov	w5, #0x32
-	strb	w5, [x4]
-
-	// Set up a default stack
-	adr	x0, #0x20000
-	mul	x0, x0, x1	// The Core ID
-	msr	SP_EL0, x0
-	msr	SP_EL1, x0
-	mov	sp, x0
+	//ldr	x4, =0x3F201000
+	//mov	w5, #0x32
+	//strb	w5, [x4]
 
+	// The stack isn't used until the boot core has allocated one, after the MMU is enabled
 	bl	_setup_common_system_registers
 
 	// Patch the program counter to use the kernel address space
@@ -94,11 +87,18 @@ _non_boot_core:
 	br	x8
 
     L_switch_to_kernel_vspace_non_boot:
-	// Reset the Stack Pointer to use a Kernel Space vaddress
+	// Wait for the boot core to allocate a stack for this core
 	mrs	x1, MPIDR_EL1
 	and	x1, x1, 0x03
-	adr	x0, #0x20000	// Stack Size
-	mul	x0, x0, x1	// The Core ID
+	adrp	x2, _secondary_stacks
+	add	x2, x2, :lo12:_secondary_stacks
+    L_wait_for_stack:
+	ldr	x0, [x2, x1, lsl #3]
+	cbnz	x0, L_set_stack
+	wfe
+	b	L_wait_for_stack
+
+    L_set_stack:
 	mov	sp, x0
 
 	// Set up Exceptions Table for EL1
@@ -213,3 +213,12 @@ _setup_common_system_registers:
 _INIT_STACK_POINTER:
 	.quad	__KERNEL_END_ADDR + 0x100000	// 1MB stack
 
+
+.section .data
+
+// The top of the stack for each secondary core, which is set by the boot core before it releases the core
+.balign 8
+.global _secondary_stacks
+_secondary_stacks:
+	.quad	0, 0, 0, 0
+
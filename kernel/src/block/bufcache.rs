@@ -1,29 +1,54 @@
 
-use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::cmp;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-use alloc::vec;
-use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+use alloc::collections::BTreeMap;
 
 use ruxpin_types::DeviceID;
 
 use crate::block;
+use crate::arch::mmu;
+use crate::mm::pages::OwnedPage;
 use crate::errors::KernelError;
-use crate::misc::cache::{Cache, CacheArc};
 use crate::sync::{Spinlock, SpinlockGuard};
 
 
 pub type BlockNum = u32;
+pub type PageNum = usize;
 
-pub struct Buf {
+// Unused pages are written back and removed when a device has more than this many cached
+const MAX_CACHED_PAGES: usize = 128;
+
+// Incremented on every access, so that the least recently used pages can be found
+static ACCESS_CLOCK: AtomicUsize = AtomicUsize::new(0);
+
+/// A page of data from a device, which holds all the blocks in that part of the device
+///
+/// The page can be mapped into memory directly, so that file data is only cached once
+pub struct BufPage {
+    device_id: DeviceID,
+    page_num: PageNum,
+    page: OwnedPage,
     dirty: AtomicBool,
-    block: Spinlock<Box<[u8]>>,
+    last_used: AtomicUsize,
+    block_size: usize,
+    // Each block has its own lock, so a block can be locked while another in the same page is being used
+    locks: Vec<Spinlock<()>>,
+}
+
+/// A block of data from a device, which refers to its part of a cached page
+pub struct Buf {
+    page: Arc<BufPage>,
+    index: usize,
 }
 
 pub struct BufCache {
     device_id: DeviceID,
     block_size: usize,
-    cache: Cache<BlockNum, Buf>,
+    pages: BTreeMap<PageNum, Arc<BufPage>>,
 }
 
 impl BufCache {
@@ -31,7 +56,7 @@ impl BufCache {
         Self {
             device_id,
             block_size,
-            cache: Cache::new(50),
+            pages: BTreeMap::new(),
         }
     }
 
@@ -40,89 +65,172 @@ impl BufCache {
     }
 
     pub fn set_block_size(&mut self, block_size: usize) -> Result<(), KernelError> {
-        self.cache.clear(|key, buf| {
-            store_buf(self.device_id, key, self.block_size, buf)
-        })?;
+        if block_size == 0 || block_size > mmu::page_size() || mmu::page_size() % block_size != 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        // The pages are divided into blocks when they're loaded, so they must all be reloaded
+        self.commit()?;
+        if self.pages.values().any(|page| Arc::strong_count(page) > 1) {
+            return Err(KernelError::OperationNotPermitted);
+        }
+        self.pages.clear();
         self.block_size = block_size;
         Ok(())
     }
 
     pub fn commit(&mut self) -> Result<(), KernelError> {
-        self.cache.commit(|key, buf| {
-            store_buf(self.device_id, key, self.block_size, buf)
-        })
+        for page in self.pages.values() {
+            page.commit()?;
+        }
+        Ok(())
     }
 
-    pub fn get_block(&mut self, block_num: BlockNum) -> Result<CacheArc<BlockNum, Buf>, KernelError> {
-        self.cache.get(block_num, || {
-            load_buf(self.device_id, block_num, self.block_size)
-        }, |key, buf| {
-            store_buf(self.device_id, key, self.block_size, buf)
+    pub fn get_block(&mut self, block_num: BlockNum) -> Result<Buf, KernelError> {
+        let blocks_per_page = mmu::page_size() / self.block_size;
+        let page = self.get_page(block_num as usize / blocks_per_page)?;
+        Ok(Buf {
+            page,
+            index: block_num as usize % blocks_per_page,
         })
     }
 
     pub fn write_block(&mut self, block_num: BlockNum) -> Result<(), KernelError> {
         let buf = self.get_block(block_num)?;
-        store_buf(self.device_id, block_num, self.block_size, &*buf)
+        buf.page.commit()
     }
-}
 
-fn load_buf(device_id: DeviceID, block_num: BlockNum, block_size: usize) -> Result<Buf, KernelError> {
-    let entry = Buf::new(block_size);
-    let nbytes = block::raw_read(device_id, &mut *entry.block.lock(), (block_num as usize * block_size) as u64)?;
-    if nbytes != block_size {
-        return Err(KernelError::IOError);
+    pub fn get_page(&mut self, page_num: PageNum) -> Result<Arc<BufPage>, KernelError> {
+        if let Some(page) = self.pages.get(&page_num) {
+            page.last_used.store(next_access_time(), Ordering::Relaxed);
+            return Ok(page.clone());
+        }
+
+        if self.pages.len() >= MAX_CACHED_PAGES {
+            self.evict_unused(self.pages.len() + 1 - MAX_CACHED_PAGES)?;
+        }
+
+        let page = Arc::new(BufPage::load(self.device_id, page_num, self.block_size)?);
+        self.pages.insert(page_num, page.clone());
+        Ok(page)
     }
 
-    // TODO this is for debugging
-    //crate::printkln!("buf {}", num);
-    //unsafe { crate::printk::printk_dump((&**entry.block.lock()).as_ptr(), self.block_size); }
+    /// Free up to the given number of clean pages that aren't in use, without writing anything to the device
+    pub fn reclaim(&mut self, count: usize) -> usize {
+        let candidates = self.find_unused(|page| !page.dirty.load(Ordering::Acquire));
+        let mut freed = 0;
+        for page_num in candidates.into_iter().take(count) {
+            self.pages.remove(&page_num);
+            freed += 1;
+        }
+        freed
+    }
 
-    Ok(entry)
+    fn evict_unused(&mut self, count: usize) -> Result<(), KernelError> {
+        let candidates = self.find_unused(|_| true);
+        for page_num in candidates.into_iter().take(count) {
+            // The page stays in the cache if it can't be written, so that it's still dirty and can be retried later
+            if let Some(page) = self.pages.get(&page_num) {
+                page.commit()?;
+                self.pages.remove(&page_num);
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the pages that aren't referenced outside the cache, or mapped into memory, with the least recently used first
+    fn find_unused<F>(&self, filter: F) -> Vec<PageNum>
+    where
+        F: Fn(&BufPage) -> bool
+    {
+        let mut unused: Vec<(usize, PageNum)> = self.pages.iter()
+            .filter(|(_, page)| Arc::strong_count(page) == 1 && page.page.ref_count() == 1 && filter(page))
+            .map(|(page_num, page)| (page.last_used.load(Ordering::Relaxed), *page_num))
+            .collect();
+        unused.sort_unstable();
+        unused.into_iter().map(|(_, page_num)| page_num).collect()
+    }
 }
 
-fn store_buf(device_id: DeviceID, block_num: BlockNum, block_size: usize, buf: &Buf) -> Result<(), KernelError> {
-    if buf.dirty.load(Ordering::Acquire) {
-        let nbytes = block::raw_write(device_id, &*buf.block.lock(), (block_num as usize * block_size) as u64)?;
-        if nbytes != block_size {
+fn next_access_time() -> usize {
+    ACCESS_CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
+
+impl BufPage {
+    fn load(device_id: DeviceID, page_num: PageNum, block_size: usize) -> Result<Self, KernelError> {
+        let page = OwnedPage::alloc_zeroed()?;
+        let buffer = mmu::get_page_slice(page.addr());
+        let entry = Self {
+            device_id,
+            page_num,
+            page,
+            dirty: AtomicBool::new(false),
+            last_used: AtomicUsize::new(next_access_time()),
+            block_size,
+            locks: (0..mmu::page_size() / block_size).map(|_| Spinlock::new(())).collect(),
+        };
+
+        let length = entry.length_on_device()?;
+        if length == 0 {
+            return Err(KernelError::IOError);
+        }
+        let nbytes = block::raw_read(device_id, &mut buffer[..length], entry.offset_on_device())?;
+        if nbytes != length {
             return Err(KernelError::IOError);
         }
-        buf.dirty.store(false, Ordering::Release);
+        Ok(entry)
     }
-    Ok(())
-}
 
+    pub fn page(&self) -> &OwnedPage {
+        &self.page
+    }
 
-impl Buf {
-    pub fn new(block_size: usize) -> Self {
-        Self {
-            dirty: AtomicBool::new(false),
-            block: Spinlock::new(vec![0; block_size].into_boxed_slice()),
+    pub fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Write the page back to the device if it's been modified
+    pub fn commit(&self) -> Result<(), KernelError> {
+        if self.dirty.swap(false, Ordering::AcqRel) {
+            let result = self.write_page();
+            if result.is_err() {
+                self.dirty.store(true, Ordering::Release);
+            }
+            return result;
         }
+        Ok(())
     }
 
-    pub fn lock(&self) -> BufGuard<'_> {
-        BufGuard {
-            guard: self.block.lock(),
+    fn write_page(&self) -> Result<(), KernelError> {
+        let length = self.length_on_device()?;
+        let nbytes = block::raw_write(self.device_id, &mmu::get_page_slice(self.page.addr())[..length], self.offset_on_device())?;
+        if nbytes != length {
+            return Err(KernelError::IOError);
         }
+        Ok(())
     }
 
-    pub fn lock_mut(&self) -> SpinlockGuard<'_, Box<[u8]>> {
-        self.dirty.store(true, Ordering::Release);
-        self.block.lock()
+    fn offset_on_device(&self) -> u64 {
+        (self.page_num * mmu::page_size()) as u64
     }
 
-    /*
-    pub fn lock_mut(&self) -> BufGuardMut<'_> {
-        BufGuardMut {
-            dirty: &self.dirty,
-            guard: self.block.lock(),
+    // The last page of the device can be only partly used
+    fn length_on_device(&self) -> Result<usize, KernelError> {
+        let device_size = block::get_size(self.device_id)?;
+        if device_size == 0 {
+            return Ok(mmu::page_size());
         }
+        Ok(cmp::min(mmu::page_size() as u64, device_size.saturating_sub(self.offset_on_device())) as usize)
+    }
+
+    fn block_slice(&self, index: usize) -> &mut [u8] {
+        let start = index * self.block_size;
+        &mut mmu::get_page_slice(self.page.addr())[start..start + self.block_size]
     }
-    */
 }
 
-impl Drop for Buf {
+impl Drop for BufPage {
     fn drop(&mut self) {
         if self.dirty.load(Ordering::Acquire) {
             panic!("bufcache: buf was not written back after use");
@@ -131,36 +239,53 @@ impl Drop for Buf {
 }
 
 
+impl Buf {
+    pub fn lock(&self) -> BufGuard<'_> {
+        BufGuard {
+            _guard: self.page.locks[self.index].lock(),
+            data: self.page.block_slice(self.index),
+        }
+    }
+
+    pub fn lock_mut(&self) -> BufGuardMut<'_> {
+        self.page.mark_dirty();
+        BufGuardMut {
+            _guard: self.page.locks[self.index].lock(),
+            data: self.page.block_slice(self.index),
+        }
+    }
+}
+
+
 pub struct BufGuard<'a> {
-    guard: SpinlockGuard<'a, Box<[u8]>>,
+    _guard: SpinlockGuard<'a, ()>,
+    data: &'a [u8],
 }
 
 impl<'a> Deref for BufGuard<'a> {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        &*self.guard
+        self.data
     }
 }
 
-/*
 pub struct BufGuardMut<'a> {
-    dirty: &'a AtomicBool,
-    guard: SpinlockGuard<'a, Box<[u8]>>,
+    _guard: SpinlockGuard<'a, ()>,
+    data: &'a mut [u8],
 }
 
 impl<'a> Deref for BufGuardMut<'a> {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        &*self.guard
+        self.data
     }
 }
 
 impl<'a> DerefMut for BufGuardMut<'a> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.dirty.store(true, Ordering::Release);
-        &mut *self.guard
+        self.data
     }
 }
-*/
+
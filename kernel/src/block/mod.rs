@@ -1,4 +1,5 @@
 
+use core::cmp;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
 use alloc::boxed::Box;
@@ -7,12 +8,11 @@ use ruxpin_types::{OpenFlags, DeviceID, DriverID, MinorDeviceID};
 
 use crate::sync::Spinlock;
 use crate::errors::KernelError;
-use crate::misc::cache::CacheArc;
 
 pub mod bufcache;
 pub mod partition;
 
-pub use self::bufcache::{BlockNum, Buf, BufCache};
+pub use self::bufcache::{BlockNum, PageNum, Buf, BufPage, BufCache};
 
 
 pub trait BlockOperations: Sync + Send {
@@ -20,6 +20,7 @@ pub trait BlockOperations: Sync + Send {
     fn close(&mut self) -> Result<(), KernelError>;
     fn read(&mut self, buffer: &mut [u8], offset: u64) -> Result<usize, KernelError>;
     fn write(&mut self, buffer: &[u8], offset: u64) -> Result<usize, KernelError>;
+    fn size(&self) -> u64;
     //int (*ioctl)(devminor_t minor, unsigned int request, void *argp, uid_t uid);
     //int (*poll)(devminor_t minor, int events);
     //offset_t (*seek)(devminor_t minor, offset_t position, int whence, offset_t offset);
@@ -56,7 +57,7 @@ pub fn register_block_device(driver_id: DriverID, dev: Box<dyn BlockOperations>)
 pub fn lookup_device(name: &str) -> Result<DeviceID, KernelError> {
     let drivers_list = BLOCK_DRIVERS.lock();
     for (driver_id, driver) in drivers_list.iter().enumerate() {
-        if driver.prefix == &name[..driver.prefix.len()] {
+        if name.starts_with(driver.prefix) {
             let subdevice_id = name[driver.prefix.len()..].parse::<MinorDeviceID>().map_err(|_| KernelError::NoSuchDevice)?;
             if (subdevice_id as usize) < driver.devices.len() {
                 return Ok(DeviceID(driver_id as DriverID, subdevice_id));
@@ -91,13 +92,27 @@ pub fn write(device_id: DeviceID, buffer: &[u8], offset: u64) -> Result<usize, K
     result
 }
 
+/// Returns the size of the device in bytes, or 0 if the size isn't known
+pub fn get_size(device_id: DeviceID) -> Result<u64, KernelError> {
+    let device = get_device(device_id)?;
+    let size = device.dev.lock().size();
+    Ok(size)
+}
+
 
-pub fn get_buf<'a>(device_id: DeviceID, block_num: BlockNum) -> Result<CacheArc<BlockNum, Buf>, KernelError> {
+pub fn get_buf<'a>(device_id: DeviceID, block_num: BlockNum) -> Result<Buf, KernelError> {
     let device = get_device(device_id)?;
     let buf = device.cache.lock().get_block(block_num)?;
     Ok(buf)
 }
 
+/// Returns the cached page of the device at the given offset in pages, which holds all the blocks in that page
+pub fn get_page(device_id: DeviceID, page_num: PageNum) -> Result<Arc<BufPage>, KernelError> {
+    let device = get_device(device_id)?;
+    let page = device.cache.lock().get_page(page_num)?;
+    Ok(page)
+}
+
 pub fn commit_buf(device_id: DeviceID, block_num: BlockNum) -> Result<(), KernelError> {
     let device = get_device(device_id)?;
     device.cache.lock().write_block(block_num)?;
@@ -124,6 +139,25 @@ pub fn set_buf_size(device_id: DeviceID, size: usize) -> Result<(), KernelError>
 
 
 
+/// Free up to the given number of clean cached pages that aren't in use, skipping any device that's busy
+pub fn reclaim_bufs(count: usize) -> usize {
+    let devices: Vec<BlockDeviceEntry> = match BLOCK_DRIVERS.try_lock_once() {
+        Some(drivers_list) => drivers_list.iter().flat_map(|driver| driver.devices.iter().cloned()).collect(),
+        None => return 0,
+    };
+
+    let mut freed = 0;
+    for device in devices {
+        if freed >= count {
+            break;
+        }
+        if let Some(mut cache) = device.cache.try_lock_once() {
+            freed += cache.reclaim(count - freed);
+        }
+    }
+    freed
+}
+
 fn get_device(device_id: DeviceID) -> Result<BlockDeviceEntry, KernelError> {
     let DeviceID(driver_id, subdevice_id) = device_id;
     let mut drivers_list = BLOCK_DRIVERS.lock();
@@ -162,29 +196,27 @@ pub(super) fn buffered_read(cache: &mut BufCache, buffer: &mut [u8], offset: u64
     let block_size = cache.block_size() as u64;
 
     let mut buffer_start = 0;
-    let mut buffer_remain = buffer.len() as u64;
     let mut block_num = (offset / block_size) as BlockNum;
-    let mut block_start = offset % block_size;
-    while buffer_remain > 0 {
-        let block_end = if buffer_remain > block_size - block_start { block_size } else { buffer_remain };
+    let mut block_start = (offset % block_size) as usize;
+    while buffer_start < buffer.len() {
+        let length = cmp::min(block_size as usize - block_start, buffer.len() - buffer_start);
         let entry = cache.get_block(block_num)?;
-        buffer[buffer_start..].copy_from_slice(&entry.lock()[block_start as usize..block_end as usize]);
+        buffer[buffer_start..buffer_start + length].copy_from_slice(&entry.lock()[block_start..block_start + length]);
 
-        buffer_remain = buffer_remain.saturating_sub(block_size - block_start);
-        buffer_start += (block_size - block_start) as usize;
+        buffer_start += length;
         block_num += 1;
         block_start = 0;
     }
-    Ok(0)
+    Ok(buffer_start)
 }
 
-pub(super) fn raw_read(device_id: DeviceID, buffer: &mut [u8], offset: u64) -> Result<usize, KernelError> {
+pub fn raw_read(device_id: DeviceID, buffer: &mut [u8], offset: u64) -> Result<usize, KernelError> {
     let device = get_device(device_id)?;
     let result = device.dev.lock().read(buffer, offset);
     result
 }
 
-pub(super) fn raw_write(device_id: DeviceID, buffer: &[u8], offset: u64) -> Result<usize, KernelError> {
+pub fn raw_write(device_id: DeviceID, buffer: &[u8], offset: u64) -> Result<usize, KernelError> {
     let device = get_device(device_id)?;
     let result = device.dev.lock().write(buffer, offset);
     result
@@ -15,6 +15,7 @@ pub enum KernelError {
     NoSegmentFound,
     MemoryPermissionDenied,
     LockTimeout,
+    StackOverflow,
 
     // Device Errors
     NoSuchDevice,
@@ -41,6 +42,7 @@ pub enum KernelError {
     AccessDenied,
     DirectoryNotEmpty,
     FileExists,
+    BrokenPipe,
 
     // Task Errors
     NoSuchTask,
@@ -49,6 +51,9 @@ pub enum KernelError {
     InvalidSegmentType,
     BadSystemCall,
     NotExited,
+    Interrupted,
+    NoChildren,
+    TryAgain,
 
     SuspendProcess,
 }
@@ -65,6 +70,7 @@ impl From<KernelError> for ApiError {
             KernelError::NoSegmentFound                 => ApiError::NoSegmentFound,
             KernelError::MemoryPermissionDenied         => ApiError::MemoryPermissionDenied,
             KernelError::LockTimeout                    => ApiError::LockTimeout,
+            KernelError::StackOverflow                  => ApiError::StackOverflow,
 
             KernelError::NoSuchDevice                   => ApiError::NoSuchDevice,
             KernelError::OperationNotPermitted          => ApiError::OperationNotPermitted,
@@ -89,6 +95,7 @@ impl From<KernelError> for ApiError {
             KernelError::AccessDenied                   => ApiError::AccessDenied,
             KernelError::DirectoryNotEmpty              => ApiError::DirectoryNotEmpty,
             KernelError::FileExists                     => ApiError::FileExists,
+            KernelError::BrokenPipe                     => ApiError::BrokenPipe,
 
             KernelError::NoSuchTask                     => ApiError::NoSuchTask,
             KernelError::NotExecutable                  => ApiError::NotExecutable,
@@ -96,6 +103,9 @@ impl From<KernelError> for ApiError {
             KernelError::InvalidSegmentType             => ApiError::InvalidSegmentType,
             KernelError::BadSystemCall                  => ApiError::BadSystemCall,
             KernelError::NotExited                      => ApiError::NotExited,
+            KernelError::Interrupted                    => ApiError::Interrupted,
+            KernelError::NoChildren                     => ApiError::NoChildren,
+            KernelError::TryAgain                       => ApiError::TryAgain,
 
             _ => ApiError::UnknownError,
         }
@@ -40,6 +40,10 @@ impl FileDescriptors {
         self.cwd.clone()
     }
 
+    pub fn set_cwd(&mut self, cwd: Vnode) {
+        self.cwd = Some(cwd);
+    }
+
     pub fn get_file(&self, file_num: FileDesc) -> Result<File, KernelError> {
         self.list.get(file_num.as_usize() as usize).map(|file| file.clone()).flatten().ok_or(KernelError::BadFileNumber)
     }
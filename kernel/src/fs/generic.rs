@@ -71,6 +71,7 @@ impl VnodeOperations for GenericDirectoryVnode {
         let vnode = create_generic_vnode(self.self_vnode.clone(), access, uid, gid);
         let entry = GenericDirEntry::new(filename, vnode.clone());
         self.contents.push(entry);
+        self.attrs.update_modified();
         Ok(vnode)
     }
 
@@ -100,10 +101,12 @@ impl VnodeOperations for GenericDirectoryVnode {
     //    Err(KernelError::OperationNotPermitted)
     //}
 
-    fn unlink(&mut self, _target: Vnode, filename: &str) -> Result<(), KernelError> {
+    fn unlink(&mut self, target: Vnode, filename: &str) -> Result<(), KernelError> {
         for (i, entry) in self.contents.iter().enumerate() {
             if entry.name == filename {
                 self.contents.remove(i);
+                self.attrs.update_modified();
+                target.lock().attributes_mut(&mut |attrs| attrs.update_changed())?;
                 return Ok(());
             }
         }
@@ -176,6 +179,7 @@ impl GenericFileVnode {
 impl VnodeOperations for GenericFileVnode {
     fn truncate(&mut self) -> Result<(), KernelError> {
         self.contents.clear();
+        self.attrs.update_modified();
         Ok(())
     }
 
@@ -219,18 +223,24 @@ impl VnodeOperations for GenericFileVnode {
             self.contents[file.position] = *byte;
             file.position += 1;
         }
+        self.attrs.update_modified();
         Ok(file.position - start)
     }
 
-    fn seek(&mut self, file: &mut FilePointer, offset: usize, whence: Seek) -> Result<usize, KernelError> {
+    fn seek(&mut self, file: &mut FilePointer, offset: isize, whence: Seek) -> Result<usize, KernelError> {
         let position = match whence {
             Seek::FromStart => offset,
-            Seek::FromCurrent => file.position + offset,
-            Seek::FromEnd => self.attrs.size + offset,
+            Seek::FromCurrent => file.position as isize + offset,
+            Seek::FromEnd => self.contents.len() as isize + offset,
         };
 
-        if position >= self.attrs.size {
-            file.position = self.attrs.size;
+        if position < 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        let position = position as usize;
+        if position >= self.contents.len() {
+            file.position = self.contents.len();
         } else {
             file.position = position;
         }
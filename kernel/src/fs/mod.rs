@@ -1,5 +1,7 @@
 
 pub mod generic;
+pub mod pipe;
+pub mod writeback;
 
 mod vfs;
 mod types;
@@ -7,10 +9,11 @@ mod filedesc;
 
 pub use vfs::{
     initialize, register_filesystem, mount, sync_all, for_each_mount,
-    link, unlink, rename, access, open,
-    read, write, seek, readdir,
+    link, unlink, rename, access, stat, change_directory, get_path, open, reopen,
+    read, write, seek, fstat, readdir, get_device_page,
     make_directory, is_directory, is_directory_empty,
 };
 pub use types::{Filesystem, MountOperations, VnodeOperations, FileAttributes, Mount, Vnode, WeakVnode, FilePointer, File, new_vnode};
 pub use filedesc::{FileDescriptors, SharableFileDescriptors};
+pub use pipe::pipe;
 
@@ -0,0 +1,197 @@
+
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use ruxpin_types::{OpenFlags, FileAccess, Signal};
+
+use crate::tasklets;
+use crate::sync::Spinlock;
+use crate::proc::scheduler;
+use crate::proc::wait::WaitQueue;
+use crate::errors::KernelError;
+
+use super::types::{new_vnode, VnodeOperations, FileAttributes, FilePointer, File};
+
+
+const PIPE_BUFFER_SIZE: usize = 4096;
+
+struct PipeBuffer {
+    buffer: Vec<u8>,
+    in_pos: usize,
+    out_pos: usize,
+    used: usize,
+    reader_open: bool,
+    writer_open: bool,
+    readers: WaitQueue,
+    writers: WaitQueue,
+}
+
+type SharablePipeBuffer = Arc<Spinlock<PipeBuffer>>;
+
+pub struct PipeReaderVnode {
+    attrs: FileAttributes,
+    pipe: SharablePipeBuffer,
+}
+
+pub struct PipeWriterVnode {
+    attrs: FileAttributes,
+    pipe: SharablePipeBuffer,
+}
+
+
+pub fn pipe() -> Result<(File, File), KernelError> {
+    let pipe = Arc::new(Spinlock::new(PipeBuffer::new()));
+
+    let reader = new_vnode(PipeReaderVnode::new(pipe.clone()));
+    let writer = new_vnode(PipeWriterVnode::new(pipe));
+
+    let read_file = Arc::new(Spinlock::new(FilePointer::new(reader)));
+    let write_file = Arc::new(Spinlock::new(FilePointer::new(writer)));
+    Ok((read_file, write_file))
+}
+
+fn pipe_attributes() -> FileAttributes {
+    FileAttributes::new(FileAccess::Fifo.plus(FileAccess::OwnerRead).plus(FileAccess::OwnerWrite), 0, 0)
+}
+
+fn wake_readers(pipe: &SharablePipeBuffer) {
+    let pipe = pipe.clone();
+    // The wake up is deferred to a tasklet because the close path can be run by the scheduler while exiting a process
+    tasklets::schedule(Box::new(move || {
+        pipe.try_lock()?.readers.wake_all();
+        Ok(())
+    }));
+}
+
+fn wake_writers(pipe: &SharablePipeBuffer) {
+    let pipe = pipe.clone();
+    tasklets::schedule(Box::new(move || {
+        pipe.try_lock()?.writers.wake_all();
+        Ok(())
+    }));
+}
+
+
+impl PipeBuffer {
+    fn new() -> Self {
+        Self {
+            buffer: vec![0; PIPE_BUFFER_SIZE],
+            in_pos: 0,
+            out_pos: 0,
+            used: 0,
+            reader_open: true,
+            writer_open: true,
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+        }
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> usize {
+        let mut nbytes = 0;
+        while nbytes < buffer.len() && self.used > 0 {
+            buffer[nbytes] = self.buffer[self.out_pos];
+            self.out_pos = (self.out_pos + 1) % PIPE_BUFFER_SIZE;
+            self.used -= 1;
+            nbytes += 1;
+        }
+        nbytes
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> usize {
+        let mut nbytes = 0;
+        while nbytes < buffer.len() && self.used < PIPE_BUFFER_SIZE {
+            self.buffer[self.in_pos] = buffer[nbytes];
+            self.in_pos = (self.in_pos + 1) % PIPE_BUFFER_SIZE;
+            self.used += 1;
+            nbytes += 1;
+        }
+        nbytes
+    }
+}
+
+impl PipeReaderVnode {
+    fn new(pipe: SharablePipeBuffer) -> Self {
+        Self {
+            attrs: pipe_attributes(),
+            pipe,
+        }
+    }
+}
+
+impl PipeWriterVnode {
+    fn new(pipe: SharablePipeBuffer) -> Self {
+        Self {
+            attrs: pipe_attributes(),
+            pipe,
+        }
+    }
+}
+
+impl VnodeOperations for PipeReaderVnode {
+    fn attributes<'a>(&'a mut self) -> Result<&'a FileAttributes, KernelError> {
+        Ok(&mut self.attrs)
+    }
+
+    fn open(&mut self, _file: &mut FilePointer, _flags: OpenFlags) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    fn close(&mut self, _file: &mut FilePointer) -> Result<(), KernelError> {
+        self.pipe.try_lock()?.reader_open = false;
+        wake_writers(&self.pipe);
+        Ok(())
+    }
+
+    fn read(&mut self, _file: &mut FilePointer, buffer: &mut [u8]) -> Result<usize, KernelError> {
+        let mut pipe = self.pipe.try_lock()?;
+
+        if pipe.used == 0 {
+            // An empty pipe with no writer is the end of the file, otherwise block until data is written
+            if pipe.writer_open {
+                pipe.readers.wait();
+            }
+            return Ok(0);
+        }
+
+        let nbytes = pipe.read(buffer);
+        wake_writers(&self.pipe);
+        Ok(nbytes)
+    }
+}
+
+impl VnodeOperations for PipeWriterVnode {
+    fn attributes<'a>(&'a mut self) -> Result<&'a FileAttributes, KernelError> {
+        Ok(&mut self.attrs)
+    }
+
+    fn open(&mut self, _file: &mut FilePointer, _flags: OpenFlags) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    fn close(&mut self, _file: &mut FilePointer) -> Result<(), KernelError> {
+        self.pipe.try_lock()?.writer_open = false;
+        wake_readers(&self.pipe);
+        Ok(())
+    }
+
+    fn write(&mut self, _file: &mut FilePointer, buffer: &[u8]) -> Result<usize, KernelError> {
+        let mut pipe = self.pipe.try_lock()?;
+
+        if !pipe.reader_open {
+            scheduler::raise_signal(scheduler::get_current(), Signal::BrokenPipe);
+            return Err(KernelError::BrokenPipe);
+        }
+
+        if pipe.used == PIPE_BUFFER_SIZE && buffer.len() > 0 {
+            // Block until a reader has made some room in the buffer
+            pipe.writers.wait();
+            return Ok(0);
+        }
+
+        let nbytes = pipe.write(buffer);
+        wake_readers(&self.pipe);
+        Ok(nbytes)
+    }
+}
@@ -1,9 +1,11 @@
 
 use alloc::sync::{Arc, Weak};
 
-use ruxpin_types::{OpenFlags, FileAccess, Seek, UserID, GroupID, InodeNum, DeviceID, Timestamp, DirEntry};
+use ruxpin_types::{OpenFlags, FileAccess, Seek, UserID, GroupID, InodeNum, DeviceID, Timestamp, DirEntry, Stat};
 
+use crate::time;
 use crate::sync::Spinlock;
+use crate::block::PageNum;
 use crate::errors::KernelError;
 
 
@@ -84,7 +86,7 @@ pub trait VnodeOperations: Sync + Send {
         Err(KernelError::OperationNotPermitted)
     }
 
-    fn seek(&mut self, _file: &mut FilePointer, _offset: usize, _whence: Seek) -> Result<usize, KernelError> {
+    fn seek(&mut self, _file: &mut FilePointer, _offset: isize, _whence: Seek) -> Result<usize, KernelError> {
         Err(KernelError::OperationNotPermitted)
     }
 
@@ -92,6 +94,11 @@ pub trait VnodeOperations: Sync + Send {
         Err(KernelError::OperationNotPermitted)
     }
 
+    /// Returns the device and page number where the page of the file at the given offset is stored, if the whole page is stored there
+    fn get_device_page(&mut self, _offset: usize) -> Result<Option<(DeviceID, PageNum)>, KernelError> {
+        Ok(None)
+    }
+
     //int (*ioctl)(struct vfile *file, unsigned int request, void *argp, uid_t uid);
     //int (*poll)(struct vfile *file, int events);
 }
@@ -144,6 +151,7 @@ impl FilePointer {
 
 impl FileAttributes {
     pub fn new(access: FileAccess, uid: UserID, gid: GroupID) -> Self {
+        let now = time::get_timestamp();
         Self {
             access,
             nlinks: 1,
@@ -153,11 +161,22 @@ impl FileAttributes {
             inode: 0,
             size: 0,
 
-            atime: Timestamp(0),
-            ctime: Timestamp(0),
-            mtime: Timestamp(0),
+            atime: now,
+            ctime: now,
+            mtime: now,
         }
     }
+
+    /// Record that the contents of the file have changed, which also counts as a change to the file
+    pub fn update_modified(&mut self) {
+        self.mtime = time::get_timestamp();
+        self.ctime = self.mtime;
+    }
+
+    /// Record that the attributes of the file, such as the number of links, have changed
+    pub fn update_changed(&mut self) {
+        self.ctime = time::get_timestamp();
+    }
 }
 
 impl Default for FileAttributes {
@@ -178,3 +197,20 @@ impl Default for FileAttributes {
     }
 }
 
+impl From<&FileAttributes> for Stat {
+    fn from(attrs: &FileAttributes) -> Self {
+        Self {
+            access: attrs.access,
+            nlinks: attrs.nlinks,
+            uid: attrs.uid,
+            gid: attrs.gid,
+            rdev: attrs.rdev,
+            inode: attrs.inode,
+            size: attrs.size,
+
+            atime: attrs.atime,
+            mtime: attrs.mtime,
+            ctime: attrs.ctime,
+        }
+    }
+}
@@ -1,11 +1,13 @@
 
 use alloc::vec::Vec;
 use alloc::sync::Arc;
+use alloc::string::String;
 
-use ruxpin_types::{OpenFlags, FileAccess, Seek, UserID, DeviceID, DirEntry};
+use ruxpin_types::{OpenFlags, FileAccess, Seek, UserID, DeviceID, DirEntry, Stat};
 
 use crate::notice;
 use crate::sync::Spinlock;
+use crate::block::PageNum;
 use crate::errors::KernelError;
 
 use super::types::{Filesystem, Mount, Vnode, File, FilePointer, FileAttributes};
@@ -163,6 +165,97 @@ pub fn access(cwd: Option<Vnode>, path: &str, access: FileAccess, current_uid: U
     Ok(())
 }
 
+pub fn stat(cwd: Option<Vnode>, path: &str, current_uid: UserID) -> Result<Stat, KernelError> {
+    let vnode = lookup(cwd, path, current_uid)?;
+    let result = Stat::from(vnode.lock().attributes()?);
+    Ok(result)
+}
+
+pub fn change_directory(cwd: Option<Vnode>, path: &str, current_uid: UserID) -> Result<Vnode, KernelError> {
+    let vnode = lookup(cwd, path, current_uid)?;
+
+    if !vnode.lock().attributes()?.access.is_dir() {
+        return Err(KernelError::NotADirectory);
+    }
+
+    // Verify that the directory is searchable
+    if !verify_file_access(current_uid, FileAccess::Exec, vnode.lock().attributes()?) {
+        return Err(KernelError::AccessDenied);
+    }
+    Ok(vnode)
+}
+
+pub fn get_path(cwd: Option<Vnode>, current_uid: UserID) -> Result<String, KernelError> {
+    let root = ROOT_NODE.lock().as_ref().ok_or(KernelError::FileNotFound)?.clone();
+    let mut current = match cwd {
+        Some(vnode) => vnode,
+        None => return Ok(String::from("/")),
+    };
+
+    let mut components = Vec::new();
+    while !Arc::ptr_eq(&current, &root) {
+        let parent = current.lock().lookup("..")?;
+        if Arc::ptr_eq(&parent, &current) {
+            break;
+        }
+
+        if !verify_file_access(current_uid, FileAccess::Read, parent.lock().attributes()?) {
+            return Err(KernelError::AccessDenied);
+        }
+
+        components.push(reverse_lookup(parent.clone(), current)?);
+        current = parent;
+    }
+
+    let mut path = String::new();
+    for component in components.iter().rev() {
+        path.push('/');
+        path.push_str(component.as_str());
+    }
+    if path.len() == 0 {
+        path.push('/');
+    }
+    Ok(path)
+}
+
+fn reverse_lookup(parent: Vnode, target: Vnode) -> Result<String, KernelError> {
+    let target_inode = target.lock().attributes()?.inode;
+
+    // Matching the inode numbers first avoids looking up every entry when the target is on the same filesystem
+    // as its parent.  If the target is the root of a mounted filesystem, or its filesystem doesn't number its
+    // inodes, then every entry needs to be looked up and compared to the target after following any mounts
+    for match_inodes in [true, false] {
+        if match_inodes && target_inode == 0 {
+            continue;
+        }
+
+        let mut file = FilePointer::new(parent.clone());
+        parent.lock().open(&mut file, OpenFlags::ReadOnly)?;
+
+        loop {
+            let dirent = match parent.lock().readdir(&mut file)? {
+                Some(dirent) => dirent,
+                None => break,
+            };
+
+            let name = dirent.as_str();
+            if name == "." || name == ".." || (match_inodes && dirent.inode != target_inode) {
+                continue;
+            }
+
+            let vnode = match parent.lock().lookup(name) {
+                Ok(vnode) => get_mounted_root(vnode),
+                Err(_) => continue,
+            };
+
+            if Arc::ptr_eq(&vnode, &target) {
+                return Ok(String::from(name));
+            }
+        }
+    }
+    Err(KernelError::FileNotFound)
+}
+
 pub fn open(cwd: Option<Vnode>, path: &str, flags: OpenFlags, access: FileAccess, current_uid: UserID) -> Result<File, KernelError> {
     let vnode = if flags.is_set(OpenFlags::Create) {
         lookup(cwd.clone(), path, current_uid).or_else(|_| {
@@ -190,6 +283,14 @@ pub fn open(cwd: Option<Vnode>, path: &str, flags: OpenFlags, access: FileAccess
     Ok(Arc::new(Spinlock::new(file)))
 }
 
+/// Open another pointer to the same file, which has its own position
+pub fn reopen(file: File) -> Result<File, KernelError> {
+    let vnode = file.lock().vnode.clone();
+    let mut new_file = FilePointer::new(vnode.clone());
+    vnode.lock().open(&mut new_file, OpenFlags::ReadOnly)?;
+    Ok(Arc::new(Spinlock::new(new_file)))
+}
+
 /*
 pub fn close(file: File) -> Result<(), KernelError> {
     //let mut fptr = file.lock();
@@ -213,6 +314,12 @@ pub fn read(file: File, buffer: &mut [u8]) -> Result<usize, KernelError> {
     Ok(result)
 }
 
+pub fn get_device_page(file: File, offset: usize) -> Result<Option<(DeviceID, PageNum)>, KernelError> {
+    let vnode = file.lock().vnode.clone();
+    let result = vnode.lock().get_device_page(offset);
+    result
+}
+
 pub fn write(file: File, buffer: &[u8]) -> Result<usize, KernelError> {
     let mut fptr = file.lock();
     let vnode = fptr.vnode.clone();
@@ -220,13 +327,20 @@ pub fn write(file: File, buffer: &[u8]) -> Result<usize, KernelError> {
     Ok(result)
 }
 
-pub fn seek(file: File, offset: usize, whence: Seek) -> Result<usize, KernelError> {
+pub fn seek(file: File, offset: isize, whence: Seek) -> Result<usize, KernelError> {
     let mut fptr = file.lock();
     let vnode = fptr.vnode.clone();
     let result = vnode.lock().seek(&mut *fptr, offset, whence)?;
     Ok(result)
 }
 
+pub fn fstat(file: File) -> Result<Stat, KernelError> {
+    let fptr = file.lock();
+    let vnode = fptr.vnode.clone();
+    let result = Stat::from(vnode.lock().attributes()?);
+    Ok(result)
+}
+
 pub fn readdir(file: File) -> Result<Option<DirEntry>, KernelError> {
     let mut fptr = file.lock();
     let vnode = fptr.vnode.clone();
@@ -290,10 +404,7 @@ pub(super) fn lookup(cwd: Option<Vnode>, path: &str, current_uid: UserID) -> Res
     };
 
     loop {
-        let mounted_root_node = current.lock().get_mounted_mut().ok().map(|mount| if let Some(mount) = mount { Some(mount.clone()) } else { None }).flatten();
-        if mounted_root_node.is_some() {
-            current = mounted_root_node.unwrap();
-        }
+        current = get_mounted_root(current);
 
         if remaining == "" {
             return Ok(current);
@@ -310,6 +421,14 @@ pub(super) fn lookup(cwd: Option<Vnode>, path: &str, current_uid: UserID) -> Res
     }
 }
 
+fn get_mounted_root(vnode: Vnode) -> Vnode {
+    let mounted_root_node = vnode.lock().get_mounted_mut().ok().map(|mount| if let Some(mount) = mount { Some(mount.clone()) } else { None }).flatten();
+    match mounted_root_node {
+        Some(root) => root,
+        None => vnode,
+    }
+}
+
 fn get_path_component<'a>(path: &'a str) -> (&'a str, &'a str) {
     let mut i = 0;
     let mut start = 0;
@@ -0,0 +1,35 @@
+
+use crate::error;
+use crate::sync::Spinlock;
+use crate::mm::pagecache;
+use crate::proc::kthread::{self, KThread};
+use crate::errors::KernelError;
+
+use super::vfs;
+
+
+// Dirty pages and buffers are written out at least this often, instead of only when the cache needs room or on sync
+const WRITEBACK_INTERVAL_NS: u64 = 30 * 1_000_000_000;
+
+static WRITEBACK_THREAD: Spinlock<Option<KThread>> = Spinlock::new(None);
+
+
+/// Start the kernel thread that periodically writes the page cache and the mounted filesystems out to disk
+pub fn start() -> Result<(), KernelError> {
+    let thread = kthread::spawn("writeback", writeback_loop)?;
+    *WRITEBACK_THREAD.try_lock()? = Some(thread);
+    Ok(())
+}
+
+fn writeback_loop() {
+    while !kthread::should_stop() {
+        kthread::sleep_for(WRITEBACK_INTERVAL_NS);
+
+        if let Err(err) = pagecache::sync_all() {
+            error!("writeback: error while writing the page cache: {:?}", err);
+        }
+        if let Err(err) = vfs::sync_all() {
+            error!("writeback: error while syncing filesystems: {:?}", err);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 
 use alloc::boxed::Box;
 
+use crate::arch;
+use crate::random;
 use crate::sync::Spinlock;
 use crate::errors::KernelError;
 
@@ -9,11 +11,31 @@ pub trait InterruptController: Sync + Send {
     fn enable_irq(&mut self, irq: usize);
     fn disable_irq(&mut self, irq: usize);
     fn pending_irqs(&mut self) -> &mut dyn Iterator<Item=usize>;
+
+    /// Interrupt another cpu, which will see the irq number of the given inter-processor interrupt as pending
+    fn send_ipi(&mut self, _cpu: usize, _ipi: Ipi) { }
+}
+
+/// The interrupts that one cpu can send to another
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Ipi {
+    /// Switch to the task that should now be running on the cpu
+    Reschedule,
+    /// Flush the cached translations after mappings were changed by another cpu
+    TlbShootdown,
 }
 
 type IrqHandler = fn();
 
-const MAX_IRQS: usize = 64;
+/// The interrupts that are local to each cpu, such as its timer, are numbered after the shared interrupts
+pub const LOCAL_IRQ_BASE: usize = 64;
+/// The inter-processor interrupts are numbered after the local interrupts
+pub const IPI_IRQ_BASE: usize = 96;
+
+const MAX_IRQS: usize = 128;
+
+// The most interrupts that are handled at once, and any others will still be pending afterwards
+const MAX_PENDING: usize = 32;
 
 static INTERRUPT_CONTROLLER: Spinlock<Option<Box<dyn InterruptController>>> = Spinlock::new(None);
 static IRQ_HANDLERS: Spinlock<[Option<IrqHandler>; MAX_IRQS]> = Spinlock::new([None; MAX_IRQS]);
@@ -43,16 +65,46 @@ pub fn disable_irq(irq: usize) {
     }
 }
 
-pub(crate) fn handle_irqs() {
-    let handlers = IRQ_HANDLERS.try_lock().unwrap();
+/// Returns the irq number that's pending when the given inter-processor interrupt is received
+pub fn ipi_irq(ipi: Ipi) -> usize {
+    IPI_IRQ_BASE + ipi as usize
+}
 
+pub fn send_ipi(cpu: usize, ipi: Ipi) {
+    // This can be called with interrupts enabled, so they're disabled to avoid deadlocking with our own irq handler
+    unsafe {
+        let flags = arch::disable_irq();
+        if let Some(ctrl) = INTERRUPT_CONTROLLER.lock().as_mut() {
+            ctrl.send_ipi(cpu, ipi);
+        }
+        arch::enable_irq(flags);
+    }
+}
+
+pub(crate) fn handle_irqs() {
+    // The controller isn't locked while the handlers run, so that they can send interrupts to other cpus
+    let mut pending = [0; MAX_PENDING];
+    let mut count = 0;
     if let Some(ctrl) = INTERRUPT_CONTROLLER.try_lock().unwrap().as_mut() {
         let iter = ctrl.pending_irqs();
-        while let Some(irq) = iter.next() {
-            if let Some(handler) = handlers[irq] {
-                handler();
+        while count < MAX_PENDING {
+            match iter.next() {
+                Some(irq) => {
+                    pending[count] = irq;
+                    count += 1;
+                },
+                None => break,
             }
         }
     }
+
+    for irq in pending[..count].iter().cloned() {
+        // The exact time that an interrupt occurs can't be predicted, so it's used as a source of entropy
+        random::add_entropy(arch::read_counter() ^ irq as u64);
+        let handler = IRQ_HANDLERS.try_lock().unwrap().get(irq).cloned().flatten();
+        if let Some(handler) = handler {
+            handler();
+        }
+    }
 }
 
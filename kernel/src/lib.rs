@@ -12,8 +12,11 @@ pub mod misc;
 pub mod mm;
 pub mod printk;
 pub mod proc;
+pub mod random;
+pub mod smp;
 pub mod sync;
 pub mod tasklets;
+pub mod time;
 pub mod tty;
 
 extern crate alloc;
@@ -50,12 +53,8 @@ pub extern "C" fn non_boot_core_start() -> ! {
         }
     }
 
-    //notice!("cpu {}: booting secondary core", arch::cpu_id());
-    //let file = crate::fs::open(None, "/", ruxpin_types::OpenFlags::ReadOnly, ruxpin_types::FileAccess::DefaultDir, 0);
-    //notice!("done");
-
-    // TODO this does nothing for the moment
-    arch::loop_forever()
+    smp::initialize_secondary_core().unwrap();
+    start_multitasking()
 }
 
 #[panic_handler]
@@ -4,20 +4,81 @@ use core::mem;
 use core::alloc::{GlobalAlloc, Layout};
 
 use crate::notice;
-use crate::arch::PhysicalAddress;
+use crate::sync::Spinlock;
+use crate::misc::align_up;
+use crate::arch::mmu::{self, TranslationTable};
+use crate::arch::{PhysicalAddress, VirtualAddress};
 
+use super::pages;
+use super::{MemoryType, MemoryPermissions};
+
+
+// Small allocations are taken from slabs of objects that are all the same size, so they don't fragment the heap
+const SIZE_CLASSES: [usize; 7] = [16, 32, 64, 128, 256, 512, 1024];
+const SLAB_SIZE: usize = 4096;
+
+// Once the page pool is available, the heap grows by mapping pages into its own region of kernel space, after the linear map
+const KERNEL_HEAP_START: u64 = 0xffff_0080_0000_0000;
+const KERNEL_HEAP_MAX_SIZE: usize = 0x4000_0000;
+const KERNEL_HEAP_GROWTH: usize = 0x4_0000;
 
 struct Block {
     size: usize,
     next: *mut Block,
 }
 
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+struct SlabCache {
+    object_size: usize,
+    free_objects: *mut FreeObject,
+    total_objects: usize,
+    used_objects: usize,
+}
+
 struct Heap {
     free_blocks: *mut Block,
+    heap_size: usize,
+    free_bytes: usize,
+    slabs: [SlabCache; SIZE_CLASSES.len()],
+    large_allocations: usize,
+    large_bytes: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SlabStats {
+    pub object_size: usize,
+    pub total_objects: usize,
+    pub used_objects: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct KmallocStats {
+    pub slabs: [SlabStats; SIZE_CLASSES.len()],
+    pub large_allocations: usize,
+    pub large_bytes: usize,
+    pub heap_size: usize,
+    pub heap_free: usize,
+}
+
+struct HeapRegion {
+    enabled: bool,
+    end: usize,
 }
 
+struct KernelAllocator;
+
 #[global_allocator]
-static mut MAIN_HEAP: Heap = Heap { free_blocks: ptr::null_mut() };
+static ALLOCATOR: KernelAllocator = KernelAllocator;
+
+static HEAP: Spinlock<Heap> = Spinlock::new(Heap::new());
+static HEAP_REGION: Spinlock<HeapRegion> = Spinlock::new(HeapRegion { enabled: false, end: 0 });
+
+// The heap is only ever accessed through its lock
+unsafe impl Send for Heap {}
+unsafe impl Sync for Heap {}
 
 #[alloc_error_handler]
 fn out_of_memory(layout: Layout) -> ! {
@@ -25,36 +86,122 @@ fn out_of_memory(layout: Layout) -> ! {
 }
 
 pub fn initialize(start: PhysicalAddress, end: PhysicalAddress) {
-    unsafe {
-        MAIN_HEAP.init(start, end);
-    }
+    with_heap(|heap| unsafe {
+        heap.init(start, end)
+    });
+}
+
+/// Allow the heap to grow past its initial area, which must only be called once the page pool is initialized
+pub fn enable_growth() {
+    let mut region = HEAP_REGION.lock();
+    region.enabled = true;
+    region.end = KERNEL_HEAP_START as usize;
 }
 
 pub unsafe fn kmalloc(size: usize) -> *mut u8 {
-    MAIN_HEAP.malloc(size)
+    alloc_or_grow(size, mem::size_of::<usize>())
 }
 
-pub unsafe fn kmfree(ptr: *mut u8) {
-    MAIN_HEAP.free(ptr);
+pub unsafe fn kmfree(ptr: *mut u8, size: usize) {
+    with_heap(|heap| heap.free(ptr, size, mem::size_of::<usize>()))
 }
 
-unsafe impl GlobalAlloc for Heap {
+pub fn get_stats() -> KmallocStats {
+    with_heap(|heap| heap.stats())
+}
+
+fn with_heap<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Heap) -> R
+{
+    // Interrupts are disabled while the lock is held, so an interrupt handler that allocates can't deadlock on it
+    f(&mut *HEAP.lock())
+}
+
+unsafe fn alloc_or_grow(size: usize, align: usize) -> *mut u8 {
+    loop {
+        let ptr = with_heap(|heap| heap.alloc(size, align));
+        if !ptr.is_null() || !grow_heap(size + align) {
+            return ptr;
+        }
+    }
+}
+
+/// Map more pages at the end of the heap region and add them to the heap, returning false if it can't grow
+///
+/// The heap lock isn't held while the pages are allocated, because the page pool can allocate from the heap when reclaiming
+fn grow_heap(min_size: usize) -> bool {
+    let result = match HEAP_REGION.try_lock_once() {
+        Some(mut region) => region.grow(min_size),
+        // Either the heap is already growing, or this allocation is from inside the page pool while it's growing
+        None => None,
+    };
+
+    match result {
+        Some((start, size)) => {
+            with_heap(|heap| unsafe { heap.add_region(start, size) });
+            true
+        },
+        None => false,
+    }
+}
+
+fn size_class(size: usize, align: usize) -> Option<usize> {
+    let size = size.max(align);
+    SIZE_CLASSES.iter().position(|class| size <= *class)
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        // TODO add mutex??
-        kmalloc(layout.size())
-        //self.malloc(layout.size())
+        alloc_or_grow(layout.size(), layout.align())
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        // TODO add mutex??
-        kmfree(ptr);
-        //self.free(ptr);
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        with_heap(|heap| heap.free(ptr, layout.size(), layout.align()))
+    }
+}
+
+impl HeapRegion {
+    fn grow(&mut self, min_size: usize) -> Option<(*mut u8, usize)> {
+        // Allow for the block headers and slack from aligning the new space
+        let size = align_up(min_size + mmu::page_size(), KERNEL_HEAP_GROWTH);
+        if !self.enabled || self.end + size > KERNEL_HEAP_START as usize + KERNEL_HEAP_MAX_SIZE {
+            return None;
+        }
+
+        let start = VirtualAddress::from(self.end as u64);
+        let mut table = TranslationTable::initial_kernel_table();
+        if table.map_paged_range(MemoryType::Allocated, MemoryPermissions::ReadWrite, start, size, pages::get_page_pool()).is_err() {
+            return None;
+        }
+
+        self.end += size;
+        Some((u64::from(start) as *mut u8, size))
     }
 }
 
 impl Heap {
-    pub unsafe fn init(&mut self, start: PhysicalAddress, end: PhysicalAddress) {
-        let mut space: *mut Block = start.to_kernel_addr().as_mut();
+    const fn new() -> Self {
+        Self {
+            free_blocks: ptr::null_mut(),
+            heap_size: 0,
+            free_bytes: 0,
+            slabs: [
+                SlabCache::new(SIZE_CLASSES[0]),
+                SlabCache::new(SIZE_CLASSES[1]),
+                SlabCache::new(SIZE_CLASSES[2]),
+                SlabCache::new(SIZE_CLASSES[3]),
+                SlabCache::new(SIZE_CLASSES[4]),
+                SlabCache::new(SIZE_CLASSES[5]),
+                SlabCache::new(SIZE_CLASSES[6]),
+            ],
+            large_allocations: 0,
+            large_bytes: 0,
+        }
+    }
+
+    unsafe fn init(&mut self, start: PhysicalAddress, end: PhysicalAddress) {
+        let space: *mut Block = start.to_kernel_addr().as_mut();
 
         let size = usize::from(end) - usize::from(start);
         notice!("kernel heap: using {:#x}, size {}MiB", u64::from(start), size / 1024 / 1024);
@@ -63,10 +210,83 @@ impl Heap {
         (*space).next = ptr::null_mut();
 
         self.free_blocks = space;
+        self.heap_size = size;
+        self.free_bytes = size;
+    }
+
+    unsafe fn add_region(&mut self, start: *mut u8, size: usize) {
+        let block: *mut Block = start.cast();
+        (*block).size = size;
+        (*block).next = ptr::null_mut();
+
+        self.heap_size += size;
+        self.mfree(block.offset(1).cast());
+    }
+
+    unsafe fn alloc(&mut self, size: usize, align: usize) -> *mut u8 {
+        match size_class(size, align) {
+            Some(class) => {
+                if self.slabs[class].free_objects.is_null() {
+                    let object_size = self.slabs[class].object_size;
+                    let slab = self.alloc_aligned(SLAB_SIZE, object_size);
+                    if slab.is_null() {
+                        return slab;
+                    }
+                    self.slabs[class].add_slab(slab);
+                }
+                self.slabs[class].alloc()
+            },
+            None => {
+                let ptr = self.alloc_aligned(size, align);
+                if !ptr.is_null() {
+                    self.large_allocations += 1;
+                    self.large_bytes += size;
+                }
+                ptr
+            },
+        }
+    }
+
+    unsafe fn free(&mut self, ptr: *mut u8, size: usize, align: usize) {
+        match size_class(size, align) {
+            Some(class) => self.slabs[class].free(ptr),
+            None => {
+                self.large_allocations -= 1;
+                self.large_bytes -= size;
+                self.free_aligned(ptr);
+            },
+        }
+    }
+
+    fn stats(&self) -> KmallocStats {
+        KmallocStats {
+            slabs: core::array::from_fn(|i| self.slabs[i].stats()),
+            large_allocations: self.large_allocations,
+            large_bytes: self.large_bytes,
+            heap_size: self.heap_size,
+            heap_free: self.free_bytes,
+        }
+    }
+
+    // The address of the block is stored just before the aligned pointer, so it can be found when it's freed
+    unsafe fn alloc_aligned(&mut self, size: usize, align: usize) -> *mut u8 {
+        let align = align.max(mem::size_of::<usize>());
+        let block = self.malloc(size + align + mem::size_of::<usize>());
+        if block.is_null() {
+            return block;
+        }
+        let ptr = align_up(block as usize + mem::size_of::<usize>(), align) as *mut u8;
+        *ptr.cast::<*mut u8>().offset(-1) = block;
+        ptr
+    }
+
+    unsafe fn free_aligned(&mut self, ptr: *mut u8) {
+        let block = *ptr.cast::<*mut u8>().offset(-1);
+        self.mfree(block);
     }
 
-    pub unsafe fn malloc(&mut self, mut size: usize) -> *mut u8 {
-        let mut nextfree: *mut Block;
+    unsafe fn malloc(&mut self, mut size: usize) -> *mut u8 {
+        let nextfree: *mut Block;
         let mut prev: *mut Block = ptr::null_mut();
         let mut cur: *mut Block = self.free_blocks;
 
@@ -95,21 +315,23 @@ impl Heap {
                     self.free_blocks = nextfree;
                 }
 
+                self.free_bytes -= (*cur).size;
                 return cur.offset(1).cast();
             }
 
             prev = cur;
             cur = (*cur).next;
         }
-        // Out Of Memory
-        panic!("Kernel out of memory!  Halting...\n");
+        // Out of memory, so the caller will try to grow the heap
+        ptr::null_mut()
     }
 
-    pub unsafe fn free(&mut self, ptr: *mut u8) {
+    unsafe fn mfree(&mut self, ptr: *mut u8) {
         let mut prev: *mut Block = ptr::null_mut();
-        let mut block: *mut Block = ptr.cast::<Block>().offset(-1);
+        let block: *mut Block = ptr.cast::<Block>().offset(-1);
         let mut cur: *mut Block = self.free_blocks;
 
+        self.free_bytes += (*block).size;
         while !cur.is_null() {
             if (*cur).next == block {
                 panic!("Double free detected at {:x}! Halting...\n", cur as usize);
@@ -147,6 +369,56 @@ impl Heap {
             prev = cur;
             cur = (*cur).next;
         }
+
+        // The block is after all the free blocks, or there are no free blocks
+        (*block).next = ptr::null_mut();
+        if !prev.is_null() {
+            (*prev).next = block;
+        } else {
+            self.free_blocks = block;
+        }
+    }
+}
+
+impl SlabCache {
+    const fn new(object_size: usize) -> Self {
+        Self {
+            object_size,
+            free_objects: ptr::null_mut(),
+            total_objects: 0,
+            used_objects: 0,
+        }
+    }
+
+    unsafe fn add_slab(&mut self, slab: *mut u8) {
+        for i in (0..SLAB_SIZE / self.object_size).rev() {
+            let object: *mut FreeObject = slab.add(i * self.object_size).cast();
+            (*object).next = self.free_objects;
+            self.free_objects = object;
+            self.total_objects += 1;
+        }
+    }
+
+    unsafe fn alloc(&mut self) -> *mut u8 {
+        let object = self.free_objects;
+        self.free_objects = (*object).next;
+        self.used_objects += 1;
+        object.cast()
+    }
+
+    unsafe fn free(&mut self, ptr: *mut u8) {
+        let object: *mut FreeObject = ptr.cast();
+        (*object).next = self.free_objects;
+        self.free_objects = object;
+        self.used_objects -= 1;
+    }
+
+    fn stats(&self) -> SlabStats {
+        SlabStats {
+            object_size: self.object_size,
+            total_objects: self.total_objects,
+            used_objects: self.used_objects,
+        }
     }
 }
 
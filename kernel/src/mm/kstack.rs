@@ -0,0 +1,86 @@
+
+use crate::sync::Spinlock;
+use crate::misc::align_up;
+use crate::errors::KernelError;
+use crate::arch::mmu::{self, TranslationTable};
+use crate::arch::VirtualAddress;
+use crate::smp;
+
+use super::pages;
+use super::{MemoryType, MemoryPermissions};
+
+
+// Kernel thread stacks are mapped into their own region of kernel space, with one slot per stack.  Only the top of
+// each slot is mapped, so the unmapped space below the stack acts as a guard that faults if the stack overflows
+const KERNEL_STACKS_START: u64 = 0xffff_0100_0000_0000;
+const KERNEL_STACK_SLOT_SIZE: usize = 0x10_0000;
+const MAX_KERNEL_STACKS: usize = 1024;
+
+static STACK_SLOTS: Spinlock<[bool; MAX_KERNEL_STACKS]> = Spinlock::new([false; MAX_KERNEL_STACKS]);
+
+
+/// A stack for a kernel thread, allocated from the page pool with an unmapped guard region below it
+pub struct KernelStack {
+    slot: usize,
+    size: usize,
+}
+
+impl KernelStack {
+    pub fn alloc(size: usize) -> Result<Self, KernelError> {
+        // At least one page of each slot is always left unmapped as the guard
+        let size = align_up(size, mmu::page_size());
+        if size >= KERNEL_STACK_SLOT_SIZE {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        // The lock is held while mapping so that two stacks which share a table can't both try to create it
+        let mut slots = STACK_SLOTS.try_lock()?;
+        let slot = slots.iter().position(|used| !*used).ok_or(KernelError::OutOfMemory)?;
+
+        let stack = Self { slot, size };
+        let mut table = TranslationTable::initial_kernel_table();
+        table.map_paged_range(MemoryType::Allocated, MemoryPermissions::ReadWrite, stack.bottom(), size, pages::get_page_pool())?;
+        slots[slot] = true;
+
+        Ok(stack)
+    }
+
+    /// The address of the top of the stack, which is where the stack pointer starts
+    pub fn top(&self) -> VirtualAddress {
+        slot_start(self.slot).add(KERNEL_STACK_SLOT_SIZE)
+    }
+
+    fn bottom(&self) -> VirtualAddress {
+        self.top().sub(self.size)
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let mut slots = STACK_SLOTS.lock();
+        let mut table = TranslationTable::initial_kernel_table();
+        let released = match table.unmap_range(self.bottom(), self.size, pages::get_page_pool()) {
+            Ok(released) => released,
+            // The slot isn't reused if its pages couldn't be unmapped
+            Err(_) => return,
+        };
+        smp::shootdown_tlb();
+        drop(released);
+        slots[self.slot] = false;
+    }
+}
+
+/// Returns true if the address is in the unmapped region below one of the kernel thread stacks
+pub fn is_kernel_stack_guard(addr: VirtualAddress) -> bool {
+    let addr = u64::from(addr);
+    if addr < KERNEL_STACKS_START || addr >= KERNEL_STACKS_START + (MAX_KERNEL_STACKS * KERNEL_STACK_SLOT_SIZE) as u64 {
+        return false;
+    }
+
+    let slot = ((addr - KERNEL_STACKS_START) as usize) / KERNEL_STACK_SLOT_SIZE;
+    STACK_SLOTS.try_lock_once().map(|slots| slots[slot]).unwrap_or(true)
+}
+
+fn slot_start(slot: usize) -> VirtualAddress {
+    VirtualAddress::from(KERNEL_STACKS_START + (slot * KERNEL_STACK_SLOT_SIZE) as u64)
+}
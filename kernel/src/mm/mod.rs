@@ -3,6 +3,8 @@ pub mod pages;
 pub mod pagecache;
 pub mod kmalloc;
 pub mod vmalloc;
+pub mod swap;
+pub mod kstack;
 
 mod segments;
 
@@ -1,36 +1,53 @@
 
-use core::cmp::Ordering;
+use core::cmp::{self, Ordering};
+use core::sync::atomic::{self, AtomicUsize};
+use alloc::vec::Vec;
 use alloc::sync::Arc;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 
 use ruxpin_types::Seek;
 
+use crate::block::{self, BufPage};
 use crate::arch::mmu;
-use crate::arch::PhysicalAddress;
 use crate::errors::KernelError;
 use crate::fs::{self, Vnode, File};
 use crate::sync::Spinlock;
 
-use super::pages;
+use super::pages::{self, OwnedPage};
 
 
+// The number of files that are no longer mapped which are kept in the cache in case they're used again
+const MAX_UNUSED_ENTRIES: usize = 16;
+
 static PAGE_CACHE: Spinlock<Option<PageCache>> = Spinlock::new(None);
 
+// Incremented on every access, so that the least recently used pages and files can be found
+static ACCESS_CLOCK: AtomicUsize = AtomicUsize::new(0);
+
 pub struct CachedFile(Vnode);
 
 pub struct PageCache {
     files: BTreeMap<CachedFile, Arc<PageCacheEntry>>,
 }
 
+struct CachedPage {
+    page: OwnedPage,
+    last_used: usize,
+    // The device's cached page, if the file's page is stored in one piece on the device and can be used directly
+    buf: Option<Arc<BufPage>>,
+}
+
 pub struct PageCacheEntry {
     file: File,
-    pages: Spinlock<BTreeMap<usize, PhysicalAddress>>,
-    
+    last_used: AtomicUsize,
+    pages: Spinlock<BTreeMap<usize, CachedPage>>,
+    dirty: Spinlock<BTreeSet<usize>>,
 }
 
 
 pub fn initialize() -> Result<(), KernelError> {
     *(PAGE_CACHE.try_lock()?) = Some(PageCache::new());
+    pages::get_page_pool().set_reclaim_hook(reclaim);
     Ok(())
 }
 
@@ -38,6 +55,75 @@ pub fn get_page_entry(file: File) -> Result<Arc<PageCacheEntry>, KernelError> {
     Ok(PAGE_CACHE.try_lock()?.as_mut().unwrap().get(file))
 }
 
+/// Write the dirty pages of all cached files back to their files
+pub fn sync_all() -> Result<(), KernelError> {
+    let entries: Vec<Arc<PageCacheEntry>> = PAGE_CACHE.try_lock()?.as_ref().unwrap().files.values().cloned().collect();
+    for entry in entries {
+        entry.writeback(0, usize::MAX)?;
+    }
+    Ok(())
+}
+
+/// Remove the least recently used files that are no longer mapped, beyond the number that are kept cached
+pub fn release_unused() -> Result<(), KernelError> {
+    let unused = PAGE_CACHE.try_lock()?.as_ref().unwrap().find_unused(MAX_UNUSED_ENTRIES);
+    if unused.len() == 0 {
+        return Ok(());
+    }
+
+    for entry in unused.iter() {
+        entry.writeback(0, usize::MAX)?;
+    }
+
+    // The entries are dropped after the cache is unlocked, which frees their pages and closes their files
+    let mut removed = Vec::new();
+    {
+        let mut locked_cache = PAGE_CACHE.try_lock()?;
+        let cache = locked_cache.as_mut().unwrap();
+        for entry in unused {
+            // The entry could have been mapped again while its pages were being written back
+            if Arc::strong_count(&entry) == 2 {
+                let vnode = entry.file.lock().vnode.clone();
+                removed.push(cache.files.remove(&CachedFile(vnode)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Free the least recently used pages that are clean and not mapped, which is called by the page pool when it's low
+///
+/// This can be called during any page allocation, so it skips anything that's locked rather than waiting
+fn reclaim(count: usize) -> usize {
+    let entries: Vec<Arc<PageCacheEntry>> = match PAGE_CACHE.try_lock_once() {
+        Some(locked_cache) => locked_cache.as_ref().unwrap().files.values().cloned().collect(),
+        None => return 0,
+    };
+
+    let mut candidates = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        entry.find_reclaimable(i, &mut candidates);
+    }
+    candidates.sort_unstable();
+
+    let mut freed = 0;
+    for (_, i, page_offset) in candidates.into_iter().take(count) {
+        if entries[i].free_page(page_offset) {
+            freed += 1;
+        }
+    }
+
+    // The device pages that were used by files are only freed once they're no longer cached for any file
+    if freed < count {
+        freed += block::reclaim_bufs(count - freed);
+    }
+    freed
+}
+
+fn next_access_time() -> usize {
+    ACCESS_CLOCK.fetch_add(1, atomic::Ordering::Relaxed)
+}
+
 impl PageCache {
     pub fn new() -> Self {
         Self {
@@ -48,7 +134,10 @@ impl PageCache {
     fn get(&mut self, file: File) -> Arc<PageCacheEntry> {
         let vnode = file.lock().vnode.clone();
         match self.files.get(&CachedFile(vnode.clone())) {
-            Some(entry) => entry.clone(),
+            Some(entry) => {
+                entry.last_used.store(next_access_time(), atomic::Ordering::Relaxed);
+                entry.clone()
+            },
             None => {
                 let entry = Arc::new(PageCacheEntry::new(file.clone()));
                 self.files.insert(CachedFile(vnode.clone()), entry);
@@ -56,40 +145,137 @@ impl PageCache {
             }
         }
     }
+
+    /// Find the least recently used entries that aren't referenced outside of the cache, except for the given number of most recent ones
+    fn find_unused(&self, keep: usize) -> Vec<Arc<PageCacheEntry>> {
+        let mut unused: Vec<&Arc<PageCacheEntry>> = self.files.values().filter(|entry| Arc::strong_count(entry) == 1).collect();
+        if unused.len() <= keep {
+            return Vec::new();
+        }
+
+        unused.sort_unstable_by_key(|entry| entry.last_used.load(atomic::Ordering::Relaxed));
+        let remove = unused.len() - keep;
+        unused.into_iter().take(remove).cloned().collect()
+    }
 }
 
 impl PageCacheEntry {
     fn new(file: File) -> Self {
         Self {
             file,
+            last_used: AtomicUsize::new(next_access_time()),
             pages: Spinlock::new(BTreeMap::new()),
+            dirty: Spinlock::new(BTreeSet::new()),
         }
     }
 
-    pub fn lookup(&self, offset: usize) -> Result<PhysicalAddress, KernelError> {
-        let pages = pages::get_page_pool();
+    /// Get a reference to the cached page that holds the given file offset, loading it if it isn't cached yet
+    pub fn lookup(&self, offset: usize) -> Result<OwnedPage, KernelError> {
         let page_offset = offset / mmu::page_size();
 
         let mut locked_pages = self.pages.try_lock()?;
-        match locked_pages.get(&page_offset) {
-            Some(page) => Ok(pages.ref_page(*page)),
+        match locked_pages.get_mut(&page_offset) {
+            Some(cached) => {
+                cached.last_used = next_access_time();
+                Ok(cached.page.clone())
+            },
             None => {
-                let page = pages.alloc_page_zeroed();
+                let cached = match fs::get_device_page(self.file.clone(), offset)? {
+                    Some((device_id, page_num)) => {
+                        let buf = block::get_page(device_id, page_num)?;
+                        CachedPage { page: buf.page().clone(), last_used: next_access_time(), buf: Some(buf) }
+                    },
+                    None => {
+                        let page = OwnedPage::alloc_zeroed()?;
 
-                let page_buffer = mmu::get_page_slice(page);
-                fs::seek(self.file.clone(), offset, Seek::FromStart)?;
+                        let page_buffer = mmu::get_page_slice(page.addr());
+                        fs::seek(self.file.clone(), offset as isize, Seek::FromStart)?;
 
-                fs::read(self.file.clone(), &mut page_buffer[..mmu::page_size()])?;
+                        fs::read(self.file.clone(), &mut page_buffer[..mmu::page_size()])?;
+                        CachedPage { page, last_used: next_access_time(), buf: None }
+                    },
+                };
 
-                locked_pages.insert(page_offset, pages.ref_page(page));
+                let page = cached.page.clone();
+                locked_pages.insert(page_offset, cached);
                 Ok(page)
             }
         }
     }
 
-    pub fn lookup_page_slice(&self, offset: usize) -> Result<&[u8], KernelError> {
-        let page = self.lookup(offset)?;
-        Ok(mmu::get_page_slice(page))
+    pub fn mark_dirty(&self, offset: usize) -> Result<(), KernelError> {
+        self.dirty.try_lock()?.insert(offset / mmu::page_size());
+        Ok(())
+    }
+
+    /// Write the dirty pages between the given file offsets back to the file
+    pub fn writeback(&self, start: usize, end: usize) -> Result<(), KernelError> {
+        let page_size = mmu::page_size();
+        let file_size = fs::fstat(self.file.clone())?.size;
+
+        let dirty: Vec<usize> = self.dirty.try_lock()?.range(start / page_size..=(end - 1) / page_size).cloned().collect();
+        for page_offset in dirty {
+            let (page, mapped, buf) = match self.pages.try_lock()?.get(&page_offset) {
+                Some(cached) => (cached.page.addr(), cached.is_mapped(), cached.buf.clone()),
+                None => continue,
+            };
+
+            // Only the part of the page inside the file is written, so that the file isn't extended
+            let offset = page_offset * page_size;
+            if let Some(buf) = buf {
+                // The page is the device's own copy of the data, so it only needs to be written to the device
+                buf.mark_dirty();
+                buf.commit()?;
+            } else if offset < file_size {
+                let length = cmp::min(page_size, file_size - offset);
+                fs::seek(self.file.clone(), offset as isize, Seek::FromStart)?;
+                fs::write(self.file.clone(), &mmu::get_page_slice(page)[..length])?;
+            }
+
+            // A page that's still mapped can be written to again without faulting, so it stays dirty until it's unmapped
+            if !mapped {
+                self.dirty.try_lock()?.remove(&page_offset);
+            }
+        }
+        Ok(())
+    }
+
+    fn find_reclaimable(&self, index: usize, candidates: &mut Vec<(usize, usize, usize)>) {
+        let (locked_pages, locked_dirty) = match (self.pages.try_lock_once(), self.dirty.try_lock_once()) {
+            (Some(locked_pages), Some(locked_dirty)) => (locked_pages, locked_dirty),
+            _ => return,
+        };
+
+        // A page is only referenced by the cache once it's no longer mapped, and dirty pages must be written back first
+        for (page_offset, cached) in locked_pages.iter() {
+            if !cached.is_mapped() && !locked_dirty.contains(page_offset) {
+                candidates.push((cached.last_used, index, *page_offset));
+            }
+        }
+    }
+
+    fn free_page(&self, page_offset: usize) -> bool {
+        let mut locked_pages = match self.pages.try_lock_once() {
+            Some(locked_pages) => locked_pages,
+            None => return false,
+        };
+
+        match locked_pages.get(&page_offset) {
+            Some(cached) if !cached.is_mapped() => {
+                locked_pages.remove(&page_offset);
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+impl CachedPage {
+    /// Returns true if the page is referenced by anything other than the caches
+    fn is_mapped(&self) -> bool {
+        // A device's page also has a reference from the device's cache, which will free it when it's no longer used
+        let cache_refs = if self.buf.is_some() { 2 } else { 1 };
+        self.page.ref_count() > cache_refs
     }
 }
 
@@ -2,19 +2,36 @@
 use core::mem;
 use core::ptr;
 use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "page-debug")]
+use core::panic::Location;
 
 use alloc::vec::Vec;
 
-use crate::{notice, trace};
+use crate::{error, notice, trace};
 use crate::arch::mmu;
 use crate::arch::PhysicalAddress;
 use crate::misc::{ceiling_div, align_up};
+use crate::sync::Spinlock;
+use crate::errors::KernelError;
 
 
 const BITS_PER_ALLOC: usize = 32;
 
+// When the free pages drop below this, the reclaim hook is asked to free this many pages
+const RECLAIM_THRESHOLD: usize = 64;
+
+/// A function that frees up to the given number of pages that can be recreated, and returns how many were freed
+pub type ReclaimHook = fn(usize) -> usize;
+
+/// The pool of physical pages shared by all cpus
+///
+/// Each operation takes the lock for only as long as it needs it, so that a reclaim hook can free pages while
+/// an allocation is waiting for them.  Pages are also freed from interrupt handlers, so the lock disables interrupts
 pub struct PagePool {
-    regions: Vec<PageRegion>,
+    regions: Spinlock<Vec<PageRegion>>,
+    reclaim: Spinlock<Option<ReclaimHook>>,
+    reclaiming: AtomicBool,
 }
 
 pub struct PageRegion {
@@ -30,52 +47,75 @@ pub type PageRefCount = u16;
 
 pub struct Page {
     refcount: PageRefCount,
+    // Where the page was allocated, so that pages which are never freed can be traced back to their source
+    #[cfg(feature = "page-debug")]
+    site: Option<&'static Location<'static>>,
 }
 
-static mut PAGES: PagePool = PagePool::new();
+/// A counted reference to a page, which releases its reference when it's dropped
+///
+/// Only the address of a page can be stored in a page table, so the table's reference is converted with `into_raw()`
+/// when a page is mapped, and back with `from_raw()` when it's unmapped
+pub struct OwnedPage(PhysicalAddress);
+
+static PAGES: PagePool = PagePool::new();
 
 
 pub fn init_pages_pool(start: PhysicalAddress, end: PhysicalAddress) {
     let pages = PageRegion::new(start, end);
-
-    unsafe {
-        PAGES.regions.push(pages);
-    }
+    PAGES.regions.lock().push(pages);
 }
 
-pub fn get_page_pool<'a>() -> &'a mut PagePool {
-    unsafe {
-        &mut PAGES
-    }
+pub fn get_page_pool() -> &'static PagePool {
+    &PAGES
 }
 
 impl PagePool {
     pub const fn new() -> Self {
         Self {
-            regions: Vec::new(),
+            regions: Spinlock::new(Vec::new()),
+            reclaim: Spinlock::new(None),
+            reclaiming: AtomicBool::new(false),
         }
     }
 
-    pub fn alloc_page(&mut self) -> PhysicalAddress {
-        for region in &mut self.regions {
+    pub fn set_reclaim_hook(&self, hook: ReclaimHook) {
+        *self.reclaim.lock() = Some(hook);
+    }
+
+    /// Allocate a page, or return an error if there are none left even after reclaiming what the hook can free
+    ///
+    /// Pages are only swapped out before a page fault allocates, since swapping needs locks that the callers of
+    /// this could be holding, so the caller must handle running out rather than waiting for a page
+    #[track_caller]
+    pub fn alloc_page(&self) -> Result<PhysicalAddress, KernelError> {
+        if self.free_page_count() < RECLAIM_THRESHOLD {
+            self.reclaim_pages(RECLAIM_THRESHOLD);
+        }
+
+        for region in self.regions.lock().iter_mut() {
             if let Some(addr) = region.alloc_page() {
+                #[cfg(feature = "page-debug")]
+                region.set_alloc_site(addr, Location::caller());
                 trace!("pages: allocating page at {:#x}", usize::from(addr));
-                return addr;
+                return Ok(addr);
             }
         }
-        panic!("pages: out of pages");
+        error!("pages: out of pages");
+        Err(KernelError::OutOfMemory)
     }
 
-    pub fn alloc_page_zeroed(&mut self) -> PhysicalAddress {
-        let paddr = self.alloc_page();
+    #[track_caller]
+    pub fn alloc_page_zeroed(&self) -> Result<PhysicalAddress, KernelError> {
+        let paddr = self.alloc_page()?;
         unsafe {
             zero_page(paddr);
         }
-        paddr
+        Ok(paddr)
     }
 
-    pub fn free_page(&mut self, ptr: PhysicalAddress) {
-        for region in &mut self.regions {
+    pub fn free_page(&self, ptr: PhysicalAddress) {
+        for region in self.regions.lock().iter_mut() {
             if ptr >= region.pages_start && ptr <= region.pages_start.add(region.total_pages() * mmu::page_size()) {
                 //trace!("pages: freeing page at {:x}", usize::from(ptr));
                 region.free_page(ptr);
@@ -85,8 +125,8 @@ impl PagePool {
         panic!("pages: attempting to free a page with no region: {:x}", usize::from(ptr));
     }
 
-    pub fn ref_page(&mut self, ptr: PhysicalAddress) -> PhysicalAddress {
-        for region in &mut self.regions {
+    pub fn ref_page(&self, ptr: PhysicalAddress) -> PhysicalAddress {
+        for region in self.regions.lock().iter_mut() {
             if ptr >= region.pages_start && ptr <= region.pages_start.add(region.total_pages() * mmu::page_size()) {
                 trace!("pages: incrementing page ref at {:#x}", usize::from(ptr));
                 return region.ref_page(ptr);
@@ -94,6 +134,114 @@ impl PagePool {
         }
         panic!("pages: attempting to reference a page with no region: {:x}", usize::from(ptr));
     }
+
+    fn reclaim_pages(&self, count: usize) -> usize {
+        let hook = match *self.reclaim.lock() {
+            Some(hook) => hook,
+            None => return 0,
+        };
+
+        // The hook can free pages but must not allocate any, so it's never called recursively, and only one cpu
+        // reclaims at a time while the others go on to allocate from whatever is left
+        if self.reclaiming.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            return 0;
+        }
+        let freed = hook(count);
+        self.reclaiming.store(false, Ordering::Release);
+        trace!("pages: reclaimed {} pages", freed);
+        freed
+    }
+
+    pub fn free_page_count(&self) -> usize {
+        self.regions.lock().iter().map(|region| region.free_pages).sum()
+    }
+
+    pub fn get_ref_count(&self, ptr: PhysicalAddress) -> PageRefCount {
+        for region in self.regions.lock().iter() {
+            if ptr >= region.pages_start && ptr <= region.pages_start.add(region.total_pages() * mmu::page_size()) {
+                return region.get_ref_count(ptr);
+            }
+        }
+        panic!("pages: attempting to get the references of a page with no region: {:x}", usize::from(ptr));
+    }
+
+    /// Print the number of pages that are still allocated from each place that pages are allocated
+    #[cfg(feature = "page-debug")]
+    pub fn print_allocated_pages(&self) {
+        const MAX_SITES: usize = 64;
+
+        // The list is allocated before counting, so the heap won't need to take more pages while the regions are being read
+        let mut sites: Vec<(&'static Location<'static>, usize)> = Vec::with_capacity(MAX_SITES);
+        let mut unknown = 0;
+        let regions = self.regions.lock();
+        for region in regions.iter() {
+            for page in region.desc_table.iter().filter(|page| page.refcount > 0) {
+                match page.site {
+                    Some(site) => match sites.iter().position(|(existing, _)| *existing == site) {
+                        Some(i) => sites[i].1 += 1,
+                        None if sites.len() < MAX_SITES => sites.push((site, 1)),
+                        None => unknown += 1,
+                    },
+                    None => unknown += 1,
+                }
+            }
+        }
+
+        let allocated = regions.iter().map(|region| region.total_pages - region.free_pages).sum::<usize>();
+        drop(regions);
+
+        notice!("pages: {} pages allocated", allocated);
+        for (site, count) in sites {
+            notice!("pages: {:>6} allocated at {}", count, site);
+        }
+        if unknown > 0 {
+            notice!("pages: {:>6} allocated elsewhere", unknown);
+        }
+    }
+}
+
+impl OwnedPage {
+    #[track_caller]
+    pub fn alloc() -> Result<Self, KernelError> {
+        Ok(Self(get_page_pool().alloc_page()?))
+    }
+
+    #[track_caller]
+    pub fn alloc_zeroed() -> Result<Self, KernelError> {
+        Ok(Self(get_page_pool().alloc_page_zeroed()?))
+    }
+
+    /// Take over a reference to a page that was counted when the page was allocated or referenced
+    pub unsafe fn from_raw(page: PhysicalAddress) -> Self {
+        Self(page)
+    }
+
+    /// Give up the reference without releasing it, so that whatever holds the address can release it later
+    pub fn into_raw(self) -> PhysicalAddress {
+        let page = self.0;
+        mem::forget(self);
+        page
+    }
+
+    pub fn addr(&self) -> PhysicalAddress {
+        self.0
+    }
+
+    pub fn ref_count(&self) -> PageRefCount {
+        get_page_pool().get_ref_count(self.0)
+    }
+}
+
+impl Clone for OwnedPage {
+    fn clone(&self) -> Self {
+        Self(get_page_pool().ref_page(self.0))
+    }
+}
+
+impl Drop for OwnedPage {
+    fn drop(&mut self) {
+        get_page_pool().free_page(self.0);
+    }
 }
 
 impl PageRegion {
@@ -156,6 +304,17 @@ impl PageRegion {
         ptr
     }
 
+    pub fn get_ref_count(&self, ptr: PhysicalAddress) -> PageRefCount {
+        let bit = (usize::from(ptr) - usize::from(self.pages_start)) / mmu::page_size();
+        self.desc_table[bit].refcount
+    }
+
+    #[cfg(feature = "page-debug")]
+    fn set_alloc_site(&mut self, ptr: PhysicalAddress, site: &'static Location<'static>) {
+        let bit = (usize::from(ptr) - usize::from(self.pages_start)) / mmu::page_size();
+        self.desc_table[bit].site = Some(site);
+    }
+
     fn alloc_bit(&mut self) -> Option<usize> {
         let mut i = self.last_index;
 
@@ -218,7 +377,9 @@ fn init_desc_table(start: PhysicalAddress, pages: usize) -> &'static mut [Page]
     for page in desc_table.iter_mut() {
         unsafe {
             ptr::write(page, Page {
-                refcount: 0
+                refcount: 0,
+                #[cfg(feature = "page-debug")]
+                site: None,
             });
         }
     }
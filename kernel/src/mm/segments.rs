@@ -1,4 +1,5 @@
 
+use alloc::vec::Vec;
 use alloc::sync::Arc;
 use alloc::boxed::Box;
 
@@ -7,7 +8,7 @@ use crate::arch::{PhysicalAddress, VirtualAddress};
 use crate::errors::KernelError;
 use crate::misc::align_up;
 
-use super::pages;
+use super::pages::{self, OwnedPage};
 use super::{MemoryType, MemoryPermissions};
 use super::pagecache::PageCacheEntry;
 
@@ -17,11 +18,16 @@ pub enum SegmentType {
     Text,
     Data,
     Stack,
+    Mapping,
 }
 
 pub trait SegmentOperations: Sync + Send {
     fn copy(&self) -> Box<dyn SegmentOperations>;
+    fn split(&self, offset: usize) -> Box<dyn SegmentOperations>;
+    fn is_shared(&self) -> bool;
     fn load_page_at(&self, segment: &Segment, table: &mut TranslationTable, vaddr: VirtualAddress) -> Result<PhysicalAddress, KernelError>;
+    fn mark_dirty(&self, segment: &Segment, vaddr: VirtualAddress) -> Result<(), KernelError>;
+    fn sync(&self, segment: &Segment) -> Result<(), KernelError>;
 }
 
 pub struct Segment {
@@ -51,8 +57,8 @@ impl Segment {
         Ok(segment)
     }
 
-    pub fn new_file_backed(table: &mut TranslationTable, stype: SegmentType, permissions: MemoryPermissions, mem_offset: usize, start: VirtualAddress, end: VirtualAddress, cache: Arc<PageCacheEntry>, file_offset: usize, file_size: usize) -> Result<Self, KernelError> {
-        let ops = Box::new(FileBackedSegment::new(cache, file_offset, file_size, mem_offset)?);
+    pub fn new_file_backed(table: &mut TranslationTable, stype: SegmentType, permissions: MemoryPermissions, mem_offset: usize, start: VirtualAddress, end: VirtualAddress, cache: Arc<PageCacheEntry>, file_offset: usize, file_size: usize, shared: bool) -> Result<Self, KernelError> {
+        let ops = Box::new(FileBackedSegment::new(cache, file_offset, file_size, mem_offset, shared)?);
         let segment = Self::new(stype, permissions, start, end, ops);
         let pages = pages::get_page_pool();
         table.map_paged_range(MemoryType::Unallocated, permissions, segment.start, segment.page_aligned_len(), pages)?;
@@ -64,22 +70,55 @@ impl Segment {
     }
 
     pub fn match_range(&self, addr: VirtualAddress) -> bool {
-        addr >= self.start && addr <= self.end
+        addr >= self.start && addr < self.end
     }
 
-    pub fn unmap(&mut self, table: &mut TranslationTable) -> Result<(), KernelError> {
+    /// Split the segment at the given page-aligned address, returning the upper part as a new segment
+    pub fn split(&mut self, addr: VirtualAddress) -> Self {
+        let offset = usize::from(addr) - usize::from(self.start);
+        let upper = Self::new(self.stype, self.permissions, addr, self.end, self.ops.split(offset));
+        self.end = addr;
+        upper
+    }
+
+    pub fn change_permissions(&mut self, table: &mut TranslationTable, permissions: MemoryPermissions) -> Result<(), KernelError> {
+        let pages = pages::get_page_pool();
+        table.change_permissions(permissions, self.start, self.page_aligned_len(), pages)?;
+        self.permissions = permissions;
+        Ok(())
+    }
+
+    /// Unmap the segment, and return the pages to be released after the TLBs have been flushed
+    pub fn unmap(&mut self, table: &mut TranslationTable) -> Result<Vec<OwnedPage>, KernelError> {
         let pages = pages::get_page_pool();
         table.unmap_range(self.start, self.page_aligned_len(), pages)
     }
 
+    pub fn is_writable(&self) -> bool {
+        self.permissions == MemoryPermissions::ReadWrite || self.permissions == MemoryPermissions::ReadWriteExecute
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.ops.is_shared()
+    }
+
     pub fn load_page_at(&self, table: &mut TranslationTable, vaddr: VirtualAddress) -> Result<PhysicalAddress, KernelError> {
         self.ops.load_page_at(&self, table, vaddr)
     }
 
+    pub fn mark_dirty(&self, vaddr: VirtualAddress) -> Result<(), KernelError> {
+        self.ops.mark_dirty(&self, vaddr)
+    }
+
+    pub fn sync(&self) -> Result<(), KernelError> {
+        self.ops.sync(&self)
+    }
+
     pub fn copy(&self, table: &mut TranslationTable, parent_table: &mut TranslationTable) -> Result<Self, KernelError> {
         let pages = pages::get_page_pool();
 
-        if self.permissions == MemoryPermissions::ReadWrite {
+        // Shared segments are also marked copy-on-write, so that writes to them can be tracked
+        if self.is_writable() {
             table.remap_range_copy_on_write(parent_table, self.start, self.page_aligned_len(), pages)?;
         } else  {
             table.duplicate_paged_range(parent_table, self.permissions, self.start, self.page_aligned_len(), pages)?;
@@ -87,34 +126,38 @@ impl Segment {
         Ok(Self::new(self.stype, self.permissions, self.start, self.end, self.ops.copy()))
     }
 
-    pub fn resize(&mut self, table: &mut TranslationTable, diff: isize) -> Result<(), KernelError> {
+    /// Grow or shrink the end of the segment, and return any pages to be released after the TLBs have been flushed
+    pub fn resize(&mut self, table: &mut TranslationTable, diff: isize) -> Result<Vec<OwnedPage>, KernelError> {
         let pages = pages::get_page_pool();
 
         if diff >= 0 {
             let aligned_diff = align_up(diff as usize, mmu::page_size());
             table.map_paged_range(MemoryType::Unallocated, self.permissions, self.end, aligned_diff, pages)?;
             self.end = self.end.add(aligned_diff);
+            Ok(Vec::new())
         } else {
             let aligned_diff = align_up((-1 * diff) as usize, mmu::page_size());
-            table.unmap_range(self.end.sub(aligned_diff), aligned_diff, pages)?;
+            let released = table.unmap_range(self.end.sub(aligned_diff), aligned_diff, pages)?;
             self.end = self.end.sub(aligned_diff);
+            Ok(released)
         }
-        Ok(())
     }
 
-    pub fn resize_stack(&mut self, table: &mut TranslationTable, diff: isize) -> Result<(), KernelError> {
+    /// Grow or shrink the start of the segment, and return any pages to be released after the TLBs have been flushed
+    pub fn resize_stack(&mut self, table: &mut TranslationTable, diff: isize) -> Result<Vec<OwnedPage>, KernelError> {
         let pages = pages::get_page_pool();
 
         if diff >= 0 {
             let aligned_diff = align_up(diff as usize, mmu::page_size());
             table.map_paged_range(MemoryType::Unallocated, self.permissions, self.start.sub(aligned_diff), aligned_diff, pages)?;
             self.start = self.start.sub(aligned_diff);
+            Ok(Vec::new())
         } else {
             let aligned_diff = align_up((-1 * diff) as usize, mmu::page_size());
-            table.unmap_range(self.start, aligned_diff, pages)?;
+            let released = table.unmap_range(self.start, aligned_diff, pages)?;
             self.start = self.start.add(aligned_diff);
+            Ok(released)
         }
-        Ok(())
     }
 }
 
@@ -134,11 +177,27 @@ impl SegmentOperations for MemorySegment {
         Box::new(self.clone())
     }
 
+    fn split(&self, _offset: usize) -> Box<dyn SegmentOperations> {
+        Box::new(self.clone())
+    }
+
+    fn is_shared(&self) -> bool {
+        false
+    }
+
     fn load_page_at(&self, _segment: &Segment, table: &mut TranslationTable, vaddr: VirtualAddress) -> Result<PhysicalAddress, KernelError> {
-        let pages = pages::get_page_pool();
-        let page = pages.alloc_page_zeroed();
-        table.update_page_addr(vaddr, page, pages).unwrap();
-        Ok(page)
+        let page = OwnedPage::alloc_zeroed()?;
+        let paddr = page.addr();
+        table.update_page_addr(vaddr, page, pages::get_page_pool()).unwrap();
+        Ok(paddr)
+    }
+
+    fn mark_dirty(&self, _segment: &Segment, _vaddr: VirtualAddress) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    fn sync(&self, _segment: &Segment) -> Result<(), KernelError> {
+        Ok(())
     }
 }
 
@@ -149,17 +208,23 @@ pub struct FileBackedSegment {
     file_offset: usize,
     file_limit: usize,
     mem_offset: usize,
+    shared: bool,
 }
 
 impl FileBackedSegment {
-    pub fn new(cache: Arc<PageCacheEntry>, file_offset: usize, file_size: usize, mem_offset: usize) -> Result<Self, KernelError> {
+    pub fn new(cache: Arc<PageCacheEntry>, file_offset: usize, file_size: usize, mem_offset: usize, shared: bool) -> Result<Self, KernelError> {
         Ok(Self {
             cache,
             file_offset,
             file_limit: align_up(file_offset + file_size, mmu::page_size()),
             mem_offset,
+            shared,
         })
     }
+
+    fn file_offset_of(&self, segment: &Segment, vaddr: VirtualAddress) -> usize {
+        usize::from(vaddr) - usize::from(segment.start) + self.file_offset - self.mem_offset
+    }
 }
 
 impl SegmentOperations for FileBackedSegment {
@@ -167,23 +232,46 @@ impl SegmentOperations for FileBackedSegment {
         Box::new(self.clone())
     }
 
+    fn split(&self, offset: usize) -> Box<dyn SegmentOperations> {
+        let mut ops = self.clone();
+        ops.file_offset += offset;
+        Box::new(ops)
+    }
+
+    fn is_shared(&self) -> bool {
+        self.shared
+    }
+
     fn load_page_at(&self, segment: &Segment, table: &mut TranslationTable, vaddr: VirtualAddress) -> Result<PhysicalAddress, KernelError> {
         let pages = pages::get_page_pool();
-        let offset = usize::from(vaddr) - usize::from(segment.start) - (self.mem_offset - self.file_offset);
+        let offset = self.file_offset_of(segment, vaddr);
 
-        if offset <= self.file_limit {
+        if offset < self.file_limit {
             let page = self.cache.lookup(offset)?;
+            let paddr = page.addr();
             table.update_page_addr(vaddr, page, pages).unwrap();
-            if segment.permissions == MemoryPermissions::ReadWrite {
+            // A private page is copied on the first write, and a shared page is marked dirty instead
+            if segment.is_writable() {
                 table.set_page_copy_on_write(vaddr).unwrap();
             }
-            Ok(page)
+            Ok(paddr)
         } else {
-            let pages = pages::get_page_pool();
-            let page = pages.alloc_page_zeroed();
+            let page = OwnedPage::alloc_zeroed()?;
+            let paddr = page.addr();
             table.update_page_addr(vaddr, page, pages).unwrap();
-            Ok(page)
+            Ok(paddr)
         }
     }
-}
 
+    fn mark_dirty(&self, segment: &Segment, vaddr: VirtualAddress) -> Result<(), KernelError> {
+        self.cache.mark_dirty(self.file_offset_of(segment, vaddr))
+    }
+
+    fn sync(&self, segment: &Segment) -> Result<(), KernelError> {
+        if self.shared {
+            let start = self.file_offset_of(segment, segment.start);
+            self.cache.writeback(start, start + segment.page_aligned_len())?;
+        }
+        Ok(())
+    }
+}
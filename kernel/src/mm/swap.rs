@@ -0,0 +1,312 @@
+
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::sync::{Arc, Weak};
+
+use ruxpin_types::{OpenFlags, FileAccess, DeviceID, Seek};
+
+use crate::{notice, error};
+use crate::block;
+use crate::fs::{self, File};
+use crate::smp;
+use crate::sync::Spinlock;
+use crate::arch::mmu;
+use crate::arch::PhysicalAddress;
+use crate::errors::KernelError;
+
+use super::pages::{self, OwnedPage};
+use super::vmalloc::{VirtualAddressSpace, SharableVirtualAddressSpace};
+
+
+pub type SwapSlot = usize;
+
+// Pages are swapped out when the free pages drop below the low mark, until they're back above the high mark
+const FREE_PAGES_LOW: usize = 128;
+const FREE_PAGES_HIGH: usize = 256;
+
+// The number of pages to swap out of each address space before moving on to the next one
+const SWAP_OUT_BATCH: usize = 16;
+
+enum SwapBacking {
+    Partition(DeviceID),
+    File(File),
+}
+
+struct SwapArea {
+    backing: SwapBacking,
+    enabled: bool,
+    refcounts: Vec<u16>,
+    used: usize,
+    last_slot: usize,
+    // Pages that were unmapped into a slot but haven't been written to it yet, which are swapped in from memory
+    pending: Vec<(SwapSlot, OwnedPage)>,
+}
+
+static SWAP_AREA: Spinlock<Option<SwapArea>> = Spinlock::new(None);
+static SPACES: Spinlock<Vec<Weak<Spinlock<VirtualAddressSpace>>>> = Spinlock::new(Vec::new());
+
+
+/// Use the given block device or regular file to swap out pages
+pub fn swapon(file: File) -> Result<(), KernelError> {
+    let stat = fs::fstat(file.clone())?;
+    let (backing, size) = match stat.access.file_type() {
+        FileAccess::BlockDevice => {
+            let device_id = stat.rdev.ok_or(KernelError::NoSuchDevice)?;
+            block::open(device_id, OpenFlags::ReadWrite)?;
+            (SwapBacking::Partition(device_id), block::get_size(device_id)? as usize)
+        },
+        FileAccess::Regular => (SwapBacking::File(file), stat.size),
+        _ => return Err(KernelError::InvalidArgument),
+    };
+
+    let total_slots = size / mmu::page_size();
+    if total_slots == 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+
+    let mut area = SWAP_AREA.try_lock()?;
+    if area.is_some() {
+        return Err(KernelError::InvalidArgument);
+    }
+    *area = Some(SwapArea::new(backing, total_slots));
+
+    notice!("swap: using {} KiB of swap", total_slots * mmu::page_size() / 1024);
+    Ok(())
+}
+
+/// Swap all pages back in from the given swap area and stop using it
+pub fn swapoff(file: File) -> Result<(), KernelError> {
+    {
+        let mut locked_area = SWAP_AREA.try_lock()?;
+        let area = locked_area.as_mut().ok_or(KernelError::InvalidArgument)?;
+        if !area.is_backed_by(file)? {
+            return Err(KernelError::InvalidArgument);
+        }
+        area.enabled = false;
+    }
+
+    for space in live_spaces() {
+        let result = space.try_lock()?.swap_in_all();
+        if let Err(err) = result {
+            // Some pages are still in swap, so it has to keep being used
+            enable_area();
+            return Err(err);
+        }
+    }
+
+    let area = {
+        let mut locked_area = SWAP_AREA.try_lock()?;
+        if locked_area.as_ref().map(|area| area.used).unwrap_or(0) != 0 {
+            // Slots are still referenced by address spaces that couldn't be swapped in
+            drop(locked_area);
+            enable_area();
+            return Err(KernelError::OutOfMemory);
+        }
+        locked_area.take()
+    };
+    if let Some(SwapArea { backing: SwapBacking::Partition(device_id), .. }) = area {
+        block::close(device_id)?;
+    }
+    notice!("swap: no longer swapping");
+    Ok(())
+}
+
+pub fn register_space(space: &SharableVirtualAddressSpace) {
+    SPACES.lock().push(Arc::downgrade(space));
+}
+
+/// Swap out the least recently used pages if the number of free pages is too low
+///
+/// This must be called without any address spaces locked, since it locks each one
+pub fn balance() {
+    let pages = pages::get_page_pool();
+    if pages.free_page_count() >= FREE_PAGES_LOW || !is_enabled() {
+        return;
+    }
+
+    let spaces = live_spaces();
+    loop {
+        // The evicted pages aren't freed until they're written out, so the free count can't be checked until then
+        let wanted = FREE_PAGES_HIGH.saturating_sub(pages.free_page_count());
+        let mut evicted = 0;
+        for space in spaces.iter() {
+            match space.try_lock().and_then(|mut locked_space| locked_space.swap_out_pages(SWAP_OUT_BATCH.min(wanted - evicted))) {
+                Ok(count) => evicted += count,
+                Err(err) => error!("swap: error while swapping out pages: {:?}", err),
+            }
+
+            if evicted >= wanted {
+                break;
+            }
+        }
+
+        // Other cpus can keep writing to the evicted pages through their TLBs until they've flushed them,
+        // so the pages can only be written out once every cpu has
+        smp::shootdown_tlb_and_wait();
+        if let Err(err) = write_pending_pages() {
+            error!("swap: error while writing out pages: {:?}", err);
+            break;
+        }
+
+        if evicted == 0 || pages.free_page_count() >= FREE_PAGES_HIGH {
+            break;
+        }
+    }
+}
+
+/// Reserve a new swap slot for a page that's about to be unmapped, or return None if there is no free slot
+///
+/// The page is written to the slot by `balance()` once it's no longer in the TLB of any cpu
+pub fn alloc_slot() -> Result<Option<SwapSlot>, KernelError> {
+    let mut locked_area = SWAP_AREA.try_lock()?;
+    match locked_area.as_mut() {
+        Some(area) if area.enabled => Ok(area.alloc_slot()),
+        _ => Ok(None),
+    }
+}
+
+/// Hold on to a page that was unmapped into the given slot until it can be written out
+pub fn swap_out(slot: SwapSlot, page: OwnedPage) -> Result<(), KernelError> {
+    let mut locked_area = SWAP_AREA.try_lock()?;
+    let area = locked_area.as_mut().ok_or(KernelError::CorruptTranslationTable)?;
+    area.pending.push((slot, page));
+    Ok(())
+}
+
+/// Read the page in the given slot into a new page, and release the slot
+pub fn swap_in(slot: SwapSlot) -> Result<OwnedPage, KernelError> {
+    let mut locked_area = SWAP_AREA.try_lock()?;
+    let area = locked_area.as_mut().ok_or(KernelError::CorruptTranslationTable)?;
+
+    let page = OwnedPage::alloc()?;
+    match area.pending.iter().find(|(pending, _)| *pending == slot) {
+        Some((_, pending_page)) => mmu::get_page_slice(page.addr()).copy_from_slice(mmu::get_page_slice(pending_page.addr())),
+        None => area.read_page(slot, page.addr())?,
+    }
+    area.release_slot(slot);
+    Ok(page)
+}
+
+pub fn ref_slot(slot: SwapSlot) {
+    if let Some(area) = SWAP_AREA.lock().as_mut() {
+        area.refcounts[slot] += 1;
+    }
+}
+
+pub fn free_slot(slot: SwapSlot) {
+    if let Some(area) = SWAP_AREA.lock().as_mut() {
+        area.release_slot(slot);
+    }
+}
+
+fn write_pending_pages() -> Result<(), KernelError> {
+    let mut locked_area = SWAP_AREA.try_lock()?;
+    let area = match locked_area.as_mut() {
+        Some(area) => area,
+        None => return Ok(()),
+    };
+
+    // The lock is held while writing, so the page can't be swapped in before its slot has been written
+    while let Some((slot, page)) = area.pending.pop() {
+        if let Err(err) = area.write_page(slot, page.addr()) {
+            // The page is kept, so that it can still be swapped in from memory
+            area.pending.push((slot, page));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+fn is_enabled() -> bool {
+    match SWAP_AREA.lock().as_ref() {
+        Some(area) => area.enabled,
+        None => false,
+    }
+}
+
+fn enable_area() {
+    if let Some(area) = SWAP_AREA.lock().as_mut() {
+        area.enabled = true;
+    }
+}
+
+fn live_spaces() -> Vec<SharableVirtualAddressSpace> {
+    let mut spaces = SPACES.lock();
+    spaces.retain(|space| space.strong_count() > 0);
+    spaces.iter().filter_map(|space| space.upgrade()).collect()
+}
+
+
+impl SwapArea {
+    fn new(backing: SwapBacking, total_slots: usize) -> Self {
+        Self {
+            backing,
+            enabled: true,
+            refcounts: vec![0; total_slots],
+            used: 0,
+            last_slot: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    fn is_backed_by(&self, file: File) -> Result<bool, KernelError> {
+        match &self.backing {
+            SwapBacking::Partition(device_id) => Ok(fs::fstat(file)?.rdev == Some(*device_id)),
+            SwapBacking::File(swap_file) => Ok(Arc::ptr_eq(&swap_file.lock().vnode, &file.lock().vnode)),
+        }
+    }
+
+    fn alloc_slot(&mut self) -> Option<SwapSlot> {
+        let total_slots = self.refcounts.len();
+        for i in 0..total_slots {
+            let slot = (self.last_slot + i) % total_slots;
+            if self.refcounts[slot] == 0 {
+                self.refcounts[slot] = 1;
+                self.used += 1;
+                self.last_slot = slot;
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    fn release_slot(&mut self, slot: SwapSlot) {
+        self.refcounts[slot] -= 1;
+        if self.refcounts[slot] == 0 {
+            self.used -= 1;
+            // A page waiting to be written isn't needed once nothing refers to its slot
+            self.pending.retain(|(pending, _)| *pending != slot);
+        }
+    }
+
+    fn read_page(&mut self, slot: SwapSlot, page: PhysicalAddress) -> Result<(), KernelError> {
+        let offset = slot * mmu::page_size();
+        let buffer = mmu::get_page_slice(page);
+        match &self.backing {
+            SwapBacking::Partition(device_id) => {
+                block::raw_read(*device_id, buffer, offset as u64)?;
+            },
+            SwapBacking::File(file) => {
+                fs::seek(file.clone(), offset as isize, Seek::FromStart)?;
+                fs::read(file.clone(), buffer)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn write_page(&mut self, slot: SwapSlot, page: PhysicalAddress) -> Result<(), KernelError> {
+        let offset = slot * mmu::page_size();
+        let buffer = mmu::get_page_slice(page);
+        match &self.backing {
+            SwapBacking::Partition(device_id) => {
+                block::raw_write(*device_id, buffer, offset as u64)?;
+            },
+            SwapBacking::File(file) => {
+                fs::seek(file.clone(), offset as isize, Seek::FromStart)?;
+                fs::write(file.clone(), buffer)?;
+            },
+        }
+        Ok(())
+    }
+}
+
@@ -1,31 +1,44 @@
 
 use alloc::vec::Vec;
 use alloc::sync::Arc;
+use alloc::collections::VecDeque;
 
+use crate::smp;
 use crate::trace;
-use crate::mm::pages;
+use crate::mm::pages::{self, OwnedPage};
 use crate::sync::Spinlock;
+use crate::misc::align_up;
 use crate::errors::KernelError;
 use crate::arch::mmu::{self, TranslationTable};
 use crate::arch::{VirtualAddress, PhysicalAddress};
 
 use super::MemoryPermissions;
+use super::swap;
+use super::kmalloc;
 use super::pagecache::{self, PageCacheEntry};
 use super::segments::{Segment, SegmentType};
 
 
-const MAX_SEGMENTS: usize = 6;
+/// The size of the unmapped region kept below each stack, so that an overflow faults instead of running into another segment
+pub const STACK_GUARD_SIZE: usize = 0x4000;
+
+/// Mappings without a fixed address are placed above the program and stack, starting from this address by default
+pub const MMAP_BASE: u64 = 0x10_0000_0000;
+pub(super) const USER_SPACE_END: u64 = 0x1_0000_0000_0000;
 
 static mut KERNEL_ADDRESS_SPACE: Option<SharableVirtualAddressSpace> = None;
 
 
 pub fn initialize(start: PhysicalAddress, end: PhysicalAddress) -> Result<(), KernelError> {
     pages::init_pages_pool(start, end);
+    kmalloc::enable_growth();
     pagecache::initialize()?;
 
     let space = VirtualAddressSpace {
         table: TranslationTable::initial_kernel_table(),
         segments: Vec::new(),
+        resident: VecDeque::new(),
+        mmap_base: VirtualAddress::from(MMAP_BASE),
     };
 
     unsafe {
@@ -39,6 +52,9 @@ pub type SharableVirtualAddressSpace = Arc<Spinlock<VirtualAddressSpace>>;
 pub struct VirtualAddressSpace {
     table: TranslationTable,
     segments: Vec<Segment>,
+    // The pages that have been faulted in, in the order they are checked for swapping out
+    resident: VecDeque<VirtualAddress>,
+    mmap_base: VirtualAddress,
 }
 
 impl VirtualAddressSpace {
@@ -48,24 +64,37 @@ impl VirtualAddressSpace {
         }
     }
 
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, KernelError> {
         let pages = pages::get_page_pool();
-        let table = TranslationTable::new_table(pages);
+        let table = TranslationTable::new_table(pages)?;
 
-        Self {
+        Ok(Self {
             table,
-            segments: Vec::with_capacity(MAX_SEGMENTS),
-        }
+            segments: Vec::new(),
+            resident: VecDeque::new(),
+            mmap_base: VirtualAddress::from(MMAP_BASE),
+        })
     }
 
-    pub fn new_sharable() -> SharableVirtualAddressSpace {
-        Arc::new(Spinlock::new(Self::new()))
+    pub fn new_sharable() -> Result<SharableVirtualAddressSpace, KernelError> {
+        let space = Arc::new(Spinlock::new(Self::new()?));
+        swap::register_space(&space);
+        Ok(space)
     }
 
     pub fn translate_addr(&mut self, vaddr: VirtualAddress) -> Result<PhysicalAddress, KernelError> {
         self.table.translate_addr(vaddr)
     }
 
+    /// Set the lowest address that mappings without a fixed address will be placed at
+    pub fn set_mmap_base(&mut self, base: VirtualAddress) -> Result<(), KernelError> {
+        if u64::from(base) >= USER_SPACE_END || usize::from(base) & (mmu::page_size() - 1) != 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+        self.mmap_base = base;
+        Ok(())
+    }
+
     pub fn add_memory_segment(&mut self, stype: SegmentType, permissions: MemoryPermissions, vaddr: VirtualAddress, len: usize) -> Result<(), KernelError> {
         let segment = Segment::new_memory(&mut self.table, stype, permissions, vaddr, vaddr.add(len))?;
 
@@ -73,25 +102,158 @@ impl VirtualAddressSpace {
         Ok(())
     }
 
-    pub fn add_file_backed_segment(&mut self, stype: SegmentType, permissions: MemoryPermissions, cache: Arc<PageCacheEntry>, file_offset: usize, file_size: usize, vaddr: VirtualAddress, mem_offset: usize, mem_size: usize) -> Result<(), KernelError> {
+    pub fn add_file_backed_segment(&mut self, stype: SegmentType, permissions: MemoryPermissions, cache: Arc<PageCacheEntry>, file_offset: usize, file_size: usize, vaddr: VirtualAddress, mem_offset: usize, mem_size: usize, shared: bool) -> Result<(), KernelError> {
         let vaddr_end = vaddr.add(mem_size).add(mem_offset).align_up(mmu::page_size());
-        let segment = Segment::new_file_backed(&mut self.table, stype, permissions, mem_offset, vaddr, vaddr_end, cache, file_offset, file_size)?;
+        let segment = Segment::new_file_backed(&mut self.table, stype, permissions, mem_offset, vaddr, vaddr_end, cache, file_offset, file_size, shared)?;
 
         self.insert_segment(segment);
         Ok(())
     }
 
+    pub fn map_anonymous(&mut self, hint: VirtualAddress, len: usize, permissions: MemoryPermissions, fixed: bool) -> Result<VirtualAddress, KernelError> {
+        let vaddr = self.find_mapping_addr(hint, len, fixed)?;
+        self.add_memory_segment(SegmentType::Mapping, permissions, vaddr, len)?;
+        Ok(vaddr)
+    }
+
+    pub fn map_file(&mut self, hint: VirtualAddress, len: usize, permissions: MemoryPermissions, fixed: bool, cache: Arc<PageCacheEntry>, file_offset: usize, shared: bool) -> Result<VirtualAddress, KernelError> {
+        let vaddr = self.find_mapping_addr(hint, len, fixed)?;
+        self.add_file_backed_segment(SegmentType::Mapping, permissions, cache, file_offset, len, vaddr, 0, len, shared)?;
+        Ok(vaddr)
+    }
+
+    pub fn unmap_range(&mut self, start: VirtualAddress, len: usize) -> Result<(), KernelError> {
+        let end = start.add(len);
+        self.split_segment_at(start);
+        self.split_segment_at(end);
+
+        let mut released = Vec::new();
+        let mut i = 0;
+        while i < self.segments.len() {
+            if self.segments[i].start >= start && self.segments[i].end <= end {
+                let mut segment = self.segments.remove(i);
+                released.extend(segment.unmap(&mut self.table)?);
+                segment.sync()?;
+            } else {
+                i += 1;
+            }
+        }
+
+        // Other threads of the process could be running on other cpus with the old mappings, so the pages
+        // can only be freed once the TLBs have been flushed
+        smp::shootdown_tlb();
+        drop(released);
+        pagecache::release_unused()
+    }
+
+    pub fn protect_range(&mut self, start: VirtualAddress, len: usize, permissions: MemoryPermissions) -> Result<(), KernelError> {
+        let end = start.add(len);
+        if !self.is_range_mapped(start, end) {
+            return Err(KernelError::AddressUnmapped);
+        }
+
+        self.split_segment_at(start);
+        self.split_segment_at(end);
+
+        for segment in self.segments.iter_mut() {
+            if segment.start >= start && segment.end <= end {
+                segment.change_permissions(&mut self.table, permissions)?;
+            }
+        }
+        smp::shootdown_tlb();
+        Ok(())
+    }
+
+    /// Write back the shared file mappings that overlap the given range
+    pub fn sync_range(&mut self, start: VirtualAddress, len: usize) -> Result<(), KernelError> {
+        let end = start.add(len);
+        for segment in &self.segments {
+            if segment.start < end && segment.end > start {
+                segment.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn find_mapping_addr(&mut self, hint: VirtualAddress, len: usize, fixed: bool) -> Result<VirtualAddress, KernelError> {
+        if u64::from(hint) + len as u64 > USER_SPACE_END {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        if fixed {
+            // A fixed mapping replaces anything that was already mapped in its range
+            self.unmap_range(hint, len)?;
+            Ok(hint)
+        } else if u64::from(hint) != 0 && self.is_range_free(hint, hint.add(len)) {
+            Ok(hint)
+        } else {
+            self.find_free_range(len)
+        }
+    }
+
+    fn find_free_range(&self, len: usize) -> Result<VirtualAddress, KernelError> {
+        let mut start = self.mmap_base;
+        for segment in &self.segments {
+            if segment.end <= start {
+                continue;
+            }
+            if start.add(len) <= segment.start {
+                break;
+            }
+            start = segment.end.align_up(mmu::page_size());
+        }
+
+        if u64::from(start) + len as u64 > USER_SPACE_END {
+            return Err(KernelError::OutOfMemory);
+        }
+        Ok(start)
+    }
+
+    fn is_range_free(&self, start: VirtualAddress, end: VirtualAddress) -> bool {
+        self.segments.iter().all(|segment| end <= lowest_reserved_addr(segment) || start >= segment.end)
+    }
+
+    fn is_stack_guard(&self, vaddr: VirtualAddress) -> bool {
+        self.segments.iter().any(|segment| segment.stype == SegmentType::Stack && vaddr >= lowest_reserved_addr(segment) && vaddr < segment.start)
+    }
+
+    fn is_range_mapped(&self, start: VirtualAddress, end: VirtualAddress) -> bool {
+        let mut next = start;
+        for segment in &self.segments {
+            if segment.match_range(next) {
+                next = segment.end;
+            }
+            if next >= end {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn split_segment_at(&mut self, vaddr: VirtualAddress) {
+        if let Some(i) = self.segments.iter().position(|segment| vaddr > segment.start && vaddr < segment.end) {
+            let upper = self.segments[i].split(vaddr);
+            self.segments.insert(i + 1, upper);
+        }
+    }
+
     fn insert_segment(&mut self, segment: Segment) {
         let i = self.segments.iter().position(|seg| segment.start < seg.start).unwrap_or(self.segments.len());
         self.segments.insert(i, segment);
     }
 
     pub fn clear_segments(&mut self) -> Result<(), KernelError> {
+        // The segments are synced after unmapping, so that the pages are no longer referenced and can be marked clean
+        let mut released = Vec::new();
         for i in 0..self.segments.len() {
-            self.segments[i].unmap(&mut self.table)?;
+            released.extend(self.segments[i].unmap(&mut self.table)?);
+            self.segments[i].sync()?;
         }
         self.segments.clear();
-        Ok(())
+        self.resident.clear();
+        smp::shootdown_tlb();
+        drop(released);
+        pagecache::release_unused()
     }
 
     pub fn copy_segments(&mut self, parent: &mut Self) -> Result<(), KernelError> {
@@ -100,33 +262,119 @@ impl VirtualAddressSpace {
             let new_segment = segment.copy(&mut self.table, &mut parent.table)?;
             self.segments.push(new_segment);
         }
+        self.resident = parent.resident.clone();
+        self.mmap_base = parent.mmap_base;
+
+        // The parent's pages are now copy-on-write, so its other threads must stop writing to them directly
+        smp::shootdown_tlb();
         Ok(())
     }
 
     pub fn adjust_stack_break(&mut self, increment: isize) -> Result<VirtualAddress, KernelError> {
         trace!("vmalloc: adjusting sbrk size by {}", increment);
         let segs = &mut self.segments;
-        let stack = segs.len() - 1;
-        let data = segs.len() - 2;
-
-        if segs.len() < 2 && segs[stack].stype != SegmentType::Stack || segs[data].stype != SegmentType::Data {
-            return Err(KernelError::NoSegmentFound);
-        }
+        let stack = segs.iter().position(|segment| segment.stype == SegmentType::Stack).ok_or(KernelError::NoSegmentFound)?;
+        let data = segs.iter().rposition(|segment| segment.stype == SegmentType::Data).ok_or(KernelError::NoSegmentFound)?;
 
         let previous_end = segs[data].end;
+        let mut released = Vec::new();
         if increment != 0 {
-            if
-                (increment >= 0 && segs[data].end.add(increment as usize) >= segs[stack].start) ||
-                (increment >= 0 && segs[data].end.sub((-1 * increment) as usize) >= segs[stack].start)
-            {
-                segs[stack].resize_stack(&mut self.table, -1 * increment)?;
+            // The stack is shrunk to make room for the data, but the guard region must be kept between them
+            let data_limit = segs[data].end.add(align_up(increment.max(0) as usize, mmu::page_size())).add(STACK_GUARD_SIZE);
+            if data_limit > segs[stack].start {
+                if data_limit >= segs[stack].end {
+                    return Err(KernelError::OutOfMemory);
+                }
+                let diff = usize::from(data_limit) - usize::from(segs[stack].start);
+                released.extend(segs[stack].resize_stack(&mut self.table, -1 * diff as isize)?);
             }
-            segs[data].resize(&mut self.table, increment)?;
+            released.extend(segs[data].resize(&mut self.table, increment)?);
+        }
+
+        if released.len() > 0 {
+            smp::shootdown_tlb();
+            drop(released);
         }
 
         Ok(previous_end)
     }
 
+    pub(crate) fn check_user_range(&self, vaddr: VirtualAddress, len: usize, write: bool) -> Result<(), KernelError> {
+        // The range can span more than one segment, since mappings can be split up by mprotect
+        let end = vaddr.add(len);
+        let mut next = vaddr;
+        for segment in &self.segments {
+            if segment.match_range(next) {
+                if write && !segment.is_writable() {
+                    return Err(KernelError::MemoryPermissionDenied);
+                }
+                next = segment.end;
+            }
+            if next >= end {
+                return Ok(());
+            }
+        }
+        Err(KernelError::AddressUnmapped)
+    }
+
+    /// Check that the user can access the given range, and load any of its pages that aren't in memory yet
+    pub(crate) fn fault_in_user_range(&mut self, vaddr: VirtualAddress, len: usize, write: bool) -> Result<(), KernelError> {
+        match u64::from(vaddr).checked_add(len as u64) {
+            Some(end) if end <= USER_SPACE_END => { },
+            _ => return Err(KernelError::AddressUnmapped),
+        }
+        self.check_user_range(vaddr, len, write)?;
+
+        let end = vaddr.add(len);
+        let mut page_vaddr = vaddr.align_down(mmu::page_size());
+        while page_vaddr < end {
+            if !self.table.set_page_accessed(page_vaddr)? {
+                self.alloc_page_at(page_vaddr)?;
+            }
+            page_vaddr = page_vaddr.add(mmu::page_size());
+        }
+        Ok(())
+    }
+
+    /// Copy data out of this address space, which doesn't need to be the current one
+    pub(crate) fn read_user_data(&mut self, vaddr: VirtualAddress, data: &mut [u8]) -> Result<(), KernelError> {
+        self.fault_in_user_range(vaddr, data.len(), false)?;
+        let mut copied = 0;
+        while copied < data.len() {
+            let (page_data, length) = self.page_data_at(vaddr.add(copied), data.len() - copied)?;
+            data[copied..copied + length].copy_from_slice(&page_data[..length]);
+            copied += length;
+        }
+        Ok(())
+    }
+
+    /// Copy data into this address space, which doesn't need to be the current one
+    pub(crate) fn write_user_data(&mut self, vaddr: VirtualAddress, data: &[u8]) -> Result<(), KernelError> {
+        self.fault_in_user_range(vaddr, data.len(), true)?;
+        let mut copied = 0;
+        while copied < data.len() {
+            let current = vaddr.add(copied);
+            // The page has to be copied before it's written to, just as if the program had written to it
+            if self.table.is_page_copy_on_write(current) {
+                self.copy_on_write_at(current)?;
+            }
+
+            let (page_data, length) = self.page_data_at(current, data.len() - copied)?;
+            page_data[..length].copy_from_slice(&data[copied..copied + length]);
+            copied += length;
+        }
+        Ok(())
+    }
+
+    // Returns the mapped page's data from the given address to the end of the page, and the number of bytes of it to use
+    fn page_data_at(&self, vaddr: VirtualAddress, remaining: usize) -> Result<(&'static mut [u8], usize), KernelError> {
+        let offset = vaddr.offset_from_align(mmu::page_size());
+        let page = self.table.translate_addr(vaddr.align_down(mmu::page_size()))?;
+        let page_data = &mut mmu::get_page_slice(page)[offset..];
+        let length = remaining.min(page_data.len());
+        Ok((page_data, length))
+    }
+
     pub(crate) fn get_ttbr(&self) -> u64 {
         self.table.get_ttbr()
     }
@@ -134,36 +382,117 @@ impl VirtualAddressSpace {
     pub(crate) fn alloc_page_at(&mut self, fault_addr: VirtualAddress) -> Result<(), KernelError> {
         let page_vaddr = fault_addr.align_down(mmu::page_size());
 
+        if let Some(slot) = self.table.get_page_swapped(page_vaddr) {
+            let page = swap::swap_in(slot)?;
+            self.table.update_page_addr(page_vaddr, page, pages::get_page_pool())?;
+            self.resident.push_back(page_vaddr);
+            return Ok(());
+        }
+
+        // The page is already mapped but its access flag was cleared while checking for pages to swap out
+        if self.table.set_page_accessed(page_vaddr)? {
+            return Ok(());
+        }
+
         for segment in &self.segments {
             if segment.match_range(fault_addr) {
                 segment.load_page_at(&mut self.table, page_vaddr)?;
+                self.resident.push_back(page_vaddr);
                 return Ok(());
             }
         }
 
+        if self.is_stack_guard(fault_addr) {
+            return Err(KernelError::StackOverflow);
+        }
         Err(KernelError::NoSegmentFound)
     }
 
+    /// Swap out up to the given number of pages that haven't been accessed recently, and return how many were swapped out
+    ///
+    /// The pages are only unmapped here, and aren't written out or freed until `swap::balance()` has flushed the TLBs
+    pub(crate) fn swap_out_pages(&mut self, count: usize) -> Result<usize, KernelError> {
+        let pages = pages::get_page_pool();
+
+        // Each page's access flag is cleared when it's checked, so that a page that's still in use will be skipped
+        // the first time and accessed again before it's next checked, like a clock algorithm
+        let mut swapped = 0;
+        let mut remaining = self.resident.len() * 2;
+        while swapped < count && remaining > 0 {
+            remaining -= 1;
+            let vaddr = match self.resident.pop_front() {
+                Some(vaddr) => vaddr,
+                None => break,
+            };
+
+            match self.table.reset_page_accessed(vaddr) {
+                Ok(true) => { },
+                Ok(false) => {
+                    let page = self.table.translate_addr(vaddr)?;
+                    // Only pages that aren't shared with another process or the page cache can be swapped out
+                    if pages.get_ref_count(page) == 1 {
+                        match swap::alloc_slot()? {
+                            Some(slot) => {
+                                // The table's reference is passed to the swap area, which frees the page once it's written out
+                                let page = self.table.set_page_swapped(vaddr, slot)?;
+                                swap::swap_out(slot, page)?;
+                                swapped += 1;
+                                continue;
+                            },
+                            None => {
+                                self.resident.push_back(vaddr);
+                                break;
+                            },
+                        }
+                    }
+                },
+                // The page was unmapped since it was faulted in
+                Err(_) => continue,
+            }
+            self.resident.push_back(vaddr);
+        }
+        Ok(swapped)
+    }
+
+    /// Read all swapped out pages back into memory
+    pub(crate) fn swap_in_all(&mut self) -> Result<(), KernelError> {
+        let swapped = self.table.find_swapped_pages(VirtualAddress::from(0), USER_SPACE_END as usize)?;
+        for (vaddr, slot) in swapped {
+            let page = swap::swap_in(slot)?;
+            self.table.update_page_addr(vaddr, page, pages::get_page_pool())?;
+            self.resident.push_back(vaddr);
+        }
+        Ok(())
+    }
+
     pub(crate) fn copy_on_write_at(&mut self, fault_addr: VirtualAddress) -> Result<(), KernelError> {
         let page_vaddr = fault_addr.align_down(mmu::page_size());
+        let segment = match self.segments.iter().find(|segment| segment.match_range(fault_addr)) {
+            Some(segment) if segment.is_writable() => segment,
+            _ => return Err(KernelError::MemoryPermissionDenied),
+        };
+
+        if segment.is_shared() {
+            // Shared pages are written to directly, and only need to be marked dirty on the first write
+            self.table.reset_page_copy_on_write(page_vaddr)?;
+            return segment.mark_dirty(page_vaddr);
+        }
+
         let (page, previous_cow) = self.table.reset_page_copy_on_write(page_vaddr)?;
         if previous_cow {
             trace!("copying page on write {:?}", page);
             let pages = pages::get_page_pool();
 
-            // Allocate new page and map it in the current address space
-            let new_page = pages.alloc_page_zeroed();
-            self.table.update_page_addr(page_vaddr, new_page, pages)?;
-
             // Copy data into new page
+            let new_page = OwnedPage::alloc()?;
             let page_buffer = mmu::get_page_slice(page);
-            let new_page_buffer = mmu::get_page_slice(new_page);
+            let new_page_buffer = mmu::get_page_slice(new_page.addr());
             for i in 0..page_buffer.len() {
                 new_page_buffer[i] = page_buffer[i];
             }
 
-            // Decrement the page reference
-            pages.free_page(page);
+            // Map the new page in the current address space, which releases this table's reference to the old page
+            self.table.update_page_addr(page_vaddr, new_page, pages)?;
 
             Ok(())
         } else {
@@ -172,3 +501,11 @@ impl VirtualAddressSpace {
     }
 }
 
+fn lowest_reserved_addr(segment: &Segment) -> VirtualAddress {
+    if segment.stype == SegmentType::Stack {
+        VirtualAddress::from(usize::from(segment.start).saturating_sub(STACK_GUARD_SIZE) as u64)
+    } else {
+        segment.start
+    }
+}
+
@@ -0,0 +1,87 @@
+
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::sync::Spinlock;
+use crate::arch::VirtualAddress;
+use crate::errors::KernelError;
+
+use super::scheduler;
+use super::wait::WaitQueue;
+
+
+// Futexes are identified by the address space and the virtual address of the futex word
+#[derive(Copy, Clone, PartialEq)]
+struct FutexKey {
+    space: usize,
+    address: VirtualAddress,
+}
+
+struct Futex {
+    key: FutexKey,
+    queue: Arc<WaitQueue>,
+}
+
+static FUTEXES: Spinlock<Vec<Futex>> = Spinlock::new(Vec::new());
+
+
+/// Suspend the current task if the futex word still has the expected value, until woken by `wake()`
+pub fn wait(futex: &AtomicU32, expected: u32) -> Result<(), KernelError> {
+    let key = get_key(futex)?;
+
+    // The lock is held from checking the value until the task is suspended, so that a `wake()` on another
+    // cpu either happens before the check, or finds the task blocked in the queue
+    let mut futexes = FUTEXES.try_lock()?;
+    if futex.load(Ordering::SeqCst) != expected {
+        return Err(KernelError::TryAgain);
+    }
+
+    let queue = match futexes.iter().find(|entry| entry.key == key) {
+        Some(entry) => entry.queue.clone(),
+        None => {
+            let queue = Arc::new(WaitQueue::new());
+            futexes.push(Futex { key, queue: queue.clone() });
+            queue
+        },
+    };
+
+    queue.wait();
+    Ok(())
+}
+
+/// Wake up to `count` tasks waiting on the futex, and return the number woken
+pub fn wake(futex: &AtomicU32, count: usize) -> Result<usize, KernelError> {
+    let key = get_key(futex)?;
+
+    let mut futexes = FUTEXES.try_lock()?;
+    let queue = match futexes.iter().find(|entry| entry.key == key) {
+        Some(entry) => entry.queue.clone(),
+        None => return Ok(0),
+    };
+
+    let mut woken = 0;
+    while woken < count && queue.wake_one() {
+        woken += 1;
+    }
+
+    if queue.is_empty() {
+        futexes.retain(|entry| entry.key != key);
+    }
+    Ok(woken)
+}
+
+fn get_key(futex: &AtomicU32) -> Result<FutexKey, KernelError> {
+    let address = VirtualAddress::from(futex as *const AtomicU32 as u64);
+    if usize::from(address) & 0x3 != 0 {
+        return Err(KernelError::AddressMisaligned);
+    }
+
+    let current = scheduler::get_current();
+    let locked_task = current.try_lock()?;
+    locked_task.space.try_lock()?.check_user_range(address, 4, false)?;
+    Ok(FutexKey {
+        space: Arc::as_ptr(&locked_task.space) as usize,
+        address,
+    })
+}
@@ -0,0 +1,197 @@
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use ruxpin_types::Tid;
+
+use crate::arch;
+use crate::time;
+use crate::sync::Spinlock;
+use crate::mm::kstack::KernelStack;
+use crate::errors::KernelError;
+
+use super::scheduler::{self, Task};
+use super::tasks::TaskState;
+
+
+const KTHREAD_STACK_SIZE: usize = 0x1_0000;
+
+// The requests a kernel thread makes to the scheduler by trapping with its number in x0
+const REQUEST_SLEEP: usize = 0;
+const REQUEST_YIELD: usize = 1;
+const REQUEST_EXIT: usize = 2;
+
+type ThreadFunc = Box<dyn FnOnce() + Send>;
+
+struct KThreadData {
+    task: Spinlock<Option<Task>>,
+    func: Spinlock<Option<ThreadFunc>>,
+    // Set when the thread is woken while it's still running, so that it doesn't miss the wake up when it next sleeps
+    wake_pending: Spinlock<bool>,
+    stop: AtomicBool,
+    exited: AtomicBool,
+    // The stack can't be freed until the thread has switched off of it, when the thread is removed from the list
+    _stack: KernelStack,
+}
+
+unsafe impl Sync for KThreadData {}
+
+static KTHREADS: Spinlock<Vec<Arc<KThreadData>>> = Spinlock::new(Vec::new());
+
+
+/// A handle to a task that runs a function inside the kernel on its own stack
+///
+/// Kernel threads run with interrupts enabled, so they can be preempted at any point except while holding a
+/// spinlock, which disables interrupts until it's released.  They must not sleep or yield while holding one.
+/// The thread isn't stopped when the handle is dropped.
+pub struct KThread {
+    data: Arc<KThreadData>,
+}
+
+/// Create a kernel thread that runs the given function, and which will appear as a process with the given name
+pub fn spawn<F>(name: &str, func: F) -> Result<KThread, KernelError>
+where
+    F: FnOnce() + Send + 'static
+{
+    let stack = KernelStack::alloc(KTHREAD_STACK_SIZE)?;
+    let stack_top = stack.top();
+
+    let data = Arc::new(KThreadData {
+        task: Spinlock::new(None),
+        func: Spinlock::new(Some(Box::new(func))),
+        wake_pending: Spinlock::new(false),
+        stop: AtomicBool::new(false),
+        exited: AtomicBool::new(false),
+        _stack: stack,
+    });
+    KTHREADS.try_lock()?.push(data.clone());
+
+    // The thread can start running on another cpu before this returns, so the data is passed to it directly
+    let arg = Arc::into_raw(data.clone()) as usize;
+    let task = scheduler::create_kernel_thread(name, kthread_start, arg, stack_top)?;
+    *data.task.try_lock()? = Some(task);
+
+    Ok(KThread { data })
+}
+
+impl KThread {
+    pub fn task_id(&self) -> Option<Tid> {
+        self.data.task.lock().as_ref().map(|task| task.lock().task_id)
+    }
+
+    /// Wake the thread if it's sleeping, or make its next sleep return immediately if it isn't
+    pub fn wake(&self) {
+        self.data.wake();
+    }
+
+    /// Ask the thread to stop, which it must check for using `should_stop()` and then return from its function
+    pub fn stop(&self) {
+        self.data.stop.store(true, Ordering::Release);
+        self.data.wake();
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.data.exited.load(Ordering::Acquire)
+    }
+}
+
+impl KThreadData {
+    fn wake(&self) {
+        let mut wake_pending = self.wake_pending.lock();
+        let task = match self.task.lock().clone() {
+            Some(task) => task,
+            None => {
+                *wake_pending = true;
+                return;
+            },
+        };
+
+        if task.lock().state == TaskState::Blocked {
+            scheduler::wake_tasks(vec![task]);
+        } else {
+            *wake_pending = true;
+        }
+    }
+}
+
+
+/// Put the current kernel thread to sleep until it's woken
+pub fn sleep() {
+    arch::kernel_thread_request(REQUEST_SLEEP);
+}
+
+/// Put the current kernel thread to sleep until it's woken or the given number of nanoseconds have passed
+pub fn sleep_for(duration_ns: u64) {
+    let data = match current_data() {
+        Some(data) => data,
+        None => return,
+    };
+
+    let timer = time::add_timer(time::get_uptime_ns() + duration_ns, move || data.wake());
+    sleep();
+    time::cancel_timer(timer);
+}
+
+/// Let the other tasks on this cpu run before the current kernel thread continues
+pub fn yield_now() {
+    arch::kernel_thread_request(REQUEST_YIELD);
+}
+
+/// Returns true if the current kernel thread has been asked to stop
+pub fn should_stop() -> bool {
+    current_data().map(|data| data.stop.load(Ordering::Acquire)).unwrap_or(false)
+}
+
+extern "C" fn kthread_start(arg: usize) -> ! {
+    let data = unsafe { Arc::from_raw(arg as *const KThreadData) };
+    *data.task.lock() = Some(scheduler::get_current());
+    let func = data.func.lock().take();
+    drop(data);
+
+    if let Some(func) = func {
+        func();
+    }
+
+    arch::kernel_thread_request(REQUEST_EXIT);
+    unreachable!();
+}
+
+/// Handle a request from the current kernel thread, after its context has been saved
+pub(crate) fn handle_request(request: usize) {
+    let current = scheduler::get_current();
+    let data = match current_data() {
+        Some(data) => data,
+        None => return,
+    };
+
+    match request {
+        REQUEST_SLEEP => {
+            // The lock is held until the task is suspended, so it can't be woken in between
+            let mut wake_pending = data.wake_pending.lock();
+            if *wake_pending {
+                *wake_pending = false;
+            } else {
+                scheduler::suspend(current);
+            }
+        },
+        REQUEST_YIELD => {
+            scheduler::schedule();
+        },
+        REQUEST_EXIT => {
+            data.exited.store(true, Ordering::Release);
+            KTHREADS.lock().retain(|thread| !Arc::ptr_eq(thread, &data));
+            scheduler::exit_kernel_thread(current);
+        },
+        _ => { },
+    }
+}
+
+fn current_data() -> Option<Arc<KThreadData>> {
+    let current = scheduler::get_current();
+    KTHREADS.lock().iter().find(|data| {
+        data.task.lock().as_ref().map(|task| Arc::ptr_eq(task, &current)).unwrap_or(false)
+    }).cloned()
+}
@@ -1,4 +1,9 @@
  
 pub mod tasks;
 pub mod scheduler;
+pub mod policy;
+pub mod signals;
+pub mod wait;
+pub mod futex;
+pub mod kthread;
 
@@ -0,0 +1,209 @@
+
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+
+use crate::misc::queue::Queue;
+
+use super::scheduler::Task;
+use super::tasks::TaskRecord;
+
+
+pub type Nice = i32;
+
+/// The nice value with the highest priority
+pub const NICE_MIN: Nice = -20;
+/// The nice value with the lowest priority
+pub const NICE_MAX: Nice = 19;
+
+const NICE_LEVELS: usize = (NICE_MAX - NICE_MIN + 1) as usize;
+
+/// A policy for choosing which of the runnable tasks should be running
+///
+/// The scheduler tells the policy when tasks become runnable or stop being runnable, and when a time slice has
+/// expired, and the policy decides which task is the current one.  The nice value of a task is only changed
+/// while it isn't in the policy's run queues.
+pub trait SchedulerPolicy: Send + Sync {
+    /// Add a task that's ready to run.  A task that was just woken up can be run right away if the policy allows it
+    fn add(&mut self, task: Task, woken: bool);
+
+    /// Remove a task that's no longer ready to run, including the current task
+    fn remove(&mut self, task: Task);
+
+    /// Returns the task that should be running
+    fn current(&self) -> Option<Task>;
+
+    /// Account for the time slice the current task has used, and choose the task to run next
+    fn tick(&mut self);
+
+    /// Returns the number of tasks that are ready to run, which is used to balance the tasks between cpus
+    fn count(&self) -> usize;
+}
+
+
+/// Always runs the tasks with the highest priority, and switches between tasks with the same priority on every tick
+pub struct PriorityPolicy {
+    queues: Vec<Queue<TaskRecord>>,
+    count: usize,
+}
+
+impl PriorityPolicy {
+    pub fn new() -> Self {
+        Self {
+            queues: (0..NICE_LEVELS).map(|_| Queue::new(None)).collect(),
+            count: 0,
+        }
+    }
+
+    fn queue_for(&mut self, task: &Task) -> &mut Queue<TaskRecord> {
+        let level = (task.try_lock().unwrap().nice - NICE_MIN) as usize;
+        &mut self.queues[level]
+    }
+
+    fn highest_queue(&mut self) -> Option<&mut Queue<TaskRecord>> {
+        self.queues.iter_mut().find(|queue| queue.get_head().is_some())
+    }
+}
+
+impl SchedulerPolicy for PriorityPolicy {
+    fn add(&mut self, task: Task, woken: bool) {
+        self.count += 1;
+        let queue = self.queue_for(&task);
+        if woken {
+            queue.insert_head(task);
+        } else {
+            queue.insert_tail(task);
+        }
+    }
+
+    fn remove(&mut self, task: Task) {
+        self.count -= 1;
+        self.queue_for(&task).remove_node(task);
+    }
+
+    fn current(&self) -> Option<Task> {
+        self.queues.iter().find_map(|queue| queue.get_head())
+    }
+
+    fn tick(&mut self) {
+        if let Some(queue) = self.highest_queue() {
+            let current = queue.get_head().unwrap();
+            queue.remove_node(current.clone());
+            queue.insert_tail(current);
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
+
+// The share of the cpu given to each nice value, where each level gets about 25% more than the next level.  These are the same as Linux uses
+const NICE_WEIGHTS: [u64; NICE_LEVELS] = [
+    88761, 71755, 56483, 46273, 36291,
+    29154, 23254, 18705, 14949, 11916,
+    9548, 7620, 6100, 4904, 3906,
+    3121, 2501, 1991, 1586, 1277,
+    1024, 820, 655, 526, 423,
+    335, 272, 215, 172, 137,
+    110, 87, 70, 56, 45,
+    36, 29, 23, 18, 15,
+];
+
+const NICE_0_WEIGHT: u64 = 1024;
+
+// The virtual runtime that a task with a nice value of 0 is charged for each tick
+const TICK_RUNTIME: u64 = 1_000_000;
+
+/// Shares the cpu between tasks in proportion to the weights of their nice values
+///
+/// Each task is charged virtual runtime for the ticks it runs, which is scaled down for higher priority tasks,
+/// and the task that has run the least is chosen to run next.  Tasks that have been waiting get a small credit
+/// when they wake up, so that interactive tasks are run ahead of tasks that use all of their time slices.
+pub struct FairPolicy {
+    // The current task is always at the head of the queue
+    queue: Queue<TaskRecord>,
+    min_vruntime: u64,
+    count: usize,
+}
+
+impl FairPolicy {
+    pub fn new() -> Self {
+        Self {
+            queue: Queue::new(None),
+            min_vruntime: 0,
+            count: 0,
+        }
+    }
+
+    fn find_next(&mut self) -> Option<(Task, u64)> {
+        let mut next: Option<(Task, u64)> = None;
+        for task in self.queue.iter() {
+            let vruntime = task.try_lock().unwrap().vruntime;
+            if next.as_ref().map(|(_, lowest)| vruntime < *lowest).unwrap_or(true) {
+                next = Some((task, vruntime));
+            }
+        }
+        next
+    }
+
+    fn choose_next(&mut self) {
+        if let Some((next, vruntime)) = self.find_next() {
+            self.min_vruntime = self.min_vruntime.max(vruntime);
+            if !Arc::ptr_eq(&next, &self.queue.get_head().unwrap()) {
+                self.queue.remove_node(next.clone());
+                self.queue.insert_head(next);
+            }
+        }
+    }
+}
+
+impl SchedulerPolicy for FairPolicy {
+    fn add(&mut self, task: Task, woken: bool) {
+        self.count += 1;
+        let vruntime = {
+            // A task can't save up runtime while it's not running, so it starts from the lowest runtime of the others
+            let mut locked_task = task.try_lock().unwrap();
+            let start = if woken { self.min_vruntime.saturating_sub(TICK_RUNTIME) } else { self.min_vruntime };
+            locked_task.vruntime = locked_task.vruntime.max(start);
+            locked_task.vruntime
+        };
+
+        let preempt = match self.queue.get_head() {
+            Some(current) => woken && vruntime < current.try_lock().unwrap().vruntime,
+            None => true,
+        };
+
+        if preempt {
+            self.queue.insert_head(task);
+        } else {
+            self.queue.insert_tail(task);
+        }
+    }
+
+    fn remove(&mut self, task: Task) {
+        self.count -= 1;
+        let was_current = self.queue.get_head().map(|current| Arc::ptr_eq(&current, &task)).unwrap_or(false);
+        self.queue.remove_node(task);
+        if was_current {
+            self.choose_next();
+        }
+    }
+
+    fn current(&self) -> Option<Task> {
+        self.queue.get_head()
+    }
+
+    fn tick(&mut self) {
+        if let Some(current) = self.queue.get_head() {
+            let mut locked_current = current.try_lock().unwrap();
+            let weight = NICE_WEIGHTS[(locked_current.nice - NICE_MIN) as usize];
+            locked_current.vruntime += TICK_RUNTIME * NICE_0_WEIGHT / weight;
+        }
+        self.choose_next();
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
@@ -1,35 +1,56 @@
 
+use core::sync::atomic::{AtomicU32, Ordering};
 use alloc::vec::Vec;
+use alloc::sync::Arc;
+use alloc::boxed::Box;
 
-use ruxpin_types::{Tid, Pid};
-use ruxpin_syscall::{SyscallFunction};
+use ruxpin_types::{Tid, Pid, Signal, WaitStatus, ApiError};
 
 use crate::api;
 use crate::info;
+use crate::smp::{self, PerCpu, MAX_CPUS};
 use crate::errors::KernelError;
 use crate::arch::{self, Context, VirtualAddress};
-use crate::misc::queue::{Queue, QueueNode, QueueNodeRef};
+use crate::misc::queue::{QueueNode, QueueNodeRef};
 use crate::sync::Spinlock;
 
 use super::tasks::{TaskCloneArgs, TaskState, TaskRecord};
+use super::policy::{SchedulerPolicy, Nice, NICE_MIN, NICE_MAX};
 
 
 pub type Task = QueueNodeRef<TaskRecord>;
 
+/// The default time in microseconds that a task runs for before the timer interrupts it to schedule the next task
+pub const DEFAULT_TIME_SLICE: u32 = 20_000;
+
+/// A function that creates a new instance of the scheduler policy for a cpu
+pub type NewPolicy = fn() -> Box<dyn SchedulerPolicy>;
+
 struct TaskManager {
     tasks: Vec<QueueNodeRef<TaskRecord>>,
-    scheduled: Queue<TaskRecord>,
-    blocked: Queue<TaskRecord>,
+    // Each cpu has its own run queues, and a task only runs on the cpu whose queues it was added to
+    policies: PerCpu<Option<Box<dyn SchedulerPolicy>>>,
+    new_policy: Option<NewPolicy>,
+    // The idle tasks aren't in the policies' queues, and only run when a cpu has no other task to run
+    idle_tasks: PerCpu<Option<Task>>,
 }
 
+const NO_POLICY: Option<Box<dyn SchedulerPolicy>> = None;
+const NO_IDLE_TASK: Option<Task> = None;
+
 static TASK_MANAGER: Spinlock<TaskManager> = Spinlock::new(TaskManager::new());
 
-pub fn initialize() -> Result<(), KernelError> {
-    TASK_MANAGER.lock().create_kernel_task("idle", idle_task)?;
+static TIME_SLICE: AtomicU32 = AtomicU32::new(DEFAULT_TIME_SLICE);
+
+/// Start scheduling tasks on the boot core, using a policy created by the given function on each cpu to choose which task to run
+pub fn initialize(new_policy: NewPolicy) -> Result<(), KernelError> {
+    TASK_MANAGER.lock().new_policy = Some(new_policy);
+    start_cpu()
+}
 
-    // NOTE this ensures the context is set before we start multitasking
-    TASK_MANAGER.lock().schedule();
-    Ok(())
+/// Start scheduling tasks on the current cpu
+pub(crate) fn start_cpu() -> Result<(), KernelError> {
+    TASK_MANAGER.try_lock()?.start_cpu()
 }
 
 fn idle_task() {
@@ -41,27 +62,121 @@ impl TaskManager {
     const fn new() -> Self {
         Self {
             tasks: Vec::new(),
-            scheduled: Queue::new(None),
-            blocked: Queue::new(None),
+            policies: PerCpu::new([NO_POLICY; MAX_CPUS]),
+            new_policy: None,
+            idle_tasks: PerCpu::new([NO_IDLE_TASK; MAX_CPUS]),
+        }
+    }
+
+    fn start_cpu(&mut self) -> Result<(), KernelError> {
+        let new_policy = self.new_policy.expect("scheduler: the scheduler hasn't been initialized");
+        *self.policies.get_mut() = Some(new_policy());
+
+        // The idle task is kept out of the policy, so that it doesn't take any share of the cpu from the other tasks
+        let idle = self.create_idle_task(smp::cpu_id())?;
+        *self.idle_tasks.get_mut() = Some(idle);
+
+        // NOTE this ensures the context is set before we start multitasking
+        self.set_current_context();
+        Ok(())
+    }
+
+    fn policy(&mut self) -> &mut dyn SchedulerPolicy {
+        self.policy_for(smp::cpu_id())
+    }
+
+    fn policy_for(&mut self, cpu: usize) -> &mut dyn SchedulerPolicy {
+        self.policies.get_for_mut(cpu).as_deref_mut().expect("scheduler: the scheduler hasn't been started on this cpu")
+    }
+
+    fn choose_cpu(&mut self) -> usize {
+        // New tasks go to the cpu with the fewest runnable tasks, preferring the current cpu
+        let current_cpu = smp::cpu_id();
+        let mut chosen = (current_cpu, self.policy().count());
+        for cpu in 0..MAX_CPUS {
+            if let Some(policy) = self.policies.get_for(cpu) {
+                if policy.count() < chosen.1 {
+                    chosen = (cpu, policy.count());
+                }
+            }
         }
+        chosen.0
+    }
+
+    fn enqueue(&mut self, task: Task, woken: bool) {
+        let cpu = task.try_lock().unwrap().cpu;
+        let previous = self.policy_for(cpu).current();
+        self.policy_for(cpu).add(task, woken);
+        self.reschedule_if_changed(cpu, previous);
     }
 
-    pub fn create_task(&mut self, parent: Option<Task>) -> Task {
-        let task = QueueNode::new(TaskRecord::new(parent));
+    fn dequeue(&mut self, task: Task) {
+        let cpu = task.try_lock().unwrap().cpu;
+        let previous = self.policy_for(cpu).current();
+        self.policy_for(cpu).remove(task);
+        self.reschedule_if_changed(cpu, previous);
+    }
+
+    fn reschedule_if_changed(&mut self, cpu: usize, previous: Option<Task>) {
+        // The current cpu switches tasks before returning from the exception, but other cpus must be interrupted to switch
+        if cpu != smp::cpu_id() {
+            let changed = match (previous, self.policy_for(cpu).current()) {
+                (Some(previous), Some(current)) => !Arc::ptr_eq(&previous, &current),
+                (None, None) => false,
+                _ => true,
+            };
+
+            if changed {
+                smp::reschedule_cpu(cpu);
+            }
+        }
+    }
+
+    pub fn create_task(&mut self, parent: Option<Task>) -> Result<Task, KernelError> {
+        let task = QueueNode::new(TaskRecord::new(parent)?);
+        self.add_task(task.clone());
+        Ok(task)
+    }
+
+    fn add_task(&mut self, task: Task) {
+        let cpu = self.choose_cpu();
+        task.try_lock().unwrap().cpu = cpu;
         self.tasks.push(task.clone());
-        self.scheduled.insert_tail(task.clone());
-        task
+        self.enqueue(task, false);
     }
 
-    fn create_kernel_task(&mut self, name: &str, entry: fn()) -> Result<(), KernelError> {
-        let task = QueueNode::new(TaskRecord::initial_kernel_task(name));
+    fn create_idle_task(&mut self, cpu: usize) -> Result<Task, KernelError> {
+        let task = QueueNode::new(TaskRecord::initial_kernel_task("idle"));
+        {
+            let mut locked_task = task.try_lock()?;
+            locked_task.nice = NICE_MAX;
+            locked_task.cpu = cpu;
+            let ttbr = locked_task.space.try_lock()?.get_ttbr();
+            locked_task.context.init_kernel_context(idle_task, VirtualAddress::from(0), ttbr);
+        }
+
+        // The task is listed with the others, but isn't added to the policy's queues
         self.tasks.push(task.clone());
-        self.scheduled.insert_tail(task.clone());
+        Ok(task)
+    }
 
-        let mut locked_task = task.try_lock()?;
-        let ttbr = locked_task.space.try_lock()?.get_ttbr();
-        locked_task.context.init_kernel_context(entry, VirtualAddress::from(0), ttbr);
-        Ok(())
+    fn create_kernel_thread(&mut self, name: &str, entry: extern "C" fn(usize) -> !, arg: usize, stack: VirtualAddress) -> Result<Task, KernelError> {
+        let task = QueueNode::new(TaskRecord::initial_kernel_task(name));
+        {
+            let mut locked_task = task.try_lock()?;
+            let ttbr = locked_task.space.try_lock()?.get_ttbr();
+            locked_task.context.init_kernel_thread_context(entry, arg, stack, ttbr);
+        }
+
+        self.add_task(task.clone());
+        Ok(task)
+    }
+
+    fn exit_kernel_thread(&mut self, task: Task) {
+        self.detach(task.clone());
+
+        // Kernel threads aren't waited on, so the record is removed immediately
+        self.tasks.retain(|thread| !Arc::ptr_eq(thread, &task));
     }
 
 
@@ -92,11 +207,10 @@ impl TaskManager {
     }
 
     pub fn get_current(&mut self) -> Task {
-        if self.scheduled.get_head().is_none() {
-            panic!("no scheduled tasks when looking for the current process");
+        match self.policy().current() {
+            Some(current) => current,
+            None => self.idle_tasks.get().clone().expect("scheduler: no idle task when looking for the current process"),
         }
-
-        self.scheduled.get_head().unwrap().clone()
     }
 
     fn set_current_context(&mut self) -> Task {
@@ -106,78 +220,264 @@ impl TaskManager {
     }
 
     fn schedule(&mut self) {
-        let current = self.get_current();
-
-        self.scheduled.remove_node(current.clone());
-        self.scheduled.insert_tail(current);
+        self.policy().tick();
+        self.set_current_context();
+    }
 
+    fn reschedule(&mut self) {
         self.set_current_context();
     }
 
     fn suspend(&mut self, task: Task) {
-        // also take an "event" arg
-        // TODO actually you need an event to block on, and you could just put it in the process, but you... I supposed... could just
-        // allocate the list nodes as needed and not have a vec (would mean a bunch of boxes, but that's ok... but also kinda implies the alloc::linked_list impl
-
         if task.try_lock().unwrap().state == TaskState::Running {
             task.try_lock().unwrap().state = TaskState::Blocked;
-            self.scheduled.remove_node(task.clone());
-            self.blocked.insert_head(task.clone());
+            self.dequeue(task.clone());
         }
 
         self.set_current_context();
     }
 
-    fn restart_blocked_by_syscall(&mut self, function: SyscallFunction) {
-        for task in self.blocked.iter() {
-            if task.try_lock().unwrap().syscall.function == function {
-                if task.try_lock().unwrap().state == TaskState::Blocked {
-                    task.try_lock().unwrap().state = TaskState::Running;
-                    self.blocked.remove_node(task.clone());
-                    self.scheduled.insert_head(task.clone());
-                    task.try_lock().unwrap().restart_syscall = true;
+    fn wake_tasks(&mut self, tasks: Vec<Task>) {
+        for task in tasks {
+            let woken = {
+                let mut locked_task = task.try_lock().unwrap();
+                if locked_task.state == TaskState::Blocked {
+                    locked_task.state = TaskState::Running;
+                    // Kernel threads continue from where they went to sleep instead of restarting a syscall
+                    locked_task.restart_syscall = !locked_task.is_kernel_task();
+                    true
+                } else {
+                    false
                 }
+            };
+
+            // Tasks are woken on the cpu they last ran on
+            if woken {
+                self.enqueue(task, true);
+            }
+        }
+
+        self.set_current_context();
+    }
+
+    fn interrupt(&mut self, task: Task) {
+        {
+            let mut locked_task = task.try_lock().unwrap();
+            if locked_task.state != TaskState::Blocked {
+                return;
+            }
+            locked_task.state = TaskState::Running;
+
+            // The blocked syscall will not be restarted, and instead returns an error
+            locked_task.restart_syscall = false;
+            locked_task.cancel_sleep();
+            locked_task.context.write_result(Err(ApiError::Interrupted as usize));
+        }
+
+        self.enqueue(task, false);
+    }
+
+    fn get_nice(&mut self, pid: Pid) -> Result<Nice, KernelError> {
+        let process = self.get_process(pid).ok_or(KernelError::NoSuchTask)?;
+        let nice = process.try_lock()?.nice;
+        Ok(nice)
+    }
+
+    fn set_nice(&mut self, pid: Pid, nice: isize) -> Result<Nice, KernelError> {
+        let nice = nice.clamp(NICE_MIN as isize, NICE_MAX as isize) as Nice;
+        let current_uid = self.get_current().try_lock()?.current_uid;
+
+        let threads: Vec<Task> = self.tasks.iter().filter(|task| {
+            let locked_task = task.try_lock().unwrap();
+            locked_task.process_id == pid && locked_task.state != TaskState::Exited
+        }).cloned().collect();
+        if threads.len() == 0 {
+            return Err(KernelError::NoSuchTask);
+        }
+
+        // Only root can raise the priority of a process, or change the priority of another user's process
+        if current_uid != 0 {
+            let locked_task = threads[0].try_lock()?;
+            if locked_task.current_uid != current_uid || nice < locked_task.nice {
+                return Err(KernelError::OperationNotPermitted);
+            }
+        }
+
+        for task in threads {
+            // The policy can only change a task's priority while it's not in its queues
+            let runnable = task.try_lock()?.state == TaskState::Running;
+            if runnable {
+                self.dequeue(task.clone());
+            }
+            task.try_lock()?.nice = nice;
+            if runnable {
+                self.enqueue(task, false);
             }
         }
 
         self.set_current_context();
+        Ok(nice)
+    }
+
+    fn raise_signal(&mut self, task: Task, signal: Signal) {
+        let interrupt = task.try_lock().unwrap().signals.raise(signal);
+        if interrupt {
+            self.interrupt(task);
+        }
+    }
+
+    fn send_signal(&mut self, pid: Pid, signal: Signal) -> Result<(), KernelError> {
+        let current = self.get_current();
+        let (current_pid, current_group, current_uid) = {
+            let locked_task = current.try_lock()?;
+            (locked_task.process_id, locked_task.process_group_id, locked_task.current_uid)
+        };
+
+        let mut found = false;
+        let mut targets = Vec::new();
+        for task in self.tasks.iter() {
+            let locked_task = task.try_lock()?;
+            if locked_task.state == TaskState::Exited || locked_task.is_kernel_task() || locked_task.task_id != locked_task.process_id {
+                continue;
+            }
+
+            let matches = match pid {
+                pid if pid > 0 => locked_task.process_id == pid,
+                0 => locked_task.process_group_id == current_group,
+                // Broadcasts don't go to the init process, since the system can't continue without it
+                -1 => locked_task.process_id != current_pid && locked_task.process_id != 1,
+                pid => locked_task.process_group_id == -pid,
+            };
+
+            // Only root can signal another user's processes
+            if matches {
+                found = true;
+                if current_uid == 0 || locked_task.current_uid == current_uid {
+                    targets.push(task.clone());
+                }
+            }
+        }
+
+        if !found {
+            return Err(KernelError::NoSuchTask);
+        } else if targets.len() == 0 {
+            return Err(KernelError::OperationNotPermitted);
+        }
+
+        // Signal 0 only checks that the process exists
+        if signal.0 != 0 {
+            for task in targets {
+                self.raise_signal(task, signal);
+            }
+        }
+        Ok(())
     }
 
     fn detach(&mut self, task: Task) {
-        if task.try_lock().unwrap().state != TaskState::Exited {
-            task.try_lock().unwrap().state = TaskState::Exited;
-            self.scheduled.remove_node(task.clone());
+        let previous_state = task.try_lock().unwrap().state;
+        if previous_state != TaskState::Exited {
+            let mut locked_task = task.try_lock().unwrap();
+            locked_task.state = TaskState::Exited;
+            locked_task.cancel_sleep();
+            drop(locked_task);
+            // Blocked tasks aren't in any scheduler queue
+            if previous_state == TaskState::Running {
+                self.dequeue(task.clone());
+            }
         }
 
         self.set_current_context();
     }
 
     fn abort(&mut self, task: Task) {
-        self.exit(task, -1);
+        self.exit(task, WaitStatus::new_exited(-1));
     }
 
-    fn exit(&mut self, task: Task, status: isize) {
-        info!("Exiting process {}", task.try_lock().unwrap().process_id);
+    fn exit(&mut self, task: Task, status: WaitStatus) {
+        let process_id = task.try_lock().unwrap().process_id;
+        info!("Exiting process {}", process_id);
+
+        // The other threads exit with the process, and the main thread holds the exit status for waitpid
+        let threads: Vec<Task> = self.tasks.iter().filter(|thread| {
+            let locked_thread = thread.try_lock().unwrap();
+            locked_thread.process_id == process_id && !locked_thread.is_main_thread()
+        }).cloned().collect();
+        for thread in threads {
+            self.exit_thread(thread, status);
+        }
+
+        let task = match self.get_process(process_id) {
+            Some(main_thread) => main_thread,
+            None => return,
+        };
 
         self.detach(task.clone());
         let _ = task.try_lock().unwrap().exit_and_free_resources(status); // Ignore the error
-        self.restart_blocked_by_syscall(SyscallFunction::WaitPid);
+
+        // Any pages the process leaked will still be counted after its memory has been freed
+        #[cfg(feature = "page-debug")]
+        crate::mm::pages::get_page_pool().print_allocated_pages();
+
+        let parent_id = task.try_lock().unwrap().parent_id;
+        if let Some(parent) = self.get_process(parent_id) {
+            let exit_queue = parent.try_lock().unwrap().child_exit_queue.clone();
+            self.wake_tasks(exit_queue.take_tasks());
+            self.raise_signal(parent, Signal::ChildExited);
+        }
+    }
+
+    fn exit_thread(&mut self, task: Task, status: WaitStatus) {
+        if task.try_lock().unwrap().is_main_thread() {
+            self.exit(task, status);
+            return;
+        }
+
+        self.detach(task.clone());
+        task.try_lock().unwrap().exit_thread(status);
+
+        // Threads can't be waited on, so the record is removed immediately
+        self.tasks.retain(|thread| !Arc::ptr_eq(thread, &task));
     }
 
-    fn find_exited(&mut self, pid: Option<Pid>, parent: Option<Pid>, process_group: Option<Pid>) -> Option<Task> {
+    fn exit_other_threads(&mut self, task: Task, status: WaitStatus) {
+        let process_id = task.try_lock().unwrap().process_id;
+        let others: Vec<Task> = self.tasks.iter().filter(|thread| {
+            !Arc::ptr_eq(thread, &task) && thread.try_lock().unwrap().process_id == process_id
+        }).cloned().collect();
+
+        // The main thread is removed like the others, rather than exiting the process, since the task takes its place
+        for thread in others {
+            self.detach(thread.clone());
+            thread.try_lock().unwrap().exit_thread(status);
+            self.tasks.retain(|other| !Arc::ptr_eq(other, &thread));
+        }
+
+        // The task becomes the main thread, so that it can be found and signalled using the process id
+        task.try_lock().unwrap().task_id = process_id;
+    }
+
+    fn find_exited(&mut self, parent: Pid, pid: Option<Pid>, process_group: Option<Pid>) -> Result<Option<Task>, KernelError> {
+        let mut found_child = false;
         for task in self.tasks.iter() {
-            let locked_task = task.try_lock().unwrap();
+            let locked_task = task.try_lock()?;
             if
-                locked_task.exit_status.is_some()
+                locked_task.parent_id == parent
+                && locked_task.task_id == locked_task.process_id
                 && (pid.is_none() || locked_task.process_id == pid.unwrap())
-                && (parent.is_none() || locked_task.parent_id == parent.unwrap())
                 && (process_group.is_none() || locked_task.process_group_id == process_group.unwrap())
             {
-                return Some(task.clone());
+                if locked_task.exit_status.is_some() {
+                    return Ok(Some(task.clone()));
+                }
+                found_child = true;
             }
         }
 
-        None
+        if found_child {
+            Ok(None)
+        } else {
+            Err(KernelError::NoChildren)
+        }
     }
 
     fn clean_up(&mut self, pid: Pid) -> Result<(), KernelError> {
@@ -195,8 +495,8 @@ impl TaskManager {
     }
 }
 
-pub fn create_task(parent: Option<Task>) -> Task {
-    TASK_MANAGER.try_lock().unwrap().create_task(parent)
+pub fn create_task(parent: Option<Task>) -> Result<Task, KernelError> {
+    TASK_MANAGER.try_lock()?.create_task(parent)
 }
 
 pub fn clean_up(pid: Pid) -> Result<(), KernelError> {
@@ -226,30 +526,87 @@ pub fn get_current() -> Task {
 pub fn clone_current(args: TaskCloneArgs) -> Result<Task, KernelError> {
     let mut manager = TASK_MANAGER.try_lock()?;
     let current_task = manager.get_current();
-    let new_proc = manager.create_task(Some(current_task.clone()));
+    let new_proc = QueueNode::new(TaskRecord::new(Some(current_task.clone()))?);
 
+    // The task is only scheduled once it's been set up, since the policy uses the priority copied from the parent
     new_proc.try_lock()?.clone_resources(&*current_task.try_lock()?, args)?;
+    manager.add_task(new_proc.clone());
 
     Ok(new_proc)
 }
 
+/// Create a kernel task that runs the entry function on the given stack, with the argument passed to it
+pub(crate) fn create_kernel_thread(name: &str, entry: extern "C" fn(usize) -> !, arg: usize, stack: VirtualAddress) -> Result<Task, KernelError> {
+    TASK_MANAGER.try_lock()?.create_kernel_thread(name, entry, arg, stack)
+}
+
+pub(crate) fn exit_kernel_thread(task: Task) {
+    TASK_MANAGER.try_lock().unwrap().exit_kernel_thread(task)
+}
+
 pub fn abort(task: Task) {
     TASK_MANAGER.try_lock().unwrap().abort(task)
 }
 
-pub fn exit_current(status: isize) {
+pub fn exit_current(status: WaitStatus) {
     let current_task = get_current();
     TASK_MANAGER.try_lock().unwrap().exit(current_task, status)
 }
 
-pub fn find_exited(pid: Option<Pid>, parent: Option<Pid>, process_group: Option<Pid>) -> Option<Task> {
-    TASK_MANAGER.try_lock().unwrap().find_exited(pid, parent, process_group)
+pub fn raise_signal(task: Task, signal: Signal) {
+    TASK_MANAGER.try_lock().unwrap().raise_signal(task, signal)
+}
+
+pub fn send_signal(pid: Pid, signal: Signal) -> Result<(), KernelError> {
+    TASK_MANAGER.try_lock()?.send_signal(pid, signal)
+}
+
+pub fn force_signal(task: Task, signal: Signal) {
+    task.try_lock().unwrap().signals.force(signal);
+}
+
+pub fn exit_thread(task: Task, status: WaitStatus) {
+    TASK_MANAGER.try_lock().unwrap().exit_thread(task, status)
+}
+
+/// End every other thread of the task's process, and make the task the main thread, before the process is replaced by exec
+pub fn exit_other_threads(task: Task, status: WaitStatus) {
+    TASK_MANAGER.try_lock().unwrap().exit_other_threads(task, status)
+}
+
+pub fn find_exited(parent: Pid, pid: Option<Pid>, process_group: Option<Pid>) -> Result<Option<Task>, KernelError> {
+    TASK_MANAGER.try_lock()?.find_exited(parent, pid, process_group)
 }
 
 pub fn schedule() {
     TASK_MANAGER.try_lock().unwrap().schedule();
 }
 
+/// Switch to the task that should be running on this cpu, after another cpu has changed this cpu's run queues
+pub fn reschedule() {
+    TASK_MANAGER.try_lock().unwrap().reschedule();
+}
+
+/// Get the nice value of a process
+pub fn get_nice(pid: Pid) -> Result<Nice, KernelError> {
+    TASK_MANAGER.try_lock()?.get_nice(pid)
+}
+
+/// Set the nice value of all the threads of a process, limited to the range of valid values, and return the value that was set
+pub fn set_nice(pid: Pid, nice: isize) -> Result<Nice, KernelError> {
+    TASK_MANAGER.try_lock()?.set_nice(pid, nice)
+}
+
+/// Get the time in microseconds that a task runs for before the next task is scheduled
+pub fn get_time_slice() -> u32 {
+    TIME_SLICE.load(Ordering::Relaxed)
+}
+
+/// Set the time in microseconds that a task runs for before the next task is scheduled, which takes effect after the current time slice
+pub fn set_time_slice(microseconds: u32) {
+    TIME_SLICE.store(microseconds.max(1), Ordering::Relaxed);
+}
+
 pub fn check_restart_syscall() {
     let current_task = get_current();
     if current_task.lock().restart_syscall {
@@ -263,7 +620,7 @@ pub(crate) fn suspend(proc: Task) {
     TASK_MANAGER.try_lock().unwrap().suspend(proc);
 }
 
-pub(crate) fn restart_blocked(function: SyscallFunction) {
-    TASK_MANAGER.try_lock().unwrap().restart_blocked_by_syscall(function);
+pub(crate) fn wake_tasks(tasks: Vec<Task>) {
+    TASK_MANAGER.try_lock().unwrap().wake_tasks(tasks);
 }
 
@@ -0,0 +1,223 @@
+
+use core::mem;
+use core::ptr;
+
+use ruxpin_types::{Signal, SignalSet, SignalAction, SignalHandler, SignalMaskHow, WaitStatus};
+
+use crate::error;
+use crate::arch::{Context, VirtualAddress};
+use crate::errors::KernelError;
+
+use super::scheduler::{self, Task};
+
+
+#[derive(Copy, Clone, PartialEq)]
+enum DefaultAction {
+    Terminate,
+    Ignore,
+}
+
+// This is written to the user stack when a handler is called, and read back by sigreturn
+#[repr(C)]
+struct SignalFrame {
+    context: Context,
+    blocked: SignalSet,
+    previous_frame: u64,
+}
+
+#[derive(Clone)]
+pub struct TaskSignals {
+    pub pending: SignalSet,
+    pub blocked: SignalSet,
+    actions: [SignalAction; Signal::Count],
+    frame: Option<VirtualAddress>,
+}
+
+impl TaskSignals {
+    pub fn new() -> Self {
+        Self {
+            pending: SignalSet::Empty,
+            blocked: SignalSet::Empty,
+            actions: [SignalAction::new(SignalHandler::Default); Signal::Count],
+            frame: None,
+        }
+    }
+
+    pub fn copy_for_fork(&self) -> Self {
+        let mut signals = self.clone();
+        signals.pending = SignalSet::Empty;
+        signals
+    }
+
+    pub fn reset_for_exec(&mut self) {
+        // Handlers refer to the old program's code, but ignored signals stay ignored
+        for action in self.actions.iter_mut() {
+            if let SignalHandler::Handler(_) = action.handler {
+                *action = SignalAction::new(SignalHandler::Default);
+            }
+        }
+        self.frame = None;
+    }
+
+    pub fn get_action(&self, signal: Signal) -> SignalAction {
+        self.actions[signal.0 as usize]
+    }
+
+    pub fn set_action(&mut self, signal: Signal, action: SignalAction) -> Result<SignalAction, KernelError> {
+        if !signal.is_valid() || !signal.is_catchable() {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        let previous = self.actions[signal.0 as usize];
+        self.actions[signal.0 as usize] = action;
+
+        if self.is_ignored(signal) {
+            self.pending = self.pending.minus(SignalSet::from_signal(signal));
+        }
+        Ok(previous)
+    }
+
+    pub fn set_blocked(&mut self, how: SignalMaskHow, mask: SignalSet) -> SignalSet {
+        let previous = self.blocked;
+        let blocked = match how {
+            SignalMaskHow::Block => self.blocked.plus(mask),
+            SignalMaskHow::Unblock => self.blocked.minus(mask),
+            SignalMaskHow::SetMask => mask,
+        };
+        self.blocked = without_unblockable(blocked);
+        previous
+    }
+
+    /// Mark the signal as pending, and return true if the task should be interrupted to handle it
+    pub fn raise(&mut self, signal: Signal) -> bool {
+        if self.is_ignored(signal) {
+            return false;
+        }
+
+        self.pending = self.pending.plus(SignalSet::from_signal(signal));
+        !self.blocked.is_set(signal)
+    }
+
+    /// Raise a signal caused by the task itself, which can't be blocked or ignored without the task faulting again
+    pub fn force(&mut self, signal: Signal) {
+        if self.blocked.is_set(signal) || self.is_ignored(signal) {
+            self.actions[signal.0 as usize] = SignalAction::new(SignalHandler::Default);
+            self.blocked = self.blocked.minus(SignalSet::from_signal(signal));
+        }
+        self.raise(signal);
+    }
+
+    fn is_ignored(&self, signal: Signal) -> bool {
+        match self.actions[signal.0 as usize].handler {
+            SignalHandler::Ignore => true,
+            SignalHandler::Default => default_action(signal) == DefaultAction::Ignore,
+            SignalHandler::Handler(_) => false,
+        }
+    }
+
+    fn next_pending(&mut self) -> Option<Signal> {
+        let signal = self.pending.minus(self.blocked).first()?;
+        self.pending = self.pending.minus(SignalSet::from_signal(signal));
+        Some(signal)
+    }
+}
+
+fn default_action(signal: Signal) -> DefaultAction {
+    match signal {
+        // TODO stopping and continuing processes isn't supported yet
+        Signal::ChildExited | Signal::Continue | Signal::Stop | Signal::TerminalStop => DefaultAction::Ignore,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+fn without_unblockable(set: SignalSet) -> SignalSet {
+    set.minus(SignalSet::from_signal(Signal::Kill)).minus(SignalSet::from_signal(Signal::Stop))
+}
+
+
+/// Deliver the pending signals of the current task before it returns to user space
+pub fn check_pending_signals() {
+    loop {
+        let current = scheduler::get_current();
+        let (signal, action) = {
+            let mut locked_task = current.lock();
+            match locked_task.signals.next_pending() {
+                Some(signal) => (signal, locked_task.signals.get_action(signal)),
+                None => return,
+            }
+        };
+
+        match action.handler {
+            SignalHandler::Ignore => { },
+            SignalHandler::Default => {
+                if default_action(signal) == DefaultAction::Terminate {
+                    // This will switch to the next task, so continue checking for whichever task is now current
+                    scheduler::exit_current(WaitStatus::new_signaled(signal));
+                }
+            },
+            SignalHandler::Handler(handler) => {
+                if let Err(err) = setup_signal_frame(current.clone(), signal, handler, action) {
+                    error!("signals: unable to deliver signal {} to pid {}: {:?}", signal.0, current.lock().process_id, err);
+                    scheduler::exit_current(WaitStatus::new_signaled(Signal::SegmentationFault));
+                    continue;
+                }
+                return;
+            },
+        }
+    }
+}
+
+fn setup_signal_frame(task: Task, signal: Signal, handler: usize, action: SignalAction) -> Result<(), KernelError> {
+    let frame_size = mem::size_of::<SignalFrame>();
+
+    let (frame, frame_addr) = {
+        let locked_task = task.try_lock()?;
+
+        let sp = usize::from(locked_task.context.get_stack());
+        if sp < frame_size {
+            return Err(KernelError::AddressUnmapped);
+        }
+        let frame_addr = VirtualAddress::from((sp - frame_size) as u64).align_down(16);
+        locked_task.space.try_lock()?.check_user_range(frame_addr, frame_size, true)?;
+
+        let frame = SignalFrame {
+            context: locked_task.context.clone(),
+            blocked: locked_task.signals.blocked,
+            previous_frame: locked_task.signals.frame.map(|addr| u64::from(addr)).unwrap_or(0),
+        };
+        (frame, frame_addr)
+    };
+
+    // The task must be unlocked while writing to user memory, in case the page fault handler needs to map in the stack
+    unsafe {
+        ptr::write(u64::from(frame_addr) as *mut SignalFrame, frame);
+    }
+
+    let mut locked_task = task.try_lock()?;
+    let blocked = locked_task.signals.blocked.plus(action.mask).plus(SignalSet::from_signal(signal));
+    locked_task.signals.blocked = without_unblockable(blocked);
+    locked_task.signals.frame = Some(frame_addr);
+    locked_task.context.setup_signal_handler(VirtualAddress::from(handler as u64), frame_addr, VirtualAddress::from(action.restorer as u64), signal.0 as usize);
+    Ok(())
+}
+
+pub fn return_from_signal(task: Task) -> Result<(), KernelError> {
+    let frame_size = mem::size_of::<SignalFrame>();
+
+    let frame_addr = {
+        let locked_task = task.try_lock()?;
+        let frame_addr = locked_task.signals.frame.ok_or(KernelError::InvalidArgument)?;
+        locked_task.space.try_lock()?.check_user_range(frame_addr, frame_size, false)?;
+        frame_addr
+    };
+
+    let frame = unsafe {
+        ptr::read(u64::from(frame_addr) as *const SignalFrame)
+    };
+
+    let mut locked_task = task.try_lock()?;
+    locked_task.context.restore_signal_context(&frame.context);
+    locked_task.signals.blocked = without_unblockable(frame.blocked);
+    locked_task.signals.frame = if frame.previous_frame != 0 { Some(VirtualAddress::from(frame.previous_frame)) } else { None };
+    Ok(())
+}
@@ -1,17 +1,22 @@
 
 use alloc::string::String;
 use alloc::string::ToString;
+use alloc::sync::Arc;
 
 use ruxpin_syscall::SyscallRequest;
-use ruxpin_types::{Tid, Pid, UserID};
+use ruxpin_types::{Tid, Pid, UserID, WaitStatus, CloneArgs, CloneFlags, Personality};
 
-use crate::arch::Context;
+use crate::arch::{Context, VirtualAddress};
+use crate::time::{self, TimerId};
 use crate::sync::Spinlock;
 use crate::errors::KernelError;
 use crate::fs::{FileDescriptors, SharableFileDescriptors};
 use crate::mm::{VirtualAddressSpace, SharableVirtualAddressSpace};
 
 use super::scheduler::Task;
+use super::policy::Nice;
+use super::signals::TaskSignals;
+use super::wait::WaitQueue;
 
 const INIT_PID: Pid = 1;
 
@@ -25,14 +30,33 @@ fn next_task_id() -> Tid {
 }
 
 pub struct TaskCloneArgs {
-    // TODO this is for the arguments telling what resources to clone
-    //flags: TaskCloneFlags,
+    pub flags: CloneFlags,
+    pub entry: Option<VirtualAddress>,
+    pub stack: VirtualAddress,
+    pub arg: usize,
+    pub clear_tid: Option<VirtualAddress>,
 }
 
 impl TaskCloneArgs {
     pub fn new() -> Self {
         Self {
+            flags: CloneFlags::Empty,
+            entry: None,
+            stack: VirtualAddress::from(0),
+            arg: 0,
+            clear_tid: None,
+        }
+    }
+}
 
+impl From<&CloneArgs> for TaskCloneArgs {
+    fn from(args: &CloneArgs) -> Self {
+        Self {
+            flags: args.flags,
+            entry: if args.entry != 0 { Some(VirtualAddress::from(args.entry as u64)) } else { None },
+            stack: VirtualAddress::from(args.stack as u64),
+            arg: args.arg,
+            clear_tid: if args.clear_tid != 0 { Some(VirtualAddress::from(args.clear_tid as u64)) } else { None },
         }
     }
 }
@@ -56,17 +80,28 @@ pub struct TaskRecord {
     pub session_id: Pid,
     pub cmd: String,
     pub current_uid: UserID,
+    pub personality: Personality,
+    pub child_exit_queue: Arc<WaitQueue>,
 
     // Other Module's Data
     pub space: SharableVirtualAddressSpace,
     pub files: SharableFileDescriptors,
+    pub signals: TaskSignals,
 
     // Thread-Specific
-    pub exit_status: Option<isize>,
+    pub clear_tid: Option<VirtualAddress>,
+    pub exit_status: Option<WaitStatus>,
     pub state: TaskState,
     pub syscall: SyscallRequest,
     pub restart_syscall: bool,
+    pub sleep_deadline: Option<u64>,
+    pub sleep_timer: Option<TimerId>,
     pub context: Context,
+
+    // Scheduling Data
+    pub nice: Nice,
+    pub vruntime: u64,
+    pub cpu: usize,
 }
 
 impl TaskRecord {
@@ -82,19 +117,29 @@ impl TaskRecord {
             session_id: task_id,
             cmd: cmd.to_string(),
             current_uid: 0,
+            personality: Personality::Default,
+            child_exit_queue: Arc::new(WaitQueue::new()),
 
             space: VirtualAddressSpace::get_kernel_space(),
             files: FileDescriptors::new_sharable(),
+            signals: TaskSignals::new(),
 
+            clear_tid: None,
             exit_status: None,
             state: TaskState::Running,
             syscall: Default::default(),
             restart_syscall: false,
+            sleep_deadline: None,
+            sleep_timer: None,
             context: Default::default(),
+
+            nice: 0,
+            vruntime: 0,
+            cpu: 0,
         }
     }
 
-    pub(super) fn new(parent: Option<Task>) -> Self {
+    pub(super) fn new(parent: Option<Task>) -> Result<Self, KernelError> {
         let task_id = next_task_id();
 
         let process_id = task_id;
@@ -106,7 +151,7 @@ impl TaskRecord {
             None => (INIT_PID, process_id, process_id),
         };
 
-        Self {
+        Ok(Self {
             task_id,
             process_id,
 
@@ -115,45 +160,115 @@ impl TaskRecord {
             session_id,
             cmd: String::new(),
             current_uid: 0,
+            personality: Personality::Default,
+            child_exit_queue: Arc::new(WaitQueue::new()),
 
-            space: VirtualAddressSpace::new_sharable(),
+            space: VirtualAddressSpace::new_sharable()?,
             files: FileDescriptors::new_sharable(),
+            signals: TaskSignals::new(),
 
+            clear_tid: None,
             exit_status: None,
             state: TaskState::Running,
             syscall: Default::default(),
             restart_syscall: false,
+            sleep_deadline: None,
+            sleep_timer: None,
             context: Default::default(),
+
+            nice: 0,
+            vruntime: 0,
+            cpu: 0,
+        })
+    }
+
+    /// Stop the task's sleep, and cancel its wake up timer so that it doesn't wake the task later on
+    pub fn cancel_sleep(&mut self) {
+        self.sleep_deadline = None;
+        if let Some(timer) = self.sleep_timer.take() {
+            time::cancel_timer(timer);
         }
     }
 
-    pub fn exit_and_free_resources(&mut self, status: isize) -> Result<(), KernelError> {
+    pub fn is_kernel_task(&self) -> bool {
+        Arc::ptr_eq(&self.space, &VirtualAddressSpace::get_kernel_space())
+    }
+
+    pub fn is_main_thread(&self) -> bool {
+        self.task_id == self.process_id
+    }
+
+    pub fn exit_and_free_resources(&mut self, status: WaitStatus) -> Result<(), KernelError> {
         self.exit_status = Some(status);
         self.free_resources()
     }
 
+    pub fn exit_thread(&mut self, status: WaitStatus) {
+        self.exit_status = Some(status);
+        // Drop the references to the process's resources so they can be freed when the last thread exits
+        self.space = VirtualAddressSpace::get_kernel_space();
+        self.files = FileDescriptors::new_sharable();
+    }
+
     pub fn free_resources(&mut self) -> Result<(), KernelError> {
-        self.files.try_lock()?.close_all();
-        self.space.try_lock()?.clear_segments()?;
-        Ok(())
+        // Resources shared with another process must be left for the other process to use
+        if Arc::strong_count(&self.files) == 1 {
+            self.files.try_lock()?.close_all();
+        }
+        self.free_memory()
     }
 
     pub fn free_memory(&mut self) -> Result<(), KernelError> {
-        self.space.try_lock()?.clear_segments()?;
+        if Arc::strong_count(&self.space) == 1 {
+            self.space.try_lock()?.clear_segments()?;
+        } else {
+            self.space = VirtualAddressSpace::new_sharable()?;
+        }
         Ok(())
     }
 
-    pub fn clone_resources(&mut self, source: &TaskRecord, _args: TaskCloneArgs) -> Result<(), KernelError> {
+    pub fn clone_resources(&mut self, source: &TaskRecord, args: TaskCloneArgs) -> Result<(), KernelError> {
+        if args.flags.is_set(CloneFlags::Thread) {
+            if !args.flags.is_set(CloneFlags::ShareSpace) || !args.flags.is_set(CloneFlags::ShareFiles) {
+                return Err(KernelError::InvalidArgument);
+            }
+
+            self.process_id = source.process_id;
+            self.parent_id = source.parent_id;
+            self.cmd = source.cmd.clone();
+        }
+
         self.current_uid = source.current_uid;
-        self.files = source.files.try_lock()?.duplicate_table();
-        self.space.try_lock()?.copy_segments(&mut *source.space.try_lock()?)?;
+        self.personality = source.personality;
+        self.nice = source.nice;
+        self.signals = source.signals.copy_for_fork();
+        self.clear_tid = args.clear_tid;
+
+        if args.flags.is_set(CloneFlags::ShareFiles) {
+            self.files = source.files.clone();
+        } else {
+            self.files = source.files.try_lock()?.duplicate_table();
+        }
+
+        if args.flags.is_set(CloneFlags::ShareSpace) {
+            self.space = source.space.clone();
+        } else {
+            self.space.try_lock()?.copy_segments(&mut *source.space.try_lock()?)?;
+        }
+
         let ttbr = self.space.try_lock()?.get_ttbr();
         self.context = source.context.clone();
         self.context.set_ttbr(ttbr);
 
-        // The return result will be 0 to indicate it's the child process
-        self.context.write_result(Ok(0));
+        match args.entry {
+            Some(entry) => {
+                self.context.setup_thread_start(entry, args.stack, args.arg);
+            },
+            None => {
+                // The return result will be 0 to indicate it's the child process
+                self.context.write_result(Ok(0));
+            },
+        }
         Ok(())
     }
 }
-
@@ -0,0 +1,74 @@
+
+use core::mem;
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+
+use crate::sync::Spinlock;
+
+use super::scheduler::{self, Task};
+use super::tasks::TaskState;
+
+
+/// A list of tasks that are blocked waiting for a specific event to occur
+///
+/// A waiting task is suspended in the middle of its syscall, which is restarted from the
+/// beginning when the queue is woken, so the event should be checked for again when woken
+pub struct WaitQueue {
+    tasks: Spinlock<Vec<Task>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            tasks: Spinlock::new(Vec::new()),
+        }
+    }
+
+    /// Suspend the current task until the queue is woken
+    pub fn wait(&self) {
+        let current = scheduler::get_current();
+        self.add_task(current.clone());
+        scheduler::suspend(current);
+    }
+
+    /// Add a task to the queue without suspending it, for when the suspend must be deferred
+    pub fn add_task(&self, task: Task) {
+        let mut tasks = self.tasks.lock();
+        // A task that was interrupted by a signal can still be in the queue when it waits again
+        if tasks.iter().find(|waiting| Arc::ptr_eq(waiting, &task)).is_none() {
+            tasks.push(task);
+        }
+    }
+
+    pub fn wake_all(&self) {
+        scheduler::wake_tasks(self.take_tasks());
+    }
+
+    /// Wake the first task that is still waiting, and return true if one was woken
+    pub fn wake_one(&self) -> bool {
+        loop {
+            let task = {
+                let mut tasks = self.tasks.lock();
+                if tasks.len() == 0 {
+                    return false;
+                }
+                tasks.remove(0)
+            };
+
+            // Skip any tasks that were interrupted or exited since they were added
+            if task.try_lock().unwrap().state == TaskState::Blocked {
+                scheduler::wake_tasks(vec![task]);
+                return true;
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.lock().len() == 0
+    }
+
+    pub(super) fn take_tasks(&self) -> Vec<Task> {
+        mem::take(&mut *self.tasks.lock())
+    }
+}
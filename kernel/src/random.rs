@@ -0,0 +1,37 @@
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::arch;
+
+
+// The state is only ever updated atomically, so random numbers can be generated and entropy added from anywhere, including interrupt handlers
+static STATE: AtomicU64 = AtomicU64::new(0x853c_49e6_748f_ea9b);
+
+const INCREMENT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+
+/// Mix a value that's hard to predict, such as the time of an event, into the random number state
+pub fn add_entropy(value: u64) {
+    STATE.fetch_xor(mix(value), Ordering::Relaxed);
+}
+
+/// Get a random number, which is not suitable for cryptography but is hard to guess from outside of the kernel
+pub fn next_u64() -> u64 {
+    // The counter is mixed in each time, so the numbers depend on when they're requested as well
+    let state = STATE.fetch_add(INCREMENT, Ordering::Relaxed);
+    mix(state ^ arch::read_counter())
+}
+
+/// Get a random number that's less than the given limit, which must not be 0
+pub fn next_below(limit: u64) -> u64 {
+    next_u64() % limit
+}
+
+// The finalizer from SplitMix64, which spreads every bit of the input across the whole output
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(INCREMENT);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
@@ -0,0 +1,177 @@
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::notice;
+use crate::irqs::{self, Ipi};
+use crate::arch::{self, mmu, VirtualAddress};
+use crate::proc::scheduler;
+use crate::sync::Spinlock;
+use crate::errors::KernelError;
+
+pub use crate::arch::MAX_CPUS;
+
+const SECONDARY_STACK_SIZE: usize = 0x10_0000;
+
+// A bit is set for each cpu that is running tasks
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+// The number of TLB shootdowns requested, and the latest request that each cpu had seen when it last flushed its TLB
+static SHOOTDOWN_REQUESTED: AtomicUsize = AtomicUsize::new(0);
+const NO_SHOOTDOWNS: AtomicUsize = AtomicUsize::new(0);
+static SHOOTDOWN_DONE: PerCpu<AtomicUsize> = PerCpu::new([NO_SHOOTDOWNS; MAX_CPUS]);
+
+// Functions to run on each secondary core as it starts, to set up the devices that each core has its own copy of
+static CORE_INITS: Spinlock<Vec<fn()>> = Spinlock::new(Vec::new());
+
+
+/// A separate copy of some data for each cpu, where each cpu normally only uses its own copy
+pub struct PerCpu<T> {
+    values: [T; MAX_CPUS],
+}
+
+impl<T> PerCpu<T> {
+    pub const fn new(values: [T; MAX_CPUS]) -> Self {
+        Self {
+            values,
+        }
+    }
+
+    /// Returns this cpu's copy of the data
+    pub fn get(&self) -> &T {
+        &self.values[cpu_id()]
+    }
+
+    /// Returns this cpu's copy of the data
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.values[cpu_id()]
+    }
+
+    /// Returns the given cpu's copy of the data
+    pub fn get_for(&self, cpu: usize) -> &T {
+        &self.values[cpu]
+    }
+
+    /// Returns the given cpu's copy of the data
+    pub fn get_for_mut(&mut self, cpu: usize) -> &mut T {
+        &mut self.values[cpu]
+    }
+}
+
+
+pub fn cpu_id() -> usize {
+    arch::cpu_id()
+}
+
+pub fn is_online(cpu: usize) -> bool {
+    ONLINE_CPUS.load(Ordering::Acquire) & (1 << cpu) != 0
+}
+
+/// Returns an iterator over the ids of the cpus that are running tasks
+pub fn online_cpus() -> impl Iterator<Item=usize> {
+    (0..MAX_CPUS).filter(|cpu| is_online(*cpu))
+}
+
+/// Set up the inter-processor interrupts, which must be done on the boot core after the interrupt controller is registered
+pub fn initialize() -> Result<(), KernelError> {
+    irqs::register_irq(irqs::ipi_irq(Ipi::Reschedule), handle_reschedule)?;
+    irqs::register_irq(irqs::ipi_irq(Ipi::TlbShootdown), handle_tlb_shootdown)?;
+    enable_ipis();
+
+    set_online(cpu_id());
+    Ok(())
+}
+
+/// Register a function to run on each secondary core when it starts, before it runs any tasks
+pub fn register_core_init(func: fn()) {
+    CORE_INITS.lock().push(func);
+}
+
+/// Release the secondary cores from the firmware, after which they will start running tasks
+pub fn start_secondary_cores() {
+    for cpu in 1..MAX_CPUS {
+        // The stack is never freed, because the core uses it until the system is shut down
+        let stack = vec![0u8; SECONDARY_STACK_SIZE].leak();
+        let stack_top = (stack.as_ptr() as u64 + SECONDARY_STACK_SIZE as u64) & !0xF;
+        arch::start_secondary_core(cpu, VirtualAddress::from(stack_top));
+    }
+}
+
+pub(crate) fn initialize_secondary_core() -> Result<(), KernelError> {
+    enable_ipis();
+    scheduler::start_cpu()?;
+
+    let inits = CORE_INITS.lock().clone();
+    for func in inits {
+        func();
+    }
+
+    set_online(cpu_id());
+    notice!("cpu {}: secondary core started", cpu_id());
+    Ok(())
+}
+
+/// Interrupt the given cpu to switch to the task that should be running on it now
+pub fn reschedule_cpu(cpu: usize) {
+    if cpu != cpu_id() && is_online(cpu) {
+        irqs::send_ipi(cpu, Ipi::Reschedule);
+    }
+}
+
+/// Flush the cached translations on all cpus, after mappings were removed or their permissions reduced
+///
+/// The broadcast invalidate covers the cores that share this core's TLB maintenance, and the interrupt
+/// makes each of the other cores flush its own TLB for when that isn't the case.  This doesn't wait for
+/// the other cores, because they could be waiting with interrupts disabled for a lock the caller holds
+pub fn shootdown_tlb() {
+    mmu::invalidate_tlb();
+    SHOOTDOWN_REQUESTED.fetch_add(1, Ordering::AcqRel);
+    for cpu in online_cpus().filter(|cpu| *cpu != cpu_id()) {
+        irqs::send_ipi(cpu, Ipi::TlbShootdown);
+    }
+}
+
+/// Flush the cached translations on all cpus, and wait until every other cpu has flushed its TLB
+///
+/// This is for when the contents of the unmapped pages are about to be used, so no other cpu can still be writing
+/// to them.  Each cpu only flushes when it takes the interrupt, so this must not be called while holding any lock that
+/// another cpu could be waiting for with interrupts disabled.  Requests from other cpus are handled while waiting, so
+/// that two cpus waiting on each other can't deadlock
+pub fn shootdown_tlb_and_wait() {
+    mmu::invalidate_tlb();
+    let request = SHOOTDOWN_REQUESTED.fetch_add(1, Ordering::AcqRel) + 1;
+    let others = || online_cpus().filter(|cpu| *cpu != cpu_id());
+
+    for cpu in others() {
+        irqs::send_ipi(cpu, Ipi::TlbShootdown);
+    }
+
+    while others().any(|cpu| SHOOTDOWN_DONE.get_for(cpu).load(Ordering::Acquire) < request) {
+        if SHOOTDOWN_DONE.get().load(Ordering::Acquire) < SHOOTDOWN_REQUESTED.load(Ordering::Acquire) {
+            handle_tlb_shootdown();
+        }
+        spin_loop();
+    }
+}
+
+fn set_online(cpu: usize) {
+    ONLINE_CPUS.fetch_or(1 << cpu, Ordering::Release);
+}
+
+fn enable_ipis() {
+    irqs::enable_irq(irqs::ipi_irq(Ipi::Reschedule));
+    irqs::enable_irq(irqs::ipi_irq(Ipi::TlbShootdown));
+}
+
+fn handle_reschedule() {
+    scheduler::reschedule();
+}
+
+fn handle_tlb_shootdown() {
+    // The request number is read before flushing, so that it only covers mappings that were changed before the flush
+    let request = SHOOTDOWN_REQUESTED.load(Ordering::Acquire);
+    mmu::invalidate_local_tlb();
+    SHOOTDOWN_DONE.get().fetch_max(request, Ordering::AcqRel);
+}
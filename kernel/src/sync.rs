@@ -2,15 +2,30 @@
 use core::cell::UnsafeCell;
 use core::hint::spin_loop;
 use core::ops::{Deref, DerefMut};
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
+use crate::arch::{self, MAX_CPUS};
 use crate::errors::KernelError;
+use crate::proc::wait::WaitQueue;
+
+
+// The number of spinlocks held by each cpu, and the state of its interrupts from before it took the first one
+const NO_LOCKS: AtomicUsize = AtomicUsize::new(0);
+static LOCKS_HELD: [AtomicUsize; MAX_CPUS] = [NO_LOCKS; MAX_CPUS];
+const NO_FLAGS: AtomicU64 = AtomicU64::new(0);
+static SAVED_IRQ_FLAGS: [AtomicU64; MAX_CPUS] = [NO_FLAGS; MAX_CPUS];
 
 
 pub struct SpinlockGuard<'a, T: ?Sized + 'a> {
     spinlock: &'a Spinlock<T>,
 }
 
+/// A lock that spins until it's free, and disables interrupts on the current cpu while it's held
+///
+/// Kernel threads run with interrupts enabled, so if the interrupts weren't disabled, a kernel thread could be
+/// switched out while holding the lock, and an interrupt handler or the next task on that cpu could then spin on it
+/// forever.  The interrupts are restored once the cpu has released all of its spinlocks, so guards can be dropped
+/// in any order, but a task must not sleep or yield while it holds one.
 pub struct Spinlock<T: ?Sized> {
     lock: AtomicBool,
     data: UnsafeCell<T>
@@ -30,6 +45,7 @@ impl<T> Spinlock<T> {
 
 impl<T: ?Sized> Spinlock<T> {
     pub fn lock(&self) -> SpinlockGuard<'_, T> {
+        disable_irq_for_lock();
         let mut count = 0;
         while self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
             // TODO delay
@@ -43,17 +59,31 @@ impl<T: ?Sized> Spinlock<T> {
     }
 
     pub fn try_lock(&self) -> Result<SpinlockGuard<'_, T>, KernelError> {
+        disable_irq_for_lock();
         let mut count = 0;
         while self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
             // TODO delay
             spin_loop();
             count += 1;
             if count == 1_000_000_000 {
+                restore_irq_after_lock();
                 return Err(KernelError::LockTimeout);
             }
         }
         Ok(SpinlockGuard { spinlock: self })
     }
+
+    /// Take the lock only if it's free, for code that must skip the data rather than wait for it
+    pub fn try_lock_once(&self) -> Option<SpinlockGuard<'_, T>> {
+        disable_irq_for_lock();
+        match self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Some(SpinlockGuard { spinlock: self }),
+            Err(_) => {
+                restore_irq_after_lock();
+                None
+            },
+        }
+    }
 }
 
 impl<T: ?Sized> Deref for SpinlockGuard<'_, T> {
@@ -77,7 +107,279 @@ impl<T: ?Sized> DerefMut for SpinlockGuard<'_, T> {
 impl<T: ?Sized> Drop for SpinlockGuard<'_, T> {
     #[inline]
     fn drop(&mut self) {
+        // The lock must be released before interrupts are enabled, or a handler could still deadlock on it
         self.spinlock.lock.store(false, Ordering::Release);
+        restore_irq_after_lock();
+    }
+}
+
+fn disable_irq_for_lock() {
+    let flags = unsafe { arch::disable_irq() };
+    // The cpu can't change once interrupts are disabled
+    let cpu = arch::cpu_id();
+    if LOCKS_HELD[cpu].fetch_add(1, Ordering::Relaxed) == 0 {
+        SAVED_IRQ_FLAGS[cpu].store(flags, Ordering::Relaxed);
+    }
+}
+
+fn restore_irq_after_lock() {
+    let cpu = arch::cpu_id();
+    if LOCKS_HELD[cpu].fetch_sub(1, Ordering::Relaxed) == 1 {
+        unsafe { arch::enable_irq(SAVED_IRQ_FLAGS[cpu].load(Ordering::Relaxed)); }
+    }
+}
+
+
+/// A lock that puts the current task to sleep while another task holds it
+///
+/// Tasks don't have their own kernel stacks, so a task can't sleep in the middle of a syscall and continue
+/// where it left off.  Instead, if the lock is held, the current task is suspended and `SuspendProcess` is
+/// returned, which should be passed back up to the syscall, and the syscall is restarted from the beginning
+/// once the lock is released.  These can only be taken by syscalls, and not by interrupt handlers or tasklets.
+pub struct Mutex<T: ?Sized> {
+    locked: Spinlock<bool>,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(t: T) -> Mutex<T> {
+        Mutex {
+            locked: Spinlock::new(false),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(t),
+        }
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Take the lock, or suspend the current task until it's released and return `SuspendProcess`
+    pub fn lock(&self) -> Result<MutexGuard<'_, T>, KernelError> {
+        let mut locked = self.locked.lock();
+        if !*locked {
+            *locked = true;
+            return Ok(MutexGuard { mutex: self });
+        }
+
+        // The spinlock is held until the task is suspended, so the lock can't be released before the task is waiting
+        self.waiters.wait();
+        Err(KernelError::SuspendProcess)
+    }
+
+    /// Take the lock only if it's free, without suspending the current task
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let mut locked = self.locked.lock();
+        if !*locked {
+            *locked = true;
+            Some(MutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    fn unlock(&self) {
+        let mut locked = self.locked.lock();
+        *locked = false;
+        self.waiters.wake_one();
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe {
+            &*self.mutex.data.get()
+        }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe {
+            &mut *self.mutex.data.get()
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+
+/// A counter of available resources that puts the current task to sleep while there are none available
+///
+/// Like `Mutex`, a task that has to wait is suspended and its syscall is restarted when a resource is released
+pub struct Semaphore {
+    count: Spinlock<usize>,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Semaphore {
+        Semaphore {
+            count: Spinlock::new(count),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Take one of the resources, or suspend the current task until one is released and return `SuspendProcess`
+    pub fn down(&self) -> Result<(), KernelError> {
+        let mut count = self.count.lock();
+        if *count > 0 {
+            *count -= 1;
+            return Ok(());
+        }
+
+        self.waiters.wait();
+        Err(KernelError::SuspendProcess)
+    }
+
+    /// Take one of the resources if there are any available, without suspending the current task
+    pub fn try_down(&self) -> bool {
+        let mut count = self.count.lock();
+        if *count > 0 {
+            *count -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Release one of the resources, and wake a task that's waiting for it
+    pub fn up(&self) {
+        let mut count = self.count.lock();
+        *count += 1;
+        self.waiters.wake_one();
+    }
+
+    pub fn available(&self) -> usize {
+        *self.count.lock()
+    }
+}
+
+
+/// A lock that can be held by many readers or one writer, which puts the current task to sleep while it can't be taken
+///
+/// Like `Mutex`, a task that has to wait is suspended and its syscall is restarted when the lock is released
+pub struct RwLock<T: ?Sized> {
+    state: Spinlock<RwLockState>,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+struct RwLockState {
+    readers: usize,
+    writer: bool,
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(t: T) -> RwLock<T> {
+        RwLock {
+            state: Spinlock::new(RwLockState { readers: 0, writer: false }),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(t),
+        }
     }
 }
 
+impl<T: ?Sized> RwLock<T> {
+    /// Take the lock for reading, or suspend the current task until the writer releases it and return `SuspendProcess`
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, T>, KernelError> {
+        let mut state = self.state.lock();
+        if !state.writer {
+            state.readers += 1;
+            return Ok(RwLockReadGuard { rwlock: self });
+        }
+
+        self.waiters.wait();
+        Err(KernelError::SuspendProcess)
+    }
+
+    /// Take the lock for writing, or suspend the current task until all others release it and return `SuspendProcess`
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, T>, KernelError> {
+        let mut state = self.state.lock();
+        if !state.writer && state.readers == 0 {
+            state.writer = true;
+            return Ok(RwLockWriteGuard { rwlock: self });
+        }
+
+        self.waiters.wait();
+        Err(KernelError::SuspendProcess)
+    }
+
+    fn release_read(&self) {
+        let mut state = self.state.lock();
+        state.readers -= 1;
+        if state.readers == 0 {
+            self.waiters.wake_all();
+        }
+    }
+
+    fn release_write(&self) {
+        let mut state = self.state.lock();
+        state.writer = false;
+        // Any of the waiting readers can take the lock together, so all the waiting tasks are woken to try again
+        self.waiters.wake_all();
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe {
+            &*self.rwlock.data.get()
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.rwlock.release_read();
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe {
+            &*self.rwlock.data.get()
+        }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe {
+            &mut *self.rwlock.data.get()
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.rwlock.release_write();
+    }
+}
@@ -0,0 +1,128 @@
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+
+use ruxpin_types::{Timespec, Timestamp, NANOSECONDS_PER_SECOND};
+
+use crate::arch;
+use crate::smp;
+use crate::sync::Spinlock;
+use crate::errors::KernelError;
+
+
+pub type TimerId = usize;
+
+type TimerCallback = Box<dyn FnOnce() + Send>;
+
+struct Timer {
+    id: TimerId,
+    deadline: u64,
+    callback: TimerCallback,
+}
+
+unsafe impl Sync for Timer {}
+
+// The number of timer interrupts on the boot core since the scheduler started
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+// The realtime clock in nanoseconds at the moment the system started, which is 0 until the time is set
+static REALTIME_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+static NEXT_TIMER_ID: AtomicUsize = AtomicUsize::new(1);
+
+// The timers are checked from the timer interrupt, so the lock must disable interrupts
+static TIMERS: Spinlock<Vec<Timer>> = Spinlock::new(Vec::new());
+
+
+/// Returns the number of scheduler ticks that have occurred since the system started
+pub fn get_ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Returns the number of nanoseconds since the system started, which only ever increases
+pub fn get_uptime_ns() -> u64 {
+    // The multiplication would overflow a u64 after a few minutes
+    (arch::read_counter() as u128 * NANOSECONDS_PER_SECOND as u128 / arch::read_counter_frequency() as u128) as u64
+}
+
+/// Returns the time since the system started
+pub fn get_uptime() -> Timespec {
+    Timespec::from_nanoseconds(get_uptime_ns())
+}
+
+/// Returns the number of nanoseconds since the epoch, according to the last time the clock was set
+pub fn get_realtime_ns() -> u64 {
+    REALTIME_OFFSET.load(Ordering::Relaxed).saturating_add(get_uptime_ns())
+}
+
+/// Returns the time since the epoch (1970-01-01 00:00:00 UTC)
+pub fn get_realtime() -> Timespec {
+    Timespec::from_nanoseconds(get_realtime_ns())
+}
+
+/// Set the realtime clock to the given time since the epoch, without affecting the uptime or any timers
+pub fn set_realtime(time: Timespec) -> Result<(), KernelError> {
+    if time.nanoseconds >= NANOSECONDS_PER_SECOND {
+        return Err(KernelError::InvalidArgument);
+    }
+    let time_ns = time.as_nanoseconds().ok_or(KernelError::InvalidArgument)?;
+
+    // The time can be set to before the system started, in which case the offset is clamped to the epoch
+    REALTIME_OFFSET.store(time_ns.saturating_sub(get_uptime_ns()), Ordering::Relaxed);
+    Ok(())
+}
+
+/// Returns the current realtime in whole seconds, for recording when files were accessed or changed
+pub fn get_timestamp() -> Timestamp {
+    Timestamp(get_realtime().seconds)
+}
+
+/// Run the callback once the uptime has reached the deadline in nanoseconds
+///
+/// The timers are only checked on each scheduler tick, so the callback can run up to one time slice late.
+/// Callbacks run in interrupt context, and should only do short things like waking tasks.
+pub fn add_timer<F>(deadline: u64, callback: F) -> TimerId
+where
+    F: FnOnce() + Send + 'static
+{
+    let id = NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed);
+    TIMERS.lock().push(Timer {
+        id,
+        deadline,
+        callback: Box::new(callback),
+    });
+    id
+}
+
+/// Remove a timer before it expires, and return true if it hadn't already run
+pub fn cancel_timer(id: TimerId) -> bool {
+    let mut timers = TIMERS.lock();
+    let len = timers.len();
+    timers.retain(|timer| timer.id != id);
+    timers.len() != len
+}
+
+/// Account for a timer interrupt on the current cpu, and run any timers that have expired
+pub fn tick() {
+    if smp::cpu_id() == 0 {
+        TICKS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let now = get_uptime_ns();
+    let expired: Vec<Timer> = {
+        let mut timers = TIMERS.lock();
+        if timers.iter().all(|timer| timer.deadline > now) {
+            return;
+        }
+
+        let (expired, pending) = timers.drain(..).partition(|timer| timer.deadline <= now);
+        *timers = pending;
+        expired
+    };
+
+    // The lock isn't held while the callbacks run, so that they can add new timers
+    for timer in expired {
+        (timer.callback)();
+    }
+}
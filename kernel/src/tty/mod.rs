@@ -2,11 +2,11 @@
 use alloc::vec::Vec;
 use alloc::boxed::Box;
  
-use ruxpin_syscall::SyscallFunction;
 use ruxpin_types::{OpenFlags, DeviceID, DriverID, MinorDeviceID};
 
 use crate::tasklets;
 use crate::proc::scheduler;
+use crate::proc::wait::WaitQueue;
 use crate::sync::Spinlock;
 use crate::errors::KernelError;
 
@@ -32,6 +32,7 @@ pub struct CharDriver {
 pub struct TtyDevice {
     dev: Box<dyn CharOperations>,
     reader: Option<CanonicalReader>,
+    input_queue: WaitQueue,
 }
 
 static TTY_DRIVERS: Spinlock<Vec<CharDriver>> = Spinlock::new(Vec::new());
@@ -95,6 +96,7 @@ pub fn read(device_id: DeviceID, buffer: &mut [u8]) -> Result<usize, KernelError
 
     if nbytes == 0 {
         let current = scheduler::get_current();
+        device.input_queue.add_task(current.clone());
         tasklets::schedule(Box::new(move || {
             scheduler::suspend(current);
             Ok(())
@@ -115,17 +117,17 @@ pub fn schedule_update(device_id: DeviceID) {
         let mut drivers_list = TTY_DRIVERS.lock();
         let device = get_device(&mut *drivers_list, device_id)?;
         if let Some(reader) = device.reader.as_mut() {
-            process_input(reader, &mut *device.dev)?;
+            process_input(reader, &mut *device.dev, &device.input_queue)?;
         }
         Ok(())
     }));
 }
 
-fn process_input(reader: &mut CanonicalReader, dev: &mut dyn CharOperations) -> Result<(), KernelError> {
+fn process_input(reader: &mut CanonicalReader, dev: &mut dyn CharOperations, input_queue: &WaitQueue) -> Result<(), KernelError> {
     let mut ch = [0; 1];
     while dev.read(&mut ch)? > 0 {
         if reader.process_char(dev, ch[0])? {
-            scheduler::restart_blocked(SyscallFunction::Read);
+            input_queue.wake_all();
             break;
         }
     }
@@ -160,6 +162,7 @@ impl TtyDevice {
         Self {
             dev,
             reader: Some(CanonicalReader::new()),
+            input_queue: WaitQueue::new(),
         }
     }
 }
@@ -2,10 +2,11 @@
 
 use core::fmt;
 use core::fmt::Write;
+use core::sync::atomic::AtomicU32;
 
 use ruxpin_syscall_proc::syscall_function;
 
-use ruxpin_types::{Pid, FileDesc, ApiError, OpenFlags, FileAccess, DirEntry};
+use ruxpin_types::{Pid, FileDesc, ApiError, OpenFlags, FileAccess, Seek, DirEntry, Stat, Signal, SignalSet, SignalAction, RawSignalAction, SignalMaskHow, WaitOptions, WaitStatus, Tid, CloneArgs, FutexOp, MemoryProtection, MapFlags, Personality, Timespec, Timeval, ClockId};
 
 
 #[syscall_function(Exit)]
@@ -18,11 +19,115 @@ pub fn fork() -> Result<Pid, ApiError> {}
 pub fn exec(path: &str, args: &[&str], envp: &[&str]) -> ! {}
 
 #[syscall_function(WaitPid)]
-pub fn waitpid(pid: Pid, status: &mut isize, options: usize) -> Result<Pid, ApiError> {}
+pub fn waitpid(pid: Pid, status: &mut WaitStatus, options: WaitOptions) -> Result<Pid, ApiError> {}
 
 #[syscall_function(Sbrk)]
 pub fn sbrk(increment: usize) -> Result<*const u8, ApiError> {}
 
+#[syscall_function(Mmap)]
+pub fn mmap(addr: usize, len: usize, prot: MemoryProtection, flags: MapFlags, file: FileDesc, offset: usize) -> Result<*const u8, ApiError> {}
+
+#[syscall_function(Munmap)]
+pub fn munmap(addr: usize, len: usize) -> Result<(), ApiError> {}
+
+#[syscall_function(Mprotect)]
+pub fn mprotect(addr: usize, len: usize, prot: MemoryProtection) -> Result<(), ApiError> {}
+
+#[syscall_function(Msync)]
+pub fn msync(addr: usize, len: usize) -> Result<(), ApiError> {}
+
+#[syscall_function(Swapon)]
+pub fn swapon(path: &str) -> Result<(), ApiError> {}
+
+#[syscall_function(Swapoff)]
+pub fn swapoff(path: &str) -> Result<(), ApiError> {}
+
+#[syscall_function(Clone)]
+pub fn clone(args: &CloneArgs) -> Result<Tid, ApiError> {}
+
+#[syscall_function(GetTid)]
+pub fn gettid() -> Result<Tid, ApiError> {}
+
+#[syscall_function(ThreadExit)]
+pub fn thread_exit(status: isize) -> ! {}
+
+#[syscall_function(Futex)]
+pub fn futex(futex_word: &AtomicU32, op: FutexOp, value: usize) -> Result<usize, ApiError> {}
+
+/// Set the flags that change how programs are run by this process and its children, and return the previous flags
+#[syscall_function(Personality)]
+pub fn personality(persona: Personality) -> Result<Personality, ApiError> {}
+
+/// Add the increment to the nice value of this process, and return the new nice value
+#[syscall_function(Nice)]
+pub fn nice(increment: isize) -> Result<i32, ApiError> {}
+
+/// Set the nice value of a process, or of this process if pid is 0
+#[syscall_function(SetPriority)]
+pub fn setpriority(pid: Pid, nice: isize) -> Result<(), ApiError> {}
+
+/// Get the nice value of a process, or of this process if pid is 0
+#[syscall_function(GetPriority)]
+pub fn getpriority(pid: Pid) -> Result<i32, ApiError> {}
+
+/// Suspend this thread for the given number of seconds, or until it's interrupted by a signal
+#[syscall_function(Sleep)]
+pub fn sleep(seconds: usize) -> Result<(), ApiError> {}
+
+/// Suspend this thread for the given length of time, or until it's interrupted by a signal
+#[syscall_function(NanoSleep)]
+pub fn nanosleep(duration: &Timespec) -> Result<(), ApiError> {}
+
+/// Get the current time of the given clock
+#[syscall_function(ClockGetTime)]
+pub fn clock_gettime(clock: ClockId, time: &mut Timespec) -> Result<(), ApiError> {}
+
+/// Set the current time of the given clock, which only root can do, and only for the realtime clock
+#[syscall_function(ClockSetTime)]
+pub fn clock_settime(clock: ClockId, time: &Timespec) -> Result<(), ApiError> {}
+
+/// Get the current time since the epoch
+#[syscall_function(GetTimeOfDay)]
+pub fn gettimeofday(time: &mut Timeval) -> Result<(), ApiError> {}
+
+/// Set the current time since the epoch, which only root can do
+#[syscall_function(SetTimeOfDay)]
+pub fn settimeofday(time: &Timeval) -> Result<(), ApiError> {}
+
+/// Wait until woken if the futex word is equal to `expected`, otherwise return `TryAgain`
+pub fn futex_wait(futex_word: &AtomicU32, expected: u32) -> Result<(), ApiError> {
+    futex(futex_word, FutexOp::Wait, expected as usize).map(|_| ())
+}
+
+pub fn futex_wake(futex_word: &AtomicU32, count: usize) -> Result<usize, ApiError> {
+    futex(futex_word, FutexOp::Wake, count)
+}
+
+#[syscall_function(Kill)]
+pub fn kill(pid: Pid, signal: Signal) -> Result<(), ApiError> {}
+
+#[syscall_function(SigAction)]
+fn raw_sigaction(signal: Signal, action: &RawSignalAction, old_action: &mut RawSignalAction) -> Result<(), ApiError> {}
+
+pub fn sigaction(signal: Signal, action: &mut SignalAction, old_action: &mut SignalAction) -> Result<(), ApiError> {
+    // Handlers return through the trampoline, which asks the kernel to restore the interrupted context
+    action.restorer = signal_return_trampoline as usize;
+    let mut raw_old_action = RawSignalAction::from(*old_action);
+    raw_sigaction(signal, &RawSignalAction::from(*action), &mut raw_old_action)?;
+    *old_action = SignalAction::try_from(raw_old_action)?;
+    Ok(())
+}
+
+#[syscall_function(SigProcMask)]
+pub fn sigprocmask(how: SignalMaskHow, mask: SignalSet) -> Result<SignalSet, ApiError> {}
+
+#[syscall_function(SigReturn)]
+pub fn sigreturn() -> ! {}
+
+extern "C" fn signal_return_trampoline() -> ! {
+    sigreturn()
+}
+
 #[syscall_function(Open)]
 pub fn open(path: &str, flags: OpenFlags, access: FileAccess) -> Result<FileDesc, ApiError> {}
 
@@ -35,12 +140,27 @@ pub fn read(file: FileDesc, buffer: &mut [u8]) -> Result<usize, ApiError> {}
 #[syscall_function(Write)]
 pub fn write(file: FileDesc, buffer: &[u8]) -> Result<usize, ApiError> {}
 
+#[syscall_function(Seek)]
+pub fn lseek(file: FileDesc, offset: isize, whence: Seek) -> Result<usize, ApiError> {}
+
 #[syscall_function(ReadDir)]
 pub fn readdir(file: FileDesc, dirent: &mut DirEntry) -> Result<bool, ApiError> {}
 
+#[syscall_function(Stat)]
+pub fn stat(path: &str, stat: &mut Stat) -> Result<(), ApiError> {}
+
+#[syscall_function(FStat)]
+pub fn fstat(file: FileDesc, stat: &mut Stat) -> Result<(), ApiError> {}
+
+#[syscall_function(LStat)]
+pub fn lstat(path: &str, stat: &mut Stat) -> Result<(), ApiError> {}
+
 #[syscall_function(Dup2)]
 pub fn dup2(old_fd: FileDesc, new_fd: FileDesc) -> Result<(), ApiError> {}
 
+#[syscall_function(Pipe)]
+pub fn pipe(fds: &mut [FileDesc; 2]) -> Result<(), ApiError> {}
+
 #[syscall_function(Unlink)]
 pub fn unlink(path: &str) -> Result<(), ApiError> {}
 
@@ -51,7 +171,10 @@ pub fn rename(old_path: &str, new_path: &str) -> Result<(), ApiError> {}
 pub fn mkdir(path: &str, access: FileAccess) -> Result<(), ApiError> {}
 
 #[syscall_function(GetCwd)]
-pub fn getcwd(path: &mut [u8]) -> Result<(), ApiError> {}
+pub fn getcwd(path: &mut [u8]) -> Result<usize, ApiError> {}
+
+#[syscall_function(ChDir)]
+pub fn chdir(path: &str) -> Result<(), ApiError> {}
 
 #[syscall_function(Sync)]
 pub fn sync() -> Result<(), ApiError> {}
@@ -83,7 +206,11 @@ impl UnbufferedFile {
 
 impl Write for UnbufferedFile {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        write(self.0, s.as_bytes()).unwrap();
+        let mut buffer = s.as_bytes();
+        while buffer.len() > 0 {
+            let nbytes = write(self.0, buffer).unwrap();
+            buffer = &buffer[nbytes..];
+        }
         Ok(())
     }
 }
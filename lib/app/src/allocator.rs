@@ -5,6 +5,8 @@ use core::alloc::{GlobalAlloc, Layout};
 
 use ruxpin_api::sbrk;
 
+use crate::sync::Mutex;
+
 
 struct Block {
     size: usize,
@@ -16,26 +18,29 @@ struct Heap {
     free_blocks: *mut Block,
 }
 
+// The heap's pointers are only accessed while the mutex is held
+unsafe impl Send for Heap {}
+
+struct LockedHeap(Mutex<Heap>);
+
 #[global_allocator]
-static mut MAIN_HEAP: Heap = Heap {
+static MAIN_HEAP: LockedHeap = LockedHeap(Mutex::new(Heap {
     last_increase: 2048,
     free_blocks: ptr::null_mut()
-};
+}));
 
 #[alloc_error_handler]
 fn out_of_memory(_: Layout) -> ! {
     panic!("user allocator: out of memory");
 }
 
-unsafe impl GlobalAlloc for Heap {
+unsafe impl GlobalAlloc for LockedHeap {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        // TODO add mutex??
-        MAIN_HEAP.malloc(layout.size())
+        self.0.lock().malloc(layout.size())
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        // TODO add mutex??
-        MAIN_HEAP.free(ptr);
+        self.0.lock().free(ptr);
     }
 }
 
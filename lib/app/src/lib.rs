@@ -2,8 +2,12 @@
 #![no_main]
 #![feature(alloc_error_handler)]
 
+extern crate alloc;
+
 pub mod env;
 pub mod allocator;
+pub mod sync;
+pub mod thread;
 
 use core::panic::PanicInfo;
 
@@ -0,0 +1,107 @@
+
+use core::mem;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+use alloc::vec;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use ruxpin_api::{clone, thread_exit, futex_wait};
+use ruxpin_types::{Tid, ApiError, CloneArgs, CloneFlags};
+
+
+const THREAD_STACK_SIZE: usize = 64 * 1024;
+
+struct Packet<T> {
+    // This is cleared by the kernel when the thread exits
+    running: AtomicU32,
+    result: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T: Send> Sync for Packet<T> {}
+
+pub struct JoinHandle<T> {
+    tid: Tid,
+    packet: Arc<Packet<T>>,
+    stack: Option<Box<[u8]>>,
+}
+
+type ThreadMain = Box<dyn FnOnce()>;
+
+
+pub fn spawn<F, T>(f: F) -> Result<JoinHandle<T>, ApiError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        running: AtomicU32::new(1),
+        result: UnsafeCell::new(None),
+    });
+
+    let thread_packet = packet.clone();
+    let main: ThreadMain = Box::new(move || {
+        let result = f();
+        unsafe {
+            *thread_packet.result.get() = Some(result);
+        }
+    });
+    // The boxed closure is a fat pointer, so box it again to pass it as a single argument
+    let main = Box::into_raw(Box::new(main));
+
+    let stack = vec![0; THREAD_STACK_SIZE].into_boxed_slice();
+    let stack_top = (stack.as_ptr() as usize + stack.len()) & !0xF;
+
+    let mut args = CloneArgs::new(CloneFlags::ShareSpace.plus(CloneFlags::ShareFiles).plus(CloneFlags::Thread));
+    args.entry = thread_start as usize;
+    args.stack = stack_top;
+    args.arg = main as usize;
+    args.clear_tid = &packet.running as *const AtomicU32 as usize;
+
+    match clone(&args) {
+        Ok(tid) => Ok(JoinHandle {
+            tid,
+            packet,
+            stack: Some(stack),
+        }),
+        Err(err) => {
+            unsafe { drop(Box::from_raw(main)); }
+            Err(err)
+        },
+    }
+}
+
+extern "C" fn thread_start(main: *mut ThreadMain) -> ! {
+    let main = unsafe { Box::from_raw(main) };
+    main();
+    thread_exit(0);
+}
+
+impl<T> JoinHandle<T> {
+    pub fn tid(&self) -> Tid {
+        self.tid
+    }
+
+    /// Wait for the thread to exit, and return the result of its closure
+    pub fn join(mut self) -> T {
+        while self.packet.running.load(Ordering::Acquire) != 0 {
+            let _ = futex_wait(&self.packet.running, 1);
+        }
+
+        // The kernel is done with the thread, so its stack can be freed
+        self.stack.take();
+        // A panic in the thread will exit the whole process, so the result is always set
+        unsafe { (*self.packet.result.get()).take().unwrap() }
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        // A detached thread is still running on its stack, and the kernel will clear the running flag when it exits,
+        // so both are leaked rather than freed
+        if let Some(stack) = self.stack.take() {
+            mem::forget(stack);
+            mem::forget(self.packet.clone());
+        }
+    }
+}
@@ -3,7 +3,7 @@
 pub mod arch;
 pub use crate::arch::execute_syscall;
 
-use ruxpin_types::{ApiError, FileDesc};
+use ruxpin_types::{ApiError, FileDesc, SignalSet, Personality};
 
 
 #[repr(usize)]
@@ -20,15 +20,48 @@ pub enum SyscallFunction {
     Close,
     Read,
     Write,
+    Seek,
     ReadDir,
+    Stat,
+    FStat,
+    LStat,
     Dup2,
+    Pipe,
     Unlink,
     Rename,
     MkDir,
     GetCwd,
+    ChDir,
     Sync,
 
     Sbrk,
+    Mmap,
+    Munmap,
+    Mprotect,
+    Msync,
+    Swapon,
+    Swapoff,
+
+    Clone,
+    GetTid,
+    ThreadExit,
+    Futex,
+    Personality,
+    Nice,
+    SetPriority,
+    GetPriority,
+    Sleep,
+    NanoSleep,
+
+    ClockGetTime,
+    ClockSetTime,
+    GetTimeOfDay,
+    SetTimeOfDay,
+
+    Kill,
+    SigAction,
+    SigProcMask,
+    SigReturn,
 }
 
 #[derive(Clone, Debug)]
@@ -100,6 +133,18 @@ impl IntoSyscallResult for FileDesc {
     }
 }
 
+impl IntoSyscallResult for SignalSet {
+    fn into_result(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl IntoSyscallResult for Personality {
+    fn into_result(self) -> usize {
+        self.0 as usize
+    }
+}
+
 impl<T> IntoSyscallResult for *const T {
     fn into_result(self) -> usize {
         self as usize
@@ -146,6 +191,18 @@ impl FromSyscallResult for FileDesc {
     }
 }
 
+impl FromSyscallResult for SignalSet {
+    fn from_result(input: usize) -> Self {
+        SignalSet(input as u32)
+    }
+}
+
+impl FromSyscallResult for Personality {
+    fn from_result(input: usize) -> Self {
+        Personality(input as u32)
+    }
+}
+
 #[macro_export]
 macro_rules! syscall_encode {
     ($syscall:ident, $i:ident, $name:ident: usize) => {
@@ -173,11 +230,61 @@ macro_rules! syscall_encode {
         $syscall.args[$i - 1] = $name.0 as usize;
     };
 
+    ($syscall:ident, $i:ident, $name:ident: WaitOptions) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name.0 as usize;
+    };
+
     ($syscall:ident, $i:ident, $name:ident: FileAccess) => {
         $i += 1;
         $syscall.args[$i - 1] = $name.0 as usize;
     };
 
+    ($syscall:ident, $i:ident, $name:ident: Seek) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name as usize;
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: Signal) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name.0 as usize;
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: ClockId) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name as usize;
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: SignalSet) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name.0 as usize;
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: SignalMaskHow) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name as usize;
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: FutexOp) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name as usize;
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: MemoryProtection) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name.0 as usize;
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: MapFlags) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name.0 as usize;
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: Personality) => {
+        $i += 1;
+        $syscall.args[$i - 1] = $name.0 as usize;
+    };
+
     ($syscall:ident, $i:ident, $name:ident: &[$type:ty]) => {
         $i += 2;
         $syscall.args[$i - 2] = $name.as_ptr() as usize;
@@ -234,11 +341,91 @@ macro_rules! syscall_decode {
         let $name = OpenFlags($syscall.args[$i - 1] as u16);
     };
 
+    ($syscall:ident, $i:ident, $name:ident: WaitOptions) => {
+        $i += 1;
+        let $name = WaitOptions($syscall.args[$i - 1] as u16);
+    };
+
     ($syscall:ident, $i:ident, $name:ident: FileAccess) => {
         $i += 1;
         let $name = FileAccess($syscall.args[$i - 1] as u16);
     };
 
+    ($syscall:ident, $i:ident, $name:ident: Seek) => {
+        $i += 1;
+        let $name = match Seek::try_from($syscall.args[$i - 1]) {
+            Ok(whence) => whence,
+            Err(err) => {
+                $syscall.store_result(Err(err));
+                return;
+            },
+        };
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: Signal) => {
+        $i += 1;
+        let $name = match Signal::try_from($syscall.args[$i - 1]) {
+            Ok(signal) => signal,
+            Err(err) => {
+                $syscall.store_result(Err(err));
+                return;
+            },
+        };
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: ClockId) => {
+        $i += 1;
+        let $name = match ClockId::try_from($syscall.args[$i - 1]) {
+            Ok(clock) => clock,
+            Err(err) => {
+                $syscall.store_result(Err(err));
+                return;
+            },
+        };
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: SignalSet) => {
+        $i += 1;
+        let $name = SignalSet($syscall.args[$i - 1] as u32);
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: MemoryProtection) => {
+        $i += 1;
+        let $name = MemoryProtection($syscall.args[$i - 1] as u16);
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: MapFlags) => {
+        $i += 1;
+        let $name = MapFlags($syscall.args[$i - 1] as u16);
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: Personality) => {
+        $i += 1;
+        let $name = Personality($syscall.args[$i - 1] as u32);
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: SignalMaskHow) => {
+        $i += 1;
+        let $name = match SignalMaskHow::try_from($syscall.args[$i - 1]) {
+            Ok(how) => how,
+            Err(err) => {
+                $syscall.store_result(Err(err));
+                return;
+            },
+        };
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: FutexOp) => {
+        $i += 1;
+        let $name = match FutexOp::try_from($syscall.args[$i - 1]) {
+            Ok(op) => op,
+            Err(err) => {
+                $syscall.store_result(Err(err));
+                return;
+            },
+        };
+    };
+
     ($syscall:ident, $i:ident, $name:ident: &[$type:ty]) => {
         $i += 2;
         let $name = unsafe {
@@ -260,6 +447,16 @@ macro_rules! syscall_decode {
         };
     };
 
+    ($syscall:ident, $i:ident, $name:ident: Option<&$type:ty>) => {
+        $i += 1;
+        let $name = unsafe { ($syscall.args[$i - 1] as *const usize as *const $type).as_ref() };
+    };
+
+    ($syscall:ident, $i:ident, $name:ident: Option<&mut $type:ty>) => {
+        $i += 1;
+        let $name = unsafe { ($syscall.args[$i - 1] as *mut usize as *mut $type).as_mut() };
+    };
+
     ($syscall:ident, $i:ident, $name:ident: &$type:ty) => {
         $i += 1;
         let $name = unsafe { &*($syscall.args[$i - 1] as *const usize as *const $type) };
@@ -270,4 +467,3 @@ macro_rules! syscall_decode {
         let $name = unsafe { &mut *($syscall.args[$i - 1] as *mut usize as *mut $type) };
     };
 }
-
@@ -2,9 +2,9 @@
 #![feature(box_patterns)]
 
 use proc_macro::TokenStream;
-use proc_macro2::Span;
+use proc_macro2::{Span, Literal};
 use quote::quote;
-use syn::{parse_macro_input, FnArg, Pat, Ident, Item, ItemFn, ReturnType, Type};
+use syn::{parse_macro_input, FnArg, Pat, Ident, Item, ItemFn, ReturnType, Type, TypePath, TypeReference, PathArguments, GenericArgument};
 
 #[proc_macro_attribute]
 pub fn syscall_handler(_attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -25,12 +25,22 @@ pub fn syscall_handler(_attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     }).collect::<Vec<_>>();
 
+    let mut index = 0;
+    let access_checks = info.sig.inputs.iter().filter_map(|arg| {
+        if let FnArg::Typed(pat_type) = arg {
+            user_access_check(&pat_type.ty, &mut index)
+        } else {
+            None
+        }
+    }).collect::<Vec<_>>();
+
     let existing = Item::Fn(info);
     let expanded = quote! {
         #existing
 
         pub fn #handler_name(syscall: &mut ruxpin_syscall::SyscallRequest) {
             use ruxpin_syscall::IntoSyscallResult; 
+            #( #access_checks )*
             let mut i = 0;
             #( ruxpin_syscall::syscall_decode!(syscall, i, #args); )*
             let result = #existing_name(#( #args_names ),*);
@@ -41,6 +51,80 @@ pub fn syscall_handler(_attr: TokenStream, item: TokenStream) -> TokenStream {
     expanded.into()
 }
 
+/// Generate a check that the user memory of a pointer argument can be accessed, before the argument is decoded
+///
+/// Slices and strings take two arguments (the pointer and the length).  A null pointer is rejected, unless the
+/// reference is wrapped in an `Option`, in which case it's decoded as `None`
+fn user_access_check(ty: &Type, index: &mut usize) -> Option<proc_macro2::TokenStream> {
+    let addr = Literal::usize_unsuffixed(*index);
+    let len = Literal::usize_unsuffixed(*index + 1);
+
+    let (reference, optional) = match ty {
+        Type::Reference(reference) => (reference, false),
+        Type::Path(path) => match optional_reference(path) {
+            Some(reference) => (reference, true),
+            None => {
+                *index += 1;
+                return None;
+            },
+        },
+        _ => {
+            *index += 1;
+            return None;
+        },
+    };
+    let write = reference.mutability.is_some();
+
+    let size = match &*reference.elem {
+        Type::Slice(slice) if !optional => {
+            let elem = &slice.elem;
+            *index += 2;
+            quote! { syscall.args[#len].saturating_mul(core::mem::size_of::<#elem>()) }
+        },
+        Type::Path(path) if !optional && path.path.is_ident("str") => {
+            *index += 2;
+            quote! { syscall.args[#len] }
+        },
+        elem => {
+            *index += 1;
+            quote! { core::mem::size_of::<#elem>() }
+        },
+    };
+
+    let check = quote! {
+        if let Err(err) = crate::api::user::check_user_access(syscall.args[#addr], #size, #write) {
+            syscall.store_result(Err(ruxpin_types::ApiError::from(err)));
+            return;
+        }
+    };
+
+    if optional {
+        Some(quote! {
+            if syscall.args[#addr] != 0 {
+                #check
+            }
+        })
+    } else {
+        Some(check)
+    }
+}
+
+/// Returns the reference inside an `Option<&T>` or `Option<&mut T>`
+fn optional_reference(path: &TypePath) -> Option<&TypeReference> {
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(Type::Reference(reference)) => Some(reference),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 #[proc_macro_attribute]
 pub fn syscall_function(attr: TokenStream, item: TokenStream) -> TokenStream {
     let function = parse_macro_input!(attr as Ident);
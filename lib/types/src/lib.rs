@@ -15,6 +15,19 @@ pub enum Seek {
     FromEnd,
 }
 
+impl TryFrom<usize> for Seek {
+    type Error = ApiError;
+
+    fn try_from(source: usize) -> Result<Self, Self::Error> {
+        match source {
+            0 => Ok(Seek::FromStart),
+            1 => Ok(Seek::FromCurrent),
+            2 => Ok(Seek::FromEnd),
+            _ => Err(ApiError::InvalidArgument),
+        }
+    }
+}
+
 
 pub type DriverID = u8;
 pub type MinorDeviceID = u8;
@@ -166,6 +179,274 @@ impl From<u32> for Timestamp {
 }
 
 
+/// A length of time, or a point in time measured from the start of a clock
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timespec {
+    pub seconds: u64,
+    /// Always less than one second
+    pub nanoseconds: u64,
+}
+
+pub const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+impl Timespec {
+    pub const fn new(seconds: u64, nanoseconds: u64) -> Self {
+        Self {
+            seconds,
+            nanoseconds,
+        }
+    }
+
+    pub const fn from_nanoseconds(nanoseconds: u64) -> Self {
+        Self {
+            seconds: nanoseconds / NANOSECONDS_PER_SECOND,
+            nanoseconds: nanoseconds % NANOSECONDS_PER_SECOND,
+        }
+    }
+
+    /// Returns the time in nanoseconds, or None if it's not a valid time or it's too large to represent
+    pub fn as_nanoseconds(&self) -> Option<u64> {
+        if self.nanoseconds >= NANOSECONDS_PER_SECOND {
+            return None;
+        }
+        self.seconds.checked_mul(NANOSECONDS_PER_SECOND)?.checked_add(self.nanoseconds)
+    }
+}
+
+/// A point in time measured in seconds and microseconds since the epoch (1970-01-01 00:00:00 UTC)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timeval {
+    pub seconds: u64,
+    /// Always less than one second
+    pub microseconds: u64,
+}
+
+impl From<Timespec> for Timeval {
+    fn from(source: Timespec) -> Self {
+        Self {
+            seconds: source.seconds,
+            microseconds: source.nanoseconds / 1000,
+        }
+    }
+}
+
+impl From<Timeval> for Timespec {
+    fn from(source: Timeval) -> Self {
+        Self {
+            seconds: source.seconds,
+            nanoseconds: source.microseconds.saturating_mul(1000),
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClockId {
+    /// The time since the epoch, which can be set and so can jump forwards or backwards
+    Realtime,
+    /// The time since the system started, which only ever increases
+    Monotonic,
+}
+
+impl TryFrom<usize> for ClockId {
+    type Error = ApiError;
+
+    fn try_from(source: usize) -> Result<Self, Self::Error> {
+        match source {
+            0 => Ok(ClockId::Realtime),
+            1 => Ok(ClockId::Monotonic),
+            _ => Err(ApiError::InvalidArgument),
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CloneFlags(pub u32);
+
+#[allow(non_upper_case_globals)]
+impl CloneFlags {
+    pub const Empty: CloneFlags         = CloneFlags(0);
+    pub const ShareSpace: CloneFlags    = CloneFlags(0x01);
+    pub const ShareFiles: CloneFlags    = CloneFlags(0x02);
+    pub const Thread: CloneFlags        = CloneFlags(0x04);
+
+    pub fn plus(self, flag: Self) -> Self {
+        CloneFlags(self.0 | flag.0)
+    }
+
+    pub fn is_set(self, flag: Self) -> bool {
+        self.0 & flag.0 != 0
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CloneArgs {
+    pub flags: CloneFlags,
+    /// The address to start the new task at, or 0 to return from clone like fork
+    pub entry: usize,
+    pub stack: usize,
+    /// The value passed as the first argument to the entry function
+    pub arg: usize,
+    /// The address of a u32 that is set to 0, and futex woken, when the new thread exits
+    pub clear_tid: usize,
+}
+
+impl CloneArgs {
+    pub fn new(flags: CloneFlags) -> Self {
+        Self {
+            flags,
+            entry: 0,
+            stack: 0,
+            arg: 0,
+            clear_tid: 0,
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FutexOp {
+    Wait,
+    Wake,
+}
+
+impl TryFrom<usize> for FutexOp {
+    type Error = ApiError;
+
+    fn try_from(source: usize) -> Result<Self, Self::Error> {
+        match source {
+            0 => Ok(FutexOp::Wait),
+            1 => Ok(FutexOp::Wake),
+            _ => Err(ApiError::InvalidArgument),
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WaitOptions(pub u16);
+
+#[allow(non_upper_case_globals)]
+impl WaitOptions {
+    pub const Empty: WaitOptions        = WaitOptions(0);
+    pub const NoHang: WaitOptions       = WaitOptions(0o1);
+    pub const Untraced: WaitOptions     = WaitOptions(0o2);
+
+    pub fn plus(self, flag: Self) -> Self {
+        WaitOptions(self.0 | flag.0)
+    }
+
+    pub fn is_set(self, flag: Self) -> bool {
+        self.0 & flag.0 != 0
+    }
+}
+
+/// The status of a terminated process, encoded the same as the unix wait status word
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WaitStatus(pub isize);
+
+impl WaitStatus {
+    pub fn new_exited(code: isize) -> Self {
+        WaitStatus((code & 0xff) << 8)
+    }
+
+    pub fn new_signaled(signal: Signal) -> Self {
+        WaitStatus(signal.0 as isize & 0x7f)
+    }
+
+    pub fn new_stopped(signal: Signal) -> Self {
+        WaitStatus(((signal.0 as isize) << 8) | 0x7f)
+    }
+
+    pub fn exited(self) -> bool {
+        self.0 & 0x7f == 0
+    }
+
+    pub fn exit_code(self) -> isize {
+        (self.0 >> 8) & 0xff
+    }
+
+    pub fn signaled(self) -> bool {
+        !self.exited() && !self.stopped()
+    }
+
+    pub fn term_signal(self) -> Signal {
+        Signal((self.0 & 0x7f) as u8)
+    }
+
+    pub fn stopped(self) -> bool {
+        self.0 & 0xff == 0x7f
+    }
+
+    pub fn stop_signal(self) -> Signal {
+        Signal(((self.0 >> 8) & 0xff) as u8)
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryProtection(pub u16);
+
+#[allow(non_upper_case_globals)]
+impl MemoryProtection {
+    pub const None: MemoryProtection        = MemoryProtection(0);
+    pub const Read: MemoryProtection        = MemoryProtection(0x1);
+    pub const Write: MemoryProtection       = MemoryProtection(0x2);
+    pub const Exec: MemoryProtection        = MemoryProtection(0x4);
+
+    pub fn plus(self, flag: Self) -> Self {
+        MemoryProtection(self.0 | flag.0)
+    }
+
+    pub fn is_set(self, flag: Self) -> bool {
+        self.0 & flag.0 != 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MapFlags(pub u16);
+
+#[allow(non_upper_case_globals)]
+impl MapFlags {
+    pub const Shared: MapFlags          = MapFlags(0x01);
+    pub const Private: MapFlags         = MapFlags(0x02);
+    pub const Fixed: MapFlags           = MapFlags(0x10);
+    pub const Anonymous: MapFlags       = MapFlags(0x20);
+
+    pub fn plus(self, flag: Self) -> Self {
+        MapFlags(self.0 | flag.0)
+    }
+
+    pub fn is_set(self, flag: Self) -> bool {
+        self.0 & flag.0 != 0
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Personality(pub u32);
+
+#[allow(non_upper_case_globals)]
+impl Personality {
+    pub const Default: Personality          = Personality(0x0000);
+    /// Load programs at the same addresses every time, instead of randomizing the layout of their memory
+    pub const NoRandomize: Personality      = Personality(0x0040000);
+
+    pub fn plus(self, flag: Self) -> Self {
+        Personality(self.0 | flag.0)
+    }
+
+    pub fn is_set(self, flag: Self) -> bool {
+        self.0 & flag.0 != 0
+    }
+}
+
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct FileDesc(pub usize);
 
@@ -175,6 +456,190 @@ impl FileDesc {
     }
 }
 
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Signal(pub u8);
+
+#[allow(dead_code)]
+#[allow(non_upper_case_globals)]
+impl Signal {
+    pub const Hangup: Signal                = Signal(1);
+    pub const Interrupt: Signal             = Signal(2);
+    pub const Quit: Signal                  = Signal(3);
+    pub const IllegalInstruction: Signal    = Signal(4);
+    pub const Trap: Signal                  = Signal(5);
+    pub const Abort: Signal                 = Signal(6);
+    pub const BusError: Signal              = Signal(7);
+    pub const FloatingPointError: Signal    = Signal(8);
+    pub const Kill: Signal                  = Signal(9);
+    pub const User1: Signal                 = Signal(10);
+    pub const SegmentationFault: Signal     = Signal(11);
+    pub const User2: Signal                 = Signal(12);
+    pub const BrokenPipe: Signal            = Signal(13);
+    pub const Alarm: Signal                 = Signal(14);
+    pub const Terminate: Signal             = Signal(15);
+    pub const ChildExited: Signal           = Signal(17);
+    pub const Continue: Signal              = Signal(18);
+    pub const Stop: Signal                  = Signal(19);
+    pub const TerminalStop: Signal          = Signal(20);
+
+    pub const Count: usize = 32;
+
+    pub fn is_valid(self) -> bool {
+        self.0 > 0 && (self.0 as usize) < Signal::Count
+    }
+
+    pub fn is_catchable(self) -> bool {
+        self != Signal::Kill && self != Signal::Stop
+    }
+}
+
+/// Convert a syscall argument, which is checked in full so that a large number can't be truncated into a valid signal
+impl TryFrom<usize> for Signal {
+    type Error = ApiError;
+
+    fn try_from(source: usize) -> Result<Self, Self::Error> {
+        if source < Signal::Count {
+            Ok(Signal(source as u8))
+        } else {
+            Err(ApiError::InvalidArgument)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SignalSet(pub u32);
+
+#[allow(non_upper_case_globals)]
+impl SignalSet {
+    pub const Empty: SignalSet = SignalSet(0);
+
+    pub fn from_signal(signal: Signal) -> Self {
+        SignalSet(1 << signal.0)
+    }
+
+    pub fn plus(self, set: Self) -> Self {
+        SignalSet(self.0 | set.0)
+    }
+
+    pub fn minus(self, set: Self) -> Self {
+        SignalSet(self.0 & !set.0)
+    }
+
+    pub fn is_set(self, signal: Signal) -> bool {
+        self.0 & (1 << signal.0) != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn first(self) -> Option<Signal> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(Signal(self.0.trailing_zeros() as u8))
+        }
+    }
+}
+
+#[repr(usize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignalMaskHow {
+    Block,
+    Unblock,
+    SetMask,
+}
+
+impl TryFrom<usize> for SignalMaskHow {
+    type Error = ApiError;
+
+    fn try_from(source: usize) -> Result<Self, Self::Error> {
+        match source {
+            0 => Ok(SignalMaskHow::Block),
+            1 => Ok(SignalMaskHow::Unblock),
+            2 => Ok(SignalMaskHow::SetMask),
+            _ => Err(ApiError::InvalidArgument),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignalHandler {
+    Default,
+    Ignore,
+    Handler(usize),
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SignalAction {
+    pub handler: SignalHandler,
+    pub mask: SignalSet,
+    pub restorer: usize,
+}
+
+impl SignalAction {
+    pub fn new(handler: SignalHandler) -> Self {
+        Self {
+            handler,
+            mask: SignalSet::Empty,
+            restorer: 0,
+        }
+    }
+}
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// A signal action as it's passed through the syscall interface, with the handler as a plain address
+///
+/// Any value can be written by user space, so it must be checked when it's converted into a `SignalAction`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct RawSignalAction {
+    pub handler: usize,
+    pub mask: SignalSet,
+    pub restorer: usize,
+}
+
+impl From<SignalAction> for RawSignalAction {
+    fn from(action: SignalAction) -> Self {
+        let handler = match action.handler {
+            SignalHandler::Default => SIG_DFL,
+            SignalHandler::Ignore => SIG_IGN,
+            SignalHandler::Handler(addr) => addr,
+        };
+
+        Self {
+            handler,
+            mask: action.mask,
+            restorer: action.restorer,
+        }
+    }
+}
+
+impl TryFrom<RawSignalAction> for SignalAction {
+    type Error = ApiError;
+
+    fn try_from(raw: RawSignalAction) -> Result<Self, Self::Error> {
+        let handler = match raw.handler {
+            SIG_DFL => SignalHandler::Default,
+            SIG_IGN => SignalHandler::Ignore,
+            // Handlers are functions, so they must be at least aligned to an instruction
+            addr if addr % 4 != 0 => return Err(ApiError::InvalidArgument),
+            addr => SignalHandler::Handler(addr),
+        };
+
+        Ok(Self {
+            handler,
+            mask: raw.mask,
+            restorer: raw.restorer,
+        })
+    }
+}
+
+
 const DIR_ENTRY_MAX_LEN: usize = 256;
 
 #[derive(Clone)]
@@ -218,6 +683,41 @@ impl DirEntry {
 }
 
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Stat {
+    pub access: FileAccess,
+    pub nlinks: u16,
+    pub uid: UserID,
+    pub gid: GroupID,
+    pub rdev: Option<DeviceID>,
+    pub inode: InodeNum,
+    pub size: usize,
+
+    pub atime: Timestamp,
+    pub mtime: Timestamp,
+    pub ctime: Timestamp,
+}
+
+impl Stat {
+    pub fn new_empty() -> Self {
+        Self {
+            access: FileAccess(0),
+            nlinks: 0,
+            uid: 0,
+            gid: 0,
+            rdev: None,
+            inode: 0,
+            size: 0,
+
+            atime: Timestamp(0),
+            mtime: Timestamp(0),
+            ctime: Timestamp(0),
+        }
+    }
+}
+
+
 #[repr(usize)]
 #[allow(dead_code)]
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -232,6 +732,7 @@ pub enum ApiError {
     NoSegmentFound              = 107,
     MemoryPermissionDenied      = 108,
     LockTimeout                 = 109,
+    StackOverflow               = 110,
 
     // Device Errors
     NoSuchDevice                = 201,
@@ -258,6 +759,7 @@ pub enum ApiError {
     AccessDenied                = 315,
     DirectoryNotEmpty           = 316,
     FileExists                  = 317,
+    BrokenPipe                  = 318,
 
     NoSuchTask                  = 401,
     NotExecutable               = 402,
@@ -265,6 +767,9 @@ pub enum ApiError {
     InvalidSegmentType          = 404,
     BadSystemCall               = 405,
     NotExited                   = 406,
+    Interrupted                 = 407,
+    NoChildren                  = 408,
+    TryAgain                    = 409,
 
     UnknownError                = 9999,
 }
@@ -281,6 +786,7 @@ impl From<usize> for ApiError {
             107 => ApiError::NoSegmentFound,
             108 => ApiError::MemoryPermissionDenied,
             109 => ApiError::LockTimeout,
+            110 => ApiError::StackOverflow,
 
             201 => ApiError::NoSuchDevice,
             202 => ApiError::OperationNotPermitted,
@@ -305,6 +811,7 @@ impl From<usize> for ApiError {
             315 => ApiError::AccessDenied,
             316 => ApiError::DirectoryNotEmpty,
             317 => ApiError::FileExists,
+            318 => ApiError::BrokenPipe,
 
             401 => ApiError::NoSuchTask,
             402 => ApiError::NotExecutable,
@@ -312,6 +819,9 @@ impl From<usize> for ApiError {
             404 => ApiError::InvalidSegmentType,
             405 => ApiError::BadSystemCall,
             406 => ApiError::NotExited,
+            407 => ApiError::Interrupted,
+            408 => ApiError::NoChildren,
+            409 => ApiError::TryAgain,
 
             _ => ApiError::UnknownError,
         }